@@ -0,0 +1,318 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use crate::flowgger::utils;
+use chrono::DateTime;
+
+const DEFAULT_FORMAT: &str = "combined";
+const DEFAULT_HOSTNAME: &str = "unknown";
+const COMMON_FORMAT: &str =
+    r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent"#;
+const COMBINED_FORMAT: &str = r#"$remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent""#;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Variable(String),
+}
+
+#[derive(Clone)]
+pub struct AccessLogDecoder {
+    tokens: Vec<Token>,
+}
+
+impl AccessLogDecoder {
+    /// `input.access_log_format` is either `common`, `combined`, or a nginx `log_format`
+    /// string such as `$remote_addr [$time_local] "$request" $status $request_time`
+    pub fn new(config: &Config) -> AccessLogDecoder {
+        let format = config
+            .lookup("input.access_log_format")
+            .map_or(DEFAULT_FORMAT, |x| {
+                x.as_str()
+                    .expect("input.access_log_format must be a string")
+            });
+        let format = match format {
+            "common" => COMMON_FORMAT,
+            "combined" => COMBINED_FORMAT,
+            format => format,
+        };
+        let tokens = compile(format).unwrap_or_else(|e| panic!("input.access_log_format: {}", e));
+        AccessLogDecoder { tokens }
+    }
+}
+
+/// Split a `log_format` string into literals and variables. Variable names are made of
+/// alphanumeric characters and underscores, and may be enclosed in braces, as in
+/// `${request_time}s`.
+fn compile(format: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut literal = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            literal.push(c);
+            continue;
+        }
+        let braced = chars.peek() == Some(&'{');
+        if braced {
+            chars.next();
+        }
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if !c.is_ascii_alphanumeric() && c != '_' {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+        if braced && chars.next() != Some('}') {
+            return Err("unterminated variable name");
+        }
+        if name.is_empty() {
+            return Err("missing variable name after $");
+        }
+        if literal.is_empty() {
+            if let Some(Token::Variable(_)) = tokens.last() {
+                return Err("variables must be separated by a literal");
+            }
+        } else {
+            tokens.push(Token::Literal(literal));
+            literal = String::new();
+        }
+        tokens.push(Token::Variable(name));
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    Ok(tokens)
+}
+
+impl Decoder for AccessLogDecoder {
+    /// Decode an access log line. `$time_local`, `$time_iso8601` or `$msec` set the
+    /// timestamp, and the severity is derived from the status class. Other variables
+    /// become structured data, with `U64` values for the status and sizes, and `F64`
+    /// values for durations. Empty (`-`) values are skipped.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let mut sd = StructuredData::new(None);
+        let mut ts = None;
+        let mut hostname = None;
+        let mut severity = None;
+        let mut msg = None;
+        let mut rest = line;
+
+        for (idx, token) in self.tokens.iter().enumerate() {
+            let name = match *token {
+                Token::Literal(ref literal) => {
+                    if !rest.starts_with(literal.as_str()) {
+                        return Err("Access log line doesn't match the format");
+                    }
+                    rest = &rest[literal.len()..];
+                    continue;
+                }
+                Token::Variable(ref name) => name,
+            };
+            let value = match self.tokens.get(idx + 1) {
+                Some(Token::Literal(next)) => {
+                    let end = rest
+                        .find(next.as_str())
+                        .ok_or("Access log line doesn't match the format")?;
+                    &rest[..end]
+                }
+                _ => rest,
+            };
+            rest = &rest[value.len()..];
+            if value == "-" || value.is_empty() {
+                continue;
+            }
+            match name.as_ref() {
+                "time_local" => ts = Some(parse_time_local(value)?),
+                "time_iso8601" => ts = Some(parse_time_iso8601(value)?),
+                "msec" => ts = Some(value.parse::<f64>().map_err(|_| "Invalid msec value")?),
+                "hostname" => hostname = Some(value.to_owned()),
+                "status" => {
+                    let status = value.parse::<u64>().map_err(|_| "Invalid status code")?;
+                    severity = Some(status_to_severity(status));
+                    sd.pairs.push(("_status".to_owned(), SDValue::U64(status)));
+                }
+                "body_bytes_sent" | "bytes_sent" => {
+                    let bytes = value.parse::<u64>().map_err(|_| "Invalid size")?;
+                    sd.pairs.push(("_bytes".to_owned(), SDValue::U64(bytes)));
+                }
+                "request_length" => {
+                    let bytes = value.parse::<u64>().map_err(|_| "Invalid size")?;
+                    sd.pairs
+                        .push(("_request_length".to_owned(), SDValue::U64(bytes)));
+                }
+                "request" => {
+                    msg = Some(value.to_owned());
+                    sd.pairs
+                        .push(("_request".to_owned(), SDValue::String(value.to_owned())));
+                }
+                "http_referer" => sd
+                    .pairs
+                    .push(("_referer".to_owned(), SDValue::String(value.to_owned()))),
+                "http_user_agent" => sd
+                    .pairs
+                    .push(("_user_agent".to_owned(), SDValue::String(value.to_owned()))),
+                name if name.ends_with("_time") => {
+                    // Upstream timings may be lists, such as `0.010, 0.002`
+                    let sdvalue = match value.parse::<f64>() {
+                        Ok(duration) => SDValue::F64(duration),
+                        Err(_) => SDValue::String(value.to_owned()),
+                    };
+                    sd.pairs.push((format!("_{}", name), sdvalue));
+                }
+                name => sd
+                    .pairs
+                    .push((format!("_{}", name), SDValue::String(value.to_owned()))),
+            }
+        }
+        if !rest.is_empty() {
+            return Err("Trailing data after the access log format");
+        }
+        let record = Record {
+            ts: ts.unwrap_or_else(|| utils::PreciseTimestamp::now().as_f64()),
+            hostname: hostname.unwrap_or_else(|| DEFAULT_HOSTNAME.to_owned()),
+            facility: None,
+            severity,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg,
+            full_msg: Some(line.to_owned()),
+            sd: if sd.pairs.is_empty() { None } else { Some(sd) },
+        };
+        Ok(record)
+    }
+}
+
+/// Server errors are errors, client errors are warnings, anything else is informational
+fn status_to_severity(status: u64) -> u8 {
+    match status {
+        500..=599 => 3,
+        400..=499 => 4,
+        _ => 6,
+    }
+}
+
+fn parse_time_local(time_local: &str) -> Result<f64, &'static str> {
+    match DateTime::parse_from_str(time_local, "%d/%b/%Y:%H:%M:%S %z") {
+        Ok(date) => Ok(utils::PreciseTimestamp::from_datetime(date).as_f64()),
+        Err(_) => Err("Unable to parse the date"),
+    }
+}
+
+fn parse_time_iso8601(time_iso8601: &str) -> Result<f64, &'static str> {
+    match DateTime::parse_from_rfc3339(time_iso8601) {
+        Ok(date) => Ok(utils::PreciseTimestamp::from_datetime(date).as_f64()),
+        Err(_) => Err("Unable to parse the date"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    fn sd_value(record: &Record, name: &str) -> Option<SDValue> {
+        record
+            .sd
+            .as_ref()?
+            .pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_access_log_combined() {
+        let config = Config::from_string("").unwrap();
+        let msg = r#"192.0.2.1 - frank [05/Aug/2015:17:53:45 +0200] "GET /index.html HTTP/1.1" 200 2326 "http://example.com/" "Mozilla/5.0 (X11; Linux x86_64)""#;
+        let res = AccessLogDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.severity, Some(6));
+        assert_eq!(res.msg, Some("GET /index.html HTTP/1.1".to_owned()));
+        assert_eq!(res.full_msg, Some(msg.to_owned()));
+        assert_eq!(
+            sd_value(&res, "_remote_addr"),
+            Some(SDValue::String("192.0.2.1".to_owned()))
+        );
+        assert_eq!(
+            sd_value(&res, "_remote_user"),
+            Some(SDValue::String("frank".to_owned()))
+        );
+        assert_eq!(sd_value(&res, "_status"), Some(SDValue::U64(200)));
+        assert_eq!(sd_value(&res, "_bytes"), Some(SDValue::U64(2326)));
+        assert_eq!(
+            sd_value(&res, "_referer"),
+            Some(SDValue::String("http://example.com/".to_owned()))
+        );
+        assert_eq!(
+            sd_value(&res, "_user_agent"),
+            Some(SDValue::String(
+                "Mozilla/5.0 (X11; Linux x86_64)".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_access_log_common() {
+        let config = Config::from_string("[input]\naccess_log_format = \"common\"").unwrap();
+        let msg = r#"192.0.2.1 - - [05/Aug/2015:15:53:45 +0000] "POST /api HTTP/1.1" 503 -"#;
+        let res = AccessLogDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.severity, Some(3));
+        assert_eq!(sd_value(&res, "_remote_user"), None);
+        assert_eq!(sd_value(&res, "_bytes"), None);
+        assert_eq!(sd_value(&res, "_status"), Some(SDValue::U64(503)));
+    }
+
+    #[test]
+    fn test_access_log_custom_format() {
+        let config = Config::from_string(
+            "[input]\naccess_log_format = '$remote_addr $hostname [$time_iso8601] \"$request\" \
+             $status ${request_time}s $upstream_response_time'",
+        )
+        .unwrap();
+        let msg =
+            r#"10.0.0.1 web01 [2015-08-05T15:53:45.5Z] "GET / HTTP/2.0" 404 0.042s 0.010, 0.030"#;
+        let res = AccessLogDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 500));
+        assert_eq!(res.hostname, "web01");
+        assert_eq!(res.severity, Some(4));
+        assert_eq!(sd_value(&res, "_request_time"), Some(SDValue::F64(0.042)));
+        assert_eq!(
+            sd_value(&res, "_upstream_response_time"),
+            Some(SDValue::String("0.010, 0.030".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_access_log_invalid() {
+        let config = Config::from_string("[input]\naccess_log_format = \"common\"").unwrap();
+        let decoder = AccessLogDecoder::new(&config);
+        assert!(decoder.decode("garbage").is_err());
+        assert!(decoder
+            .decode(r#"192.0.2.1 - - [05/Aug/2015:15:53:45 +0000] "GET / HTTP/1.1" OK 12"#)
+            .is_err());
+        assert!(decoder
+            .decode(r#"192.0.2.1 - - [yesterday] "GET / HTTP/1.1" 200 12"#)
+            .is_err());
+    }
+
+    #[test]
+    fn test_access_log_compile() {
+        assert_eq!(
+            compile("$a [$b]").unwrap(),
+            vec![
+                Token::Variable("a".to_owned()),
+                Token::Literal(" [".to_owned()),
+                Token::Variable("b".to_owned()),
+                Token::Literal("]".to_owned()),
+            ]
+        );
+        assert!(compile("$a$b").is_err());
+        assert!(compile("${a").is_err());
+        assert!(compile("$ a").is_err());
+    }
+}
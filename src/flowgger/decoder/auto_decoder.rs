@@ -0,0 +1,277 @@
+#[cfg(feature = "cef")]
+use super::CefDecoder;
+use super::Decoder;
+#[cfg(feature = "gelf")]
+use super::GelfDecoder;
+#[cfg(feature = "json")]
+use super::JsonDecoder;
+#[cfg(feature = "ltsv")]
+use super::LTSVDecoder;
+#[cfg(feature = "leef")]
+use super::LeefDecoder;
+#[cfg(feature = "rfc3164")]
+use super::RFC3164Decoder;
+#[cfg(feature = "rfc5424")]
+use super::RFC5424Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use crate::flowgger::utils::metrics::{self, Counter};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+struct Candidate {
+    name: &'static str,
+    decoder: Box<dyn Decoder + Send>,
+    counter: Counter,
+}
+
+impl Clone for Candidate {
+    fn clone(&self) -> Candidate {
+        Candidate {
+            name: self.name,
+            decoder: self.decoder.clone_boxed(),
+            counter: self.counter.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AutoDecoder {
+    candidates: Vec<Candidate>,
+    fallback: Vec<usize>,
+    failed: Counter,
+}
+
+impl AutoDecoder {
+    /// Every compiled-in format among `rfc5424`, `rfc3164`, `json`, `gelf`, `cef`, `leef`
+    /// and `ltsv` can be detected. `input.auto_fallback` is an optional, ordered list of
+    /// formats to try when a line cannot be detected, or fails to decode with the
+    /// detected format.
+    ///
+    /// # Panics
+    ///
+    /// - `input.auto_fallback must be a list of formats`
+    /// - `Unsupported format in input.auto_fallback: <format>`
+    pub fn new(config: &Config) -> AutoDecoder {
+        let decoders: Vec<(&'static str, Box<dyn Decoder + Send>)> = vec![
+            #[cfg(feature = "rfc5424")]
+            ("rfc5424", Box::new(RFC5424Decoder::new(config))),
+            #[cfg(feature = "rfc3164")]
+            ("rfc3164", Box::new(RFC3164Decoder::new(config))),
+            #[cfg(feature = "json")]
+            ("json", Box::new(JsonDecoder::new(config))),
+            #[cfg(feature = "gelf")]
+            ("gelf", Box::new(GelfDecoder::new(config))),
+            #[cfg(feature = "cef")]
+            ("cef", Box::new(CefDecoder::new(config))),
+            #[cfg(feature = "leef")]
+            ("leef", Box::new(LeefDecoder::new(config))),
+            #[cfg(feature = "ltsv")]
+            ("ltsv", Box::new(LTSVDecoder::new(config))),
+        ];
+        let candidates: Vec<Candidate> = decoders
+            .into_iter()
+            .map(|(name, decoder)| Candidate {
+                name,
+                decoder,
+                counter: metrics::counter(&format!("decoder.auto.{}", name)),
+            })
+            .collect();
+
+        let fallback = match config.lookup("input.auto_fallback") {
+            None => Vec::new(),
+            Some(fallback) => fallback
+                .as_array()
+                .expect("input.auto_fallback must be a list of formats")
+                .iter()
+                .map(|name| {
+                    let name = name
+                        .as_str()
+                        .expect("input.auto_fallback must be a list of formats");
+                    candidates
+                        .iter()
+                        .position(|x| x.name == name)
+                        .unwrap_or_else(|| {
+                            panic!("Unsupported format in input.auto_fallback: {}", name)
+                        })
+                })
+                .collect(),
+        };
+        AutoDecoder {
+            candidates,
+            fallback,
+            failed: metrics::counter("decoder.auto.failed"),
+        }
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.candidates.iter().position(|x| x.name == name)
+    }
+
+    /// Guess the format of a line from its first bytes
+    fn sniff(&self, line: &str) -> Option<usize> {
+        if line.contains("CEF:0|") || line.contains("CEF:1|") {
+            return self.position("cef");
+        }
+        if line.contains("LEEF:1.0|") || line.contains("LEEF:2.0|") {
+            return self.position("leef");
+        }
+        if line.starts_with('<') {
+            let pri_end = line.find('>')?;
+            let pri = &line[1..pri_end];
+            if pri.is_empty() || pri.len() > 3 || !pri.bytes().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            return if line[pri_end + 1..].starts_with("1 ") {
+                self.position("rfc5424")
+            } else {
+                self.position("rfc3164")
+            };
+        }
+        if line.starts_with('{') {
+            if line.contains("\"short_message\"") {
+                if let Some(idx) = self.position("gelf") {
+                    return Some(idx);
+                }
+            }
+            return self.position("json");
+        }
+        if MONTHS
+            .iter()
+            .any(|month| line.starts_with(month) && line[month.len()..].starts_with(' '))
+        {
+            return self.position("rfc3164");
+        }
+        if let Some(tab) = line.find('\t') {
+            if line[..tab].contains(':') {
+                return self.position("ltsv");
+            }
+        }
+        None
+    }
+}
+
+impl Decoder for AutoDecoder {
+    /// Decode a line with the format it looks like, then with each fallback format in
+    /// order. The counter of the format that decoded the line is incremented.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let detected = self.sniff(line);
+        let mut err = None;
+        let order = detected
+            .iter()
+            .chain(self.fallback.iter().filter(|&&idx| Some(idx) != detected));
+        for &idx in order {
+            let candidate = &self.candidates[idx];
+            match candidate.decoder.decode(line) {
+                Ok(record) => {
+                    candidate.counter.inc();
+                    return Ok(record);
+                }
+                Err(e) => {
+                    err.get_or_insert(e);
+                }
+            }
+        }
+        self.failed.inc();
+        Err(err.unwrap_or("Unable to detect the input format"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn decoder(config: &str) -> AutoDecoder {
+        AutoDecoder::new(&Config::from_string(config).unwrap())
+    }
+
+    #[cfg(all(
+        feature = "rfc5424",
+        feature = "rfc3164",
+        feature = "json",
+        feature = "gelf",
+        feature = "cef",
+        feature = "leef",
+        feature = "ltsv"
+    ))]
+    #[test]
+    fn test_auto_sniff() {
+        let decoder = decoder("");
+        let name = |line: &str| decoder.sniff(line).map(|idx| decoder.candidates[idx].name);
+        assert_eq!(
+            name("<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 - msg"),
+            Some("rfc5424")
+        );
+        assert_eq!(
+            name("<23>Aug  6 11:15:24 testhostname appname: msg"),
+            Some("rfc3164")
+        );
+        assert_eq!(name("Aug  6 11:15:24 testhostname msg"), Some("rfc3164"));
+        assert_eq!(name(r#"{"message":"test"}"#), Some("json"));
+        assert_eq!(
+            name(r#"{"version":"1.1","host":"h","short_message":"test"}"#),
+            Some("gelf")
+        );
+        assert_eq!(name("CEF:0|V|P|1|100|Name|5|src=10.0.0.1"), Some("cef"));
+        assert_eq!(
+            name("<13>Aug  6 11:15:24 fw01 CEF:0|V|P|1|100|Name|5|src=h"),
+            Some("cef")
+        );
+        assert_eq!(name("LEEF:2.0|V|P|1|42|x09|src=h"), Some("leef"));
+        assert_eq!(name("host:127.0.0.1\tmessage:test"), Some("ltsv"));
+        assert_eq!(name("just some text"), None);
+        assert_eq!(name("<abc>1 text"), None);
+    }
+
+    #[cfg(all(feature = "rfc5424", feature = "json", feature = "ltsv"))]
+    #[test]
+    fn test_auto_decode() {
+        let decoder = decoder("");
+        let before = decoder.candidates[decoder.position("rfc5424").unwrap()]
+            .counter
+            .get();
+        let res = decoder
+            .decode("<23>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 - test message")
+            .unwrap();
+        assert_eq!(res.hostname, "testhostname");
+        assert_eq!(res.msg, Some("test message".to_owned()));
+        let after = decoder.candidates[decoder.position("rfc5424").unwrap()]
+            .counter
+            .get();
+        assert!(after > before);
+
+        let res = decoder
+            .decode(r#"{"host":"jsonhost","message":"from json"}"#)
+            .unwrap();
+        assert_eq!(res.hostname, "jsonhost");
+        assert_eq!(res.msg, Some("from json".to_owned()));
+
+        let res = decoder
+            .decode("host:ltsvhost\tmessage:from ltsv\ttime:[05/Aug/2015:15:53:45 +0000]")
+            .unwrap();
+        assert_eq!(res.hostname, "ltsvhost");
+
+        assert!(decoder.decode("just some text").is_err());
+    }
+
+    #[cfg(all(feature = "json", feature = "rfc3164"))]
+    #[test]
+    fn test_auto_fallback() {
+        let decoder = decoder("[input]\nauto_fallback = [\"json\", \"rfc3164\"]");
+        let res = decoder
+            .decode("2015-08-05T15:53:45Z testhostname undetected message")
+            .unwrap();
+        assert_eq!(res.hostname, "testhostname");
+        assert_eq!(res.msg, Some("undetected message".to_owned()));
+
+        assert!(decoder.decode("{not json}").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unsupported format in input.auto_fallback: capnp")]
+    fn test_auto_fallback_unknown() {
+        decoder("[input]\nauto_fallback = [\"capnp\"]");
+    }
+}
@@ -0,0 +1,283 @@
+use super::syslog_header::SyslogHeaderDecoder;
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use crate::flowgger::utils;
+use chrono::NaiveDateTime;
+
+const CEF_MAGIC: &str = "CEF:";
+
+#[derive(Clone)]
+pub struct CefDecoder {
+    header_decoder: SyslogHeaderDecoder,
+}
+
+impl CefDecoder {
+    /// CEF events can be sent as is, or wrapped in a syslog header
+    pub fn new(config: &Config) -> CefDecoder {
+        CefDecoder {
+            header_decoder: SyslogHeaderDecoder::new(config),
+        }
+    }
+}
+
+impl Decoder for CefDecoder {
+    /// Decode a `CEF:Version|Device Vendor|Device Product|Device Version|Signature ID|Name|Severity|Extension`
+    /// event. The signature ID is mapped to the msgid, the name to the message, and the
+    /// severity to a syslog severity. Extensions become structured data, except `rt`,
+    /// `dvchost`, `deviceProcessName` and `dvcpid` that are mapped to the record.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let cef_start = line.find(CEF_MAGIC).ok_or("Missing CEF header")?;
+        let mut record = self.header_decoder.decode(&line[..cef_start])?;
+        record.full_msg = Some(line.to_owned());
+
+        let (header, extension) = split_header(&line[cef_start + CEF_MAGIC.len()..])?;
+        match header[0].as_ref() {
+            "0" | "1" => {}
+            _ => return Err("Unsupported CEF version"),
+        }
+        let mut sd = record
+            .sd
+            .take()
+            .unwrap_or_else(|| StructuredData::new(None));
+        sd.pairs.push((
+            "_device_vendor".to_owned(),
+            SDValue::String(header[1].clone()),
+        ));
+        sd.pairs.push((
+            "_device_product".to_owned(),
+            SDValue::String(header[2].clone()),
+        ));
+        sd.pairs.push((
+            "_device_version".to_owned(),
+            SDValue::String(header[3].clone()),
+        ));
+        sd.pairs.push((
+            "_cef_severity".to_owned(),
+            SDValue::String(header[6].clone()),
+        ));
+        record.msgid = Some(header[4].clone());
+        record.msg = Some(header[5].clone());
+        if let Some(severity) = cef_severity_to_syslog(&header[6])? {
+            record.severity = Some(severity);
+        }
+
+        for (key, value) in parse_extension(extension)? {
+            match key {
+                "rt" => record.ts = parse_rt(&value)?,
+                "dvchost" => record.hostname = value,
+                "deviceProcessName" => record.appname = Some(value),
+                "dvcpid" => record.procid = Some(value),
+                _ => sd.pairs.push((format!("_{}", key), SDValue::String(value))),
+            }
+        }
+        record.sd = Some(sd);
+        Ok(record)
+    }
+}
+
+/// Split the 7 pipe-separated header fields, unescaping `\|` and `\\`, and return them
+/// along with the raw extension
+fn split_header(line: &str) -> Result<(Vec<String>, &str), &'static str> {
+    let mut fields = Vec::with_capacity(7);
+    let mut field = String::new();
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c)) if c == '|' || c == '\\' => field.push(c),
+                Some((_, c)) => {
+                    field.push('\\');
+                    field.push(c);
+                }
+                None => field.push('\\'),
+            },
+            '|' => {
+                fields.push(field);
+                field = String::new();
+                if fields.len() == 7 {
+                    return Ok((fields, &line[i + 1..]));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    Err("Truncated CEF header")
+}
+
+/// Parse the space-separated `key=value` extension pairs. Values may contain spaces, so a
+/// value only ends where the next key starts. `\=`, `\\`, `\n` and `\r` are unescaped.
+fn parse_extension(extension: &str) -> Result<Vec<(&str, String)>, &'static str> {
+    let mut keys = Vec::new();
+    let mut escaped = false;
+    for (i, c) in extension.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '=' => {
+                let key_start = extension[..i].rfind(' ').map_or(0, |pos| pos + 1);
+                // Unescaped `=` signs within a value, as in URLs, are tolerated
+                if matches!(keys.last(), Some(&(_, eq)) if key_start <= eq) {
+                    continue;
+                }
+                if key_start == i {
+                    return Err("Missing key in a CEF extension");
+                }
+                keys.push((key_start, i));
+            }
+            _ => {}
+        }
+    }
+    match keys.first() {
+        None if !extension.trim().is_empty() => return Err("Invalid CEF extension"),
+        Some(&(key_start, _)) if !extension[..key_start].trim().is_empty() => {
+            return Err("Invalid CEF extension")
+        }
+        _ => {}
+    }
+    let mut pairs = Vec::with_capacity(keys.len());
+    for (idx, &(key_start, eq)) in keys.iter().enumerate() {
+        let value_end = keys.get(idx + 1).map_or(extension.len(), |&(next, _)| next);
+        let value = extension[eq + 1..value_end].trim_end_matches(' ');
+        pairs.push((&extension[key_start..eq], unescape_value(value)));
+    }
+    Ok(pairs)
+}
+
+fn unescape_value(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('r') => res.push('\r'),
+            Some(c) => res.push(c),
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+/// Map a CEF severity (0-10 or Unknown/Low/Medium/High/Very-High) to a syslog severity
+fn cef_severity_to_syslog(severity: &str) -> Result<Option<u8>, &'static str> {
+    let severity = match severity.to_lowercase().as_ref() {
+        "unknown" => return Ok(None),
+        "low" => 3,
+        "medium" => 6,
+        "high" => 8,
+        "very-high" => 10,
+        severity => severity.parse::<u8>().map_err(|_| "Invalid CEF severity")?,
+    };
+    match severity {
+        0..=3 => Ok(Some(6)),
+        4..=6 => Ok(Some(4)),
+        7..=8 => Ok(Some(3)),
+        9 => Ok(Some(2)),
+        10 => Ok(Some(1)),
+        _ => Err("Invalid CEF severity"),
+    }
+}
+
+/// `rt` is either a number of milliseconds since the epoch, or a `MMM dd yyyy HH:mm:ss`
+/// date in UTC
+fn parse_rt(rt: &str) -> Result<f64, &'static str> {
+    if let Ok(ms) = rt.parse::<f64>() {
+        return Ok(ms / 1000.0);
+    }
+    match NaiveDateTime::parse_from_str(rt, "%b %d %Y %H:%M:%S%.f") {
+        Ok(date) => Ok(utils::PreciseTimestamp::from_naive_datetime(date).as_f64()),
+        Err(_) => Err("Unable to parse the CEF rt date"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    fn sd_value(record: &Record, name: &str) -> Option<String> {
+        record
+            .sd
+            .as_ref()?
+            .pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .and_then(|(_, v)| match v {
+                SDValue::String(ref v) => Some(v.clone()),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_cef_decode() {
+        let config = Config::from_string("").unwrap();
+        let msg = r#"CEF:0|Security|threat\|manager|1.0|100|worm successfully stopped|10|src=10.0.0.1 dst=2.1.2.2 spt=1232 msg=Detected a threat. No action needed\= done request=http://x/?a=b rt=1438790025637 dvchost=firewall"#;
+        let res = CefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, 1_438_790_025.637);
+        assert_eq!(res.hostname, "firewall");
+        assert_eq!(res.severity, Some(1));
+        assert_eq!(res.msgid, Some("100".to_owned()));
+        assert_eq!(res.msg, Some("worm successfully stopped".to_owned()));
+        assert_eq!(res.full_msg, Some(msg.to_owned()));
+        assert_eq!(
+            sd_value(&res, "_device_vendor"),
+            Some("Security".to_owned())
+        );
+        assert_eq!(
+            sd_value(&res, "_device_product"),
+            Some("threat|manager".to_owned())
+        );
+        assert_eq!(sd_value(&res, "_cef_severity"), Some("10".to_owned()));
+        assert_eq!(sd_value(&res, "_src"), Some("10.0.0.1".to_owned()));
+        assert_eq!(sd_value(&res, "_spt"), Some("1232".to_owned()));
+        assert_eq!(sd_value(&res, "_request"), Some("http://x/?a=b".to_owned()));
+        assert_eq!(
+            sd_value(&res, "_msg"),
+            Some("Detected a threat. No action needed= done".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_cef_decode_rfc3164_header() {
+        let config = Config::from_string("").unwrap();
+        let msg = "<134>2015-08-05T15:53:45Z fw01 asa[42]: CEF:0|Cisco|ASA|9.1|106023|Deny|Medium|";
+        let res = CefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.hostname, "fw01");
+        assert_eq!(res.facility, Some(16));
+        assert_eq!(res.severity, Some(4));
+        assert_eq!(res.appname, Some("asa".to_owned()));
+        assert_eq!(res.procid, Some("42".to_owned()));
+        assert_eq!(res.msg, Some("Deny".to_owned()));
+    }
+
+    #[test]
+    fn test_cef_decode_rfc5424_header() {
+        let config = Config::from_string("").unwrap();
+        let msg = "<14>1 2015-08-05T15:53:45.637824Z host app 69 - - CEF:0|V|P|1|42|Name|Unknown|act=blocked\\nnow rt=Aug 05 2015 15:53:46";
+        let res = CefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 46, 0));
+        assert_eq!(res.hostname, "host");
+        assert_eq!(res.appname, Some("app".to_owned()));
+        assert_eq!(res.severity, Some(6));
+        assert_eq!(res.msgid, Some("42".to_owned()));
+        assert_eq!(sd_value(&res, "_act"), Some("blocked\nnow".to_owned()));
+    }
+
+    #[test]
+    fn test_cef_decode_invalid() {
+        let config = Config::from_string("").unwrap();
+        let decoder = CefDecoder::new(&config);
+        assert!(decoder.decode("not a CEF event").is_err());
+        assert!(decoder.decode("CEF:0|V|P|1|42|Name").is_err());
+        assert!(decoder.decode("CEF:0|V|P|1|42|Name|11|").is_err());
+        assert!(decoder.decode("CEF:9|V|P|1|42|Name|1|").is_err());
+        assert!(decoder
+            .decode("CEF:0|V|P|1|42|Name|1|garbage src=1")
+            .is_err());
+    }
+}
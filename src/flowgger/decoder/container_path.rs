@@ -0,0 +1,109 @@
+use crate::flowgger::config::Config;
+use crate::flowgger::record::SDValue;
+use std::path::Path;
+
+/// Kubernetes metadata extracted from the path of a container log file, for the
+/// `docker` and `cri` decoders
+#[derive(Clone)]
+pub struct ContainerPath {
+    enabled: bool,
+    pairs: Vec<(String, SDValue)>,
+}
+
+impl ContainerPath {
+    /// Extraction is enabled by `input.container_path_metadata = true`
+    pub fn new(config: &Config) -> ContainerPath {
+        let enabled = config
+            .lookup("input.container_path_metadata")
+            .map(|x| {
+                x.as_bool()
+                    .expect("input.container_path_metadata must be a boolean")
+            })
+            .unwrap_or(false);
+        ContainerPath {
+            enabled,
+            pairs: Vec::new(),
+        }
+    }
+
+    pub fn set_source(&mut self, path: &Path) {
+        if self.enabled {
+            self.pairs = parse_path(path);
+        }
+    }
+
+    /// Structured data pairs to add to every record read from the current source
+    pub fn pairs(&self) -> &[(String, SDValue)] {
+        &self.pairs
+    }
+}
+
+fn pair(name: &str, value: &str) -> (String, SDValue) {
+    (name.to_owned(), SDValue::String(value.to_owned()))
+}
+
+/// Recognize `/var/log/pods/<namespace>_<pod>_<pod uid>/<container>/<n>.log` and
+/// `/var/log/containers/<pod>_<namespace>_<container>-<container id>.log` paths. Other
+/// paths don't carry any metadata.
+fn parse_path(path: &Path) -> Vec<(String, SDValue)> {
+    let components: Vec<&str> = path.iter().filter_map(|x| x.to_str()).collect();
+    let len = components.len();
+    if len >= 4 && components[len - 4] == "pods" {
+        let pod: Vec<&str> = components[len - 3].splitn(3, '_').collect();
+        if pod.len() == 3 {
+            return vec![
+                pair("_namespace", pod[0]),
+                pair("_pod", pod[1]),
+                pair("_pod_uid", pod[2]),
+                pair("_container", components[len - 2]),
+            ];
+        }
+    }
+    if len >= 2 && components[len - 2] == "containers" {
+        let name = components[len - 1];
+        let name = name.strip_suffix(".log").unwrap_or(name);
+        let parts: Vec<&str> = name.splitn(3, '_').collect();
+        if parts.len() == 3 {
+            if let Some(id_start) = parts[2].rfind('-') {
+                return vec![
+                    pair("_namespace", parts[1]),
+                    pair("_pod", parts[0]),
+                    pair("_container", &parts[2][..id_start]),
+                    pair("_container_id", &parts[2][id_start + 1..]),
+                ];
+            }
+        }
+    }
+    Vec::new()
+}
+
+#[test]
+fn test_container_path() {
+    let pairs = parse_path(Path::new(
+        "/var/log/pods/default_web-7d4b9c_0f1e2d3c-aaaa-bbbb-cccc-123456789abc/nginx/0.log",
+    ));
+    assert_eq!(
+        pairs,
+        vec![
+            pair("_namespace", "default"),
+            pair("_pod", "web-7d4b9c"),
+            pair("_pod_uid", "0f1e2d3c-aaaa-bbbb-cccc-123456789abc"),
+            pair("_container", "nginx"),
+        ]
+    );
+
+    let pairs = parse_path(Path::new(
+        "/var/log/containers/web-7d4b9c_kube-system_nginx-proxy-4f2a9e.log",
+    ));
+    assert_eq!(
+        pairs,
+        vec![
+            pair("_namespace", "kube-system"),
+            pair("_pod", "web-7d4b9c"),
+            pair("_container", "nginx-proxy"),
+            pair("_container_id", "4f2a9e"),
+        ]
+    );
+
+    assert!(parse_path(Path::new("/var/log/syslog")).is_empty());
+}
@@ -0,0 +1,193 @@
+use super::container_path::ContainerPath;
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use crate::flowgger::utils;
+use chrono::DateTime;
+use std::path::Path;
+
+const DEFAULT_HOSTNAME: &str = "unknown";
+
+#[derive(Clone)]
+pub struct CriDecoder {
+    container_path: ContainerPath,
+}
+
+struct CriLine<'a> {
+    time: &'a str,
+    stream: &'a str,
+    partial: bool,
+    msg: &'a str,
+}
+
+impl CriDecoder {
+    /// With `input.container_path_metadata = true`, the namespace, pod and container
+    /// names are extracted from the path of the log file
+    pub fn new(config: &Config) -> CriDecoder {
+        CriDecoder {
+            container_path: ContainerPath::new(config),
+        }
+    }
+}
+
+/// Split a `<time> <stream> <P|F> <message>` line
+fn parse_line(line: &str) -> Result<CriLine<'_>, &'static str> {
+    let mut parts = line.splitn(4, ' ');
+    let time = parts.next().ok_or("Missing CRI timestamp")?;
+    let stream = parts.next().ok_or("Missing CRI stream")?;
+    let partial = match parts.next() {
+        Some("P") => true,
+        Some("F") => false,
+        _ => return Err("Invalid CRI tag"),
+    };
+    Ok(CriLine {
+        time,
+        stream,
+        partial,
+        msg: parts.next().unwrap_or(""),
+    })
+}
+
+impl Decoder for CriDecoder {
+    /// Decode a CRI log line. Partial (`P`) lines are joined with the lines of the same
+    /// stream that follow them, up to the final (`F`) one. The timestamp and stream of the
+    /// first line are used, and the stream is stored as a `_stream` structured data pair.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let mut lines = line.split('\n');
+        let first = parse_line(lines.next().unwrap_or(""))?;
+        let mut msg = first.msg.to_owned();
+        let mut partial = first.partial;
+        for line in lines {
+            if !partial {
+                return Err("Unexpected CRI line after a final line");
+            }
+            let line = parse_line(line)?;
+            if line.stream != first.stream {
+                return Err("Unexpected CRI line from another stream");
+            }
+            msg.push_str(line.msg);
+            partial = line.partial;
+        }
+        let ts = match DateTime::parse_from_rfc3339(first.time) {
+            Ok(date) => utils::PreciseTimestamp::from_datetime(date).as_f64(),
+            Err(_) => return Err("Unable to parse the date"),
+        };
+        let mut sd = StructuredData::new(None);
+        sd.pairs.push((
+            "_stream".to_owned(),
+            SDValue::String(first.stream.to_owned()),
+        ));
+        sd.pairs.extend_from_slice(self.container_path.pairs());
+        let record = Record {
+            ts,
+            hostname: DEFAULT_HOSTNAME.to_owned(),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: Some(msg),
+            full_msg: None,
+            sd: Some(sd),
+        };
+        Ok(record)
+    }
+
+    /// A record isn't complete until its final (`F`) line has been read
+    fn is_complete(&self, line: &str) -> bool {
+        let last = line.rsplit('\n').next().unwrap_or(line);
+        parse_line(last).map_or(true, |x| !x.partial)
+    }
+
+    /// stdout and stderr lines are interleaved in the same file
+    fn record_stream(&self, line: &str) -> Option<String> {
+        parse_line(line).ok().map(|x| x.stream.to_owned())
+    }
+
+    fn set_source(&mut self, path: &Path) {
+        self.container_path.set_source(path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    fn sd_value(record: &Record, name: &str) -> Option<SDValue> {
+        record
+            .sd
+            .as_ref()?
+            .pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_cri_decode() {
+        let config = Config::from_string("").unwrap();
+        let decoder = CriDecoder::new(&config);
+        let res = decoder
+            .decode("2015-08-05T15:53:45.637Z stderr F Something went wrong")
+            .unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 637));
+        assert_eq!(res.msg, Some("Something went wrong".to_owned()));
+        assert_eq!(
+            sd_value(&res, "_stream"),
+            Some(SDValue::String("stderr".to_owned()))
+        );
+
+        let res = decoder
+            .decode("2015-08-05T15:53:45.637Z stdout F ")
+            .unwrap();
+        assert_eq!(res.msg, Some("".to_owned()));
+    }
+
+    #[test]
+    fn test_cri_decode_partial() {
+        let config = Config::from_string("[input]\ncontainer_path_metadata = true").unwrap();
+        let mut decoder = CriDecoder::new(&config);
+        decoder.set_source(Path::new(
+            "/var/log/pods/default_web-1_0f1e2d3c/nginx/0.log",
+        ));
+        let first = "2015-08-05T15:53:45Z stdout P first ";
+        assert!(!decoder.is_complete(first));
+        let line = format!("{}\n2015-08-05T15:53:46Z stdout P second ", first);
+        assert!(!decoder.is_complete(&line));
+        let line = format!("{}\n2015-08-05T15:53:46Z stdout F third", line);
+        assert!(decoder.is_complete(&line));
+        let res = decoder.decode(&line).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.msg, Some("first second third".to_owned()));
+        assert_eq!(
+            sd_value(&res, "_pod"),
+            Some(SDValue::String("web-1".to_owned()))
+        );
+        assert_eq!(
+            sd_value(&res, "_container"),
+            Some(SDValue::String("nginx".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_cri_decode_interleaved() {
+        let config = Config::from_string("").unwrap();
+        let decoder = CriDecoder::new(&config);
+        let line = "2015-08-05T15:53:45Z stdout P a";
+        assert_eq!(decoder.record_stream(line), Some("stdout".to_owned()));
+        let line = format!("{}\n2015-08-05T15:53:46Z stderr F x", line);
+        assert!(decoder.decode(&line).is_err());
+    }
+
+    #[test]
+    fn test_cri_decode_invalid() {
+        let config = Config::from_string("").unwrap();
+        let decoder = CriDecoder::new(&config);
+        assert!(decoder.decode("2015-08-05T15:53:45Z stdout X msg").is_err());
+        assert!(decoder.decode("yesterday stdout F msg").is_err());
+        assert!(decoder
+            .decode("2015-08-05T15:53:45Z stdout F a\n2015-08-05T15:53:45Z stdout F b")
+            .is_err());
+    }
+}
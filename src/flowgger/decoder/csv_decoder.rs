@@ -0,0 +1,336 @@
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, SDValueType, StructuredData};
+use crate::flowgger::utils;
+use chrono::DateTime;
+
+const DEFAULT_DELIMITER: char = ',';
+const DEFAULT_QUOTE: char = '"';
+const DEFAULT_HOSTNAME: &str = "unknown";
+const ERR_UNTERMINATED: &str = "Unterminated quoted CSV field";
+
+#[derive(Clone)]
+struct Column {
+    name: String,
+    sdtype: SDValueType,
+}
+
+#[derive(Clone)]
+pub struct CsvDecoder {
+    columns: Vec<Column>,
+    delimiter: char,
+    quote: char,
+    escape: char,
+    ts_column: Option<String>,
+    ts_format: Option<String>,
+    host_column: Option<String>,
+    severity_column: Option<String>,
+    msg_column: Option<String>,
+}
+
+fn lookup_char(config: &Config, key: &str, default: char) -> char {
+    match config.lookup(key) {
+        None => default,
+        Some(c) => {
+            let c = c
+                .as_str()
+                .unwrap_or_else(|| panic!("{} must be a single character", key));
+            let mut chars = c.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => panic!("{} must be a single character", key),
+            }
+        }
+    }
+}
+
+fn lookup_column(config: &Config, key: &str, columns: &[Column]) -> Option<String> {
+    let column = config
+        .lookup(key)?
+        .as_str()
+        .unwrap_or_else(|| panic!("{} must be a column name", key));
+    if !columns.iter().any(|x| x.name == column) {
+        panic!(
+            "{} must be one of the names listed in input.csv_columns",
+            key
+        );
+    }
+    Some(column.to_owned())
+}
+
+impl CsvDecoder {
+    /// `input.csv_columns` lists the columns in order, as tables with a `name` and an
+    /// optional `type` (`string`, `bool`, `f64`, `i64` or `u64`). `input.csv_ts_column`,
+    /// `input.csv_host_column`, `input.csv_severity_column` and `input.csv_msg_column`
+    /// name the columns mapped to the record, other columns become structured data.
+    pub fn new(config: &Config) -> CsvDecoder {
+        let columns: Vec<Column> = config
+            .lookup("input.csv_columns")
+            .expect("input.csv_columns is required by the csv format")
+            .as_array()
+            .expect("input.csv_columns must be a list of columns")
+            .iter()
+            .map(|column| {
+                let name = column
+                    .get("name")
+                    .and_then(|x| x.as_str())
+                    .expect("input.csv_columns entries must have a name");
+                let sdtype = match column.get("type") {
+                    None => SDValueType::String,
+                    Some(sdtype) => sdtype
+                        .as_str()
+                        .and_then(SDValueType::from_name)
+                        .unwrap_or_else(|| {
+                            panic!("Unsupported type in input.csv_columns for name [{}]", name)
+                        }),
+                };
+                Column {
+                    name: name.to_owned(),
+                    sdtype,
+                }
+            })
+            .collect();
+        if columns.is_empty() {
+            panic!("input.csv_columns cannot be empty");
+        }
+        let delimiter = lookup_char(config, "input.csv_delimiter", DEFAULT_DELIMITER);
+        let quote = lookup_char(config, "input.csv_quote", DEFAULT_QUOTE);
+        let escape = lookup_char(config, "input.csv_escape", quote);
+        let ts_format = config.lookup("input.csv_ts_format").map(|x| {
+            x.as_str()
+                .expect("input.csv_ts_format must be a string")
+                .to_owned()
+        });
+        CsvDecoder {
+            ts_column: lookup_column(config, "input.csv_ts_column", &columns),
+            host_column: lookup_column(config, "input.csv_host_column", &columns),
+            severity_column: lookup_column(config, "input.csv_severity_column", &columns),
+            msg_column: lookup_column(config, "input.csv_msg_column", &columns),
+            columns,
+            delimiter,
+            quote,
+            escape,
+            ts_format,
+        }
+    }
+
+    /// Split a record into fields, following RFC 4180. Quoted fields can contain
+    /// delimiters and line breaks, and quotes are escaped by doubling them, or with the
+    /// configured escape character.
+    fn split_fields(&self, line: &str) -> Result<Vec<String>, &'static str> {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let mut fields = Vec::with_capacity(self.columns.len());
+        let mut field = String::new();
+        let mut in_quotes = false;
+        let mut quoted = false;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if in_quotes {
+                if c == self.escape && self.escape != self.quote {
+                    field.push(chars.next().ok_or(ERR_UNTERMINATED)?);
+                } else if c == self.quote {
+                    if self.escape == self.quote && chars.peek() == Some(&self.quote) {
+                        chars.next();
+                        field.push(self.quote);
+                    } else {
+                        in_quotes = false;
+                    }
+                } else {
+                    field.push(c);
+                }
+            } else if c == self.delimiter {
+                fields.push(field);
+                field = String::new();
+                quoted = false;
+            } else if c == self.quote && field.is_empty() && !quoted {
+                in_quotes = true;
+                quoted = true;
+            } else if c == self.escape && self.escape != self.quote {
+                field.push(chars.next().ok_or("Trailing escape character")?);
+            } else {
+                field.push(c);
+            }
+        }
+        if in_quotes {
+            return Err(ERR_UNTERMINATED);
+        }
+        fields.push(field);
+        Ok(fields)
+    }
+
+    fn parse_ts(&self, ts: &str) -> Result<f64, &'static str> {
+        match self.ts_format {
+            Some(ref format) => utils::parse_ts_with_format(ts, format),
+            None => match ts.parse::<f64>() {
+                Ok(ts) => Ok(ts),
+                Err(_) => match DateTime::parse_from_rfc3339(ts) {
+                    Ok(date) => Ok(utils::PreciseTimestamp::from_datetime(date).as_f64()),
+                    Err(_) => Err("Unable to parse the date"),
+                },
+            },
+        }
+    }
+}
+
+impl Decoder for CsvDecoder {
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let fields = self.split_fields(line)?;
+        if fields.len() != self.columns.len() {
+            return Err("Unexpected number of CSV fields");
+        }
+        let mut sd = StructuredData::new(None);
+        let mut ts = None;
+        let mut hostname = None;
+        let mut severity = None;
+        let mut msg = None;
+        for (column, value) in self.columns.iter().zip(fields) {
+            let name = Some(&column.name);
+            if name == self.ts_column.as_ref() {
+                ts = Some(self.parse_ts(&value)?);
+            } else if name == self.host_column.as_ref() {
+                hostname = Some(value);
+            } else if name == self.severity_column.as_ref() {
+                severity = Some(utils::parse_severity(&value)?);
+            } else if name == self.msg_column.as_ref() {
+                msg = Some(value);
+            } else if value.is_empty() && column.sdtype != SDValueType::String {
+                continue;
+            } else {
+                let value = match column.sdtype {
+                    SDValueType::String => SDValue::String(value),
+                    ref sdtype => sdtype.parse_value(&value)?,
+                };
+                sd.pairs.push((format!("_{}", column.name), value));
+            }
+        }
+        let record = Record {
+            ts: ts.unwrap_or_else(|| utils::PreciseTimestamp::now().as_f64()),
+            hostname: hostname.unwrap_or_else(|| DEFAULT_HOSTNAME.to_owned()),
+            facility: None,
+            severity,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg,
+            full_msg: None,
+            sd: if sd.pairs.is_empty() { None } else { Some(sd) },
+        };
+        Ok(record)
+    }
+
+    /// A record isn't complete as long as a quoted field is open
+    fn is_complete(&self, line: &str) -> bool {
+        self.split_fields(line) != Err(ERR_UNTERMINATED)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    const CONFIG: &str = r#"[input]
+csv_columns = [
+    { name = "time" },
+    { name = "host" },
+    { name = "level" },
+    { name = "message" },
+    { name = "job" },
+    { name = "duration", type = "f64" },
+    { name = "rows", type = "u64" },
+]
+csv_ts_column = "time"
+csv_ts_format = "%Y-%m-%d %H:%M:%S"
+csv_host_column = "host"
+csv_severity_column = "level"
+csv_msg_column = "message"
+"#;
+
+    fn sd_value(record: &Record, name: &str) -> Option<SDValue> {
+        record
+            .sd
+            .as_ref()?
+            .pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_csv_decode() {
+        let config = Config::from_string(CONFIG).unwrap();
+        let decoder = CsvDecoder::new(&config);
+        let msg = r#"2015-08-05 15:53:45,batch01,warn,"Slow job, ""nightly""",export,12.5,42"#;
+        let res = decoder.decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.hostname, "batch01");
+        assert_eq!(res.severity, Some(4));
+        assert_eq!(res.msg, Some(r#"Slow job, "nightly""#.to_owned()));
+        assert_eq!(
+            sd_value(&res, "_job"),
+            Some(SDValue::String("export".to_owned()))
+        );
+        assert_eq!(sd_value(&res, "_duration"), Some(SDValue::F64(12.5)));
+        assert_eq!(sd_value(&res, "_rows"), Some(SDValue::U64(42)));
+
+        let res = decoder
+            .decode("2015-08-05 15:53:45,batch01,info,\"two\nlines\",,,\r")
+            .unwrap();
+        assert_eq!(res.msg, Some("two\nlines".to_owned()));
+        assert_eq!(sd_value(&res, "_job"), Some(SDValue::String("".to_owned())));
+        assert_eq!(sd_value(&res, "_rows"), None);
+    }
+
+    #[test]
+    fn test_tsv_decode_escape() {
+        let config = Config::from_string(
+            "[input]\ncsv_columns = [{ name = \"a\" }, { name = \"b\", type = \"i64\" }]\n\
+             csv_delimiter = \"\\t\"\ncsv_quote = \"'\"\ncsv_escape = \"\\\\\"\n",
+        )
+        .unwrap();
+        let decoder = CsvDecoder::new(&config);
+        let res = decoder.decode("'it\\'s\ta tab'\t-3").unwrap();
+        assert_eq!(
+            sd_value(&res, "_a"),
+            Some(SDValue::String("it's\ta tab".to_owned()))
+        );
+        assert_eq!(sd_value(&res, "_b"), Some(SDValue::I64(-3)));
+        assert_eq!(res.hostname, DEFAULT_HOSTNAME);
+    }
+
+    #[test]
+    fn test_csv_is_complete() {
+        let config = Config::from_string(CONFIG).unwrap();
+        let decoder = CsvDecoder::new(&config);
+        assert!(decoder.is_complete("a,b,c"));
+        assert!(!decoder.is_complete("a,\"b"));
+        assert!(!decoder.is_complete("a,\"b\nc\"\"d"));
+        assert!(decoder.is_complete("a,\"b\nc\"\"d\""));
+    }
+
+    #[test]
+    fn test_csv_decode_invalid() {
+        let config = Config::from_string(CONFIG).unwrap();
+        let decoder = CsvDecoder::new(&config);
+        assert!(decoder.decode("2015-08-05 15:53:45,h,info,msg").is_err());
+        assert!(decoder
+            .decode("2015-08-05 15:53:45,h,info,msg,job,fast,1")
+            .is_err());
+        assert!(decoder.decode("yesterday,h,info,msg,job,1.0,1").is_err());
+        assert!(decoder
+            .decode("2015-08-05 15:53:45,h,info,\"msg,job,1.0,1")
+            .is_err());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "input.csv_msg_column must be one of the names listed in input.csv_columns"
+    )]
+    fn test_csv_unknown_column() {
+        let config = Config::from_string(
+            "[input]\ncsv_columns = [{ name = \"a\" }]\ncsv_msg_column = \"b\"\n",
+        )
+        .unwrap();
+        CsvDecoder::new(&config);
+    }
+}
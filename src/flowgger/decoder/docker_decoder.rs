@@ -0,0 +1,188 @@
+use super::container_path::ContainerPath;
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use crate::flowgger::utils;
+use chrono::DateTime;
+use serde_json::de;
+use serde_json::value::Value;
+use std::path::Path;
+
+const DEFAULT_HOSTNAME: &str = "unknown";
+
+#[derive(Clone)]
+pub struct DockerDecoder {
+    container_path: ContainerPath,
+}
+
+struct DockerLine {
+    log: String,
+    stream: Option<String>,
+    time: String,
+}
+
+impl DockerDecoder {
+    /// With `input.container_path_metadata = true`, the namespace, pod and container
+    /// names are extracted from the path of the log file
+    pub fn new(config: &Config) -> DockerDecoder {
+        DockerDecoder {
+            container_path: ContainerPath::new(config),
+        }
+    }
+}
+
+/// Parse a `{"log":"...","stream":"stdout","time":"..."}` line
+fn parse_line(line: &str) -> Result<DockerLine, &'static str> {
+    let obj: Value =
+        de::from_str(line).or(Err("Invalid JSON input, unable to parse as a JSON object"))?;
+    let obj = obj.as_object().ok_or("Empty JSON input")?;
+    let log = obj
+        .get("log")
+        .and_then(|x| x.as_str())
+        .ok_or("Missing log entry")?;
+    let time = obj
+        .get("time")
+        .and_then(|x| x.as_str())
+        .ok_or("Missing time entry")?;
+    let stream = obj.get("stream").and_then(|x| x.as_str());
+    Ok(DockerLine {
+        log: log.to_owned(),
+        stream: stream.map(|x| x.to_owned()),
+        time: time.to_owned(),
+    })
+}
+
+impl Decoder for DockerDecoder {
+    /// Decode a Docker `json-file` log line. Docker splits messages larger than 16 KB into
+    /// several lines, and only the last one ends with a line break: these lines are joined
+    /// with the following lines of the same stream. The timestamp and stream of the first
+    /// line are used, and the stream is stored as a `_stream` structured data pair.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let mut lines = line.split('\n');
+        let first = parse_line(lines.next().unwrap_or(""))?;
+        let mut msg = first.log;
+        for line in lines {
+            if msg.ends_with('\n') {
+                return Err("Unexpected Docker line after a complete line");
+            }
+            let line = parse_line(line)?;
+            if line.stream != first.stream {
+                return Err("Unexpected Docker line from another stream");
+            }
+            msg.push_str(&line.log);
+        }
+        if msg.ends_with('\n') {
+            msg.pop();
+            if msg.ends_with('\r') {
+                msg.pop();
+            }
+        }
+        let ts = match DateTime::parse_from_rfc3339(&first.time) {
+            Ok(date) => utils::PreciseTimestamp::from_datetime(date).as_f64(),
+            Err(_) => return Err("Unable to parse the date"),
+        };
+        let mut sd = StructuredData::new(None);
+        if let Some(stream) = first.stream {
+            sd.pairs
+                .push(("_stream".to_owned(), SDValue::String(stream)));
+        }
+        sd.pairs.extend_from_slice(self.container_path.pairs());
+        let record = Record {
+            ts,
+            hostname: DEFAULT_HOSTNAME.to_owned(),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: Some(msg),
+            full_msg: None,
+            sd: if sd.pairs.is_empty() { None } else { Some(sd) },
+        };
+        Ok(record)
+    }
+
+    /// A record isn't complete until a line whose log ends with a line break has been read
+    fn is_complete(&self, line: &str) -> bool {
+        let last = line.rsplit('\n').next().unwrap_or(line);
+        parse_line(last).map_or(true, |x| x.log.ends_with('\n'))
+    }
+
+    /// stdout and stderr lines are interleaved in the same file
+    fn record_stream(&self, line: &str) -> Option<String> {
+        parse_line(line).ok().and_then(|x| x.stream)
+    }
+
+    fn set_source(&mut self, path: &Path) {
+        self.container_path.set_source(path);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    fn sd_value(record: &Record, name: &str) -> Option<SDValue> {
+        record
+            .sd
+            .as_ref()?
+            .pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_docker_decode() {
+        let config = Config::from_string("").unwrap();
+        let decoder = DockerDecoder::new(&config);
+        let res = decoder
+            .decode(r#"{"log":"Listening on :8080\n","stream":"stdout","time":"2015-08-05T15:53:45.637Z"}"#)
+            .unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 637));
+        assert_eq!(res.msg, Some("Listening on :8080".to_owned()));
+        assert_eq!(
+            sd_value(&res, "_stream"),
+            Some(SDValue::String("stdout".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_docker_decode_split() {
+        let config = Config::from_string("[input]\ncontainer_path_metadata = true").unwrap();
+        let mut decoder = DockerDecoder::new(&config);
+        decoder.set_source(Path::new(
+            "/var/log/containers/web-1_default_nginx-4f2a9e.log",
+        ));
+        let first = r#"{"log":"first ","stream":"stderr","time":"2015-08-05T15:53:45Z"}"#;
+        assert!(!decoder.is_complete(first));
+        let line = format!(
+            "{}\n{}",
+            first, r#"{"log":"second\n","stream":"stderr","time":"2015-08-05T15:53:46Z"}"#
+        );
+        assert!(decoder.is_complete(&line));
+        let res = decoder.decode(&line).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.msg, Some("first second".to_owned()));
+        assert_eq!(
+            sd_value(&res, "_namespace"),
+            Some(SDValue::String("default".to_owned()))
+        );
+        assert_eq!(
+            sd_value(&res, "_container_id"),
+            Some(SDValue::String("4f2a9e".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_docker_decode_invalid() {
+        let config = Config::from_string("").unwrap();
+        let decoder = DockerDecoder::new(&config);
+        assert!(decoder.decode("not json").is_err());
+        assert!(decoder.decode(r#"{"log":"msg\n"}"#).is_err());
+        assert!(decoder
+            .decode(r#"{"log":"msg\n","time":"yesterday"}"#)
+            .is_err());
+    }
+}
@@ -2,24 +2,151 @@ use serde_json::de;
 use serde_json::error::Error::Syntax;
 use serde_json::error::ErrorCode;
 use serde_json::value::Value;
+use std::collections::HashMap;
 
 use crate::flowgger::config::Config;
 use crate::flowgger::record::{Record, SDValue, SEVERITY_MAX, StructuredData};
 use crate::flowgger::utils;
+use chrono::DateTime;
 
 use super::Decoder;
 
+const DEFAULT_HOSTNAME: &str = "unknown";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Ts,
+    Host,
+    Msg,
+    FullMsg,
+    Severity,
+    Appname,
+    Procid,
+    Msgid,
+}
+
+#[derive(Clone)]
+enum TsFormat {
+    Auto,
+    Unix,
+    UnixMs,
+    Rfc3339,
+    Strftime(String),
+}
+
 #[derive(Clone)]
-pub struct JsonDecoder;
+pub struct JsonDecoder {
+    fields: HashMap<String, Field>,
+    ts_format: TsFormat,
+}
 
 impl JsonDecoder {
-    /// The JSON decoder doesn't support any configuration, the config is passed as an argument
-    /// just to respect the interface
-    pub fn new(_config: &Config) -> JsonDecoder {
-        JsonDecoder
+    /// `input.json_fields` maps record fields (`ts`, `host`, `msg`, `full_msg`, `severity`,
+    /// `appname`, `procid` and `msgid`) to one or more JSON keys. Unmapped keys become
+    /// structured data. `input.json_ts_format` is either `rfc3339`, `unix`, `unix_ms` or a
+    /// strftime format. By default, numbers are Unix timestamps and strings RFC3339 dates.
+    pub fn new(config: &Config) -> JsonDecoder {
+        let mut mapping: Vec<(Field, Vec<String>)> = vec![
+            (Field::Ts, vec!["timestamp".to_owned()]),
+            (Field::Host, vec!["host".to_owned()]),
+            (
+                Field::Msg,
+                vec!["message".to_owned(), "short_message".to_owned()],
+            ),
+            (Field::FullMsg, vec!["full_message".to_owned()]),
+            (Field::Severity, vec!["level".to_owned()]),
+        ];
+        if let Some(json_fields) = config.lookup("input.json_fields") {
+            let json_fields = json_fields
+                .as_table()
+                .expect("input.json_fields must be a list of field/key pairs");
+            for (field, keys) in json_fields {
+                let field = match field.as_ref() {
+                    "ts" => Field::Ts,
+                    "host" => Field::Host,
+                    "msg" => Field::Msg,
+                    "full_msg" => Field::FullMsg,
+                    "severity" => Field::Severity,
+                    "appname" => Field::Appname,
+                    "procid" => Field::Procid,
+                    "msgid" => Field::Msgid,
+                    _ => panic!("Unsupported field in input.json_fields: [{}]", field),
+                };
+                let keys = match keys.as_array() {
+                    Some(keys) => keys.iter().collect(),
+                    None => vec![keys],
+                };
+                let keys = keys
+                    .iter()
+                    .map(|key| {
+                        key.as_str()
+                            .expect("input.json_fields keys must be strings or lists of strings")
+                            .to_owned()
+                    })
+                    .collect();
+                mapping.retain(|&(f, _)| f != field);
+                mapping.push((field, keys));
+            }
+        }
+        let mut fields = HashMap::new();
+        for (field, keys) in mapping {
+            for key in keys {
+                fields.insert(key, field);
+            }
+        }
+        let ts_format = match config.lookup("input.json_ts_format") {
+            None => TsFormat::Auto,
+            Some(ts_format) => match ts_format
+                .as_str()
+                .expect("input.json_ts_format must be a string")
+            {
+                "rfc3339" => TsFormat::Rfc3339,
+                "unix" => TsFormat::Unix,
+                "unix_ms" => TsFormat::UnixMs,
+                format => TsFormat::Strftime(format.to_owned()),
+            },
+        };
+        JsonDecoder { fields, ts_format }
+    }
+
+    fn parse_ts(&self, value: &Value) -> Result<f64, &'static str> {
+        let unix_ts = || {
+            value
+                .as_f64()
+                .or_else(|| value.as_str().and_then(|x| x.parse::<f64>().ok()))
+        };
+        let rfc3339_ts = || {
+            let date = DateTime::parse_from_rfc3339(value.as_str()?).ok()?;
+            Some(utils::PreciseTimestamp::from_datetime(date).as_f64())
+        };
+        let ts = match self.ts_format {
+            TsFormat::Auto => rfc3339_ts().or_else(unix_ts),
+            TsFormat::Unix => unix_ts(),
+            TsFormat::UnixMs => unix_ts().map(|ts| ts / 1000.0),
+            TsFormat::Rfc3339 => rfc3339_ts(),
+            TsFormat::Strftime(ref format) => value
+                .as_str()
+                .and_then(|x| utils::parse_ts_with_format(x, format).ok()),
+        };
+        ts.ok_or("Invalid timestamp")
     }
 }
 
+fn parse_severity(value: &Value) -> Result<u8, &'static str> {
+    if let Some(severity) = value.as_str() {
+        return utils::parse_severity(severity);
+    }
+    let severity_given = value.as_u64().ok_or("Invalid severity level")?;
+    if severity_given > u64::from(SEVERITY_MAX) {
+        return Err("Invalid severity level (too high)");
+    }
+    Ok(severity_given as u8)
+}
+
+fn as_string(value: &Value, err: &'static str) -> Result<String, &'static str> {
+    Ok(value.as_str().ok_or(err)?.to_owned())
+}
+
 impl Decoder for JsonDecoder {
     /// Implements decode from a JSON formatted text line to a Record object
     ///
@@ -34,11 +161,14 @@ impl Decoder for JsonDecoder {
     /// types associated with specific fields
     fn decode(&self, line: &str) -> Result<Record, &'static str> {
         let mut sd = StructuredData::new(None);
-        let mut ts = 0.0;
+        let mut ts = None;
         let mut hostname = None;
         let mut msg = None;
-        let full_msg = None;
+        let mut full_msg = None;
         let mut severity = None;
+        let mut appname = None;
+        let mut procid = None;
+        let mut msgid = None;
 
         let obj = match de::from_str(line) {
             x @ Ok(_) => x,
@@ -47,36 +177,25 @@ impl Decoder for JsonDecoder {
             }
             x => x,
         };
-        let obj: Value = obj.or(Err("Unable to parse as a JSON object"))?;
+        let obj: Value = obj.or(Err("Invalid JSON input, unable to parse as a JSON object"))?;
         let obj = obj.as_object().ok_or("Empty JSON input")?;
         for (key, value) in obj {
-            match key.as_ref() {
-                "timestamp" => ts = value.as_f64().unwrap_or( utils::PreciseTimestamp::now().as_f64())
-                ,
-                "host" => {
-                    hostname = Some(
-                        value
-                            .as_str()
-                            .ok_or("host name must be a string")?
-                            .to_owned(),
-                    )
+            match self.fields.get(key) {
+                Some(Field::Ts) => ts = Some(self.parse_ts(value)?),
+                Some(Field::Host) => {
+                    hostname = Some(as_string(value, "host name must be a string")?)
                 }
-                "message" => {
-                    msg = Some(
-                        value
-                            .as_str()
-                            .ok_or("message must be a string")?
-                            .to_owned(),
-                    )
+                Some(Field::Msg) => msg = Some(as_string(value, "message must be a string")?),
+                Some(Field::FullMsg) => {
+                    full_msg = Some(as_string(value, "full message must be a string")?)
                 }
-                "level" => {
-                    let severity_given = value.as_u64().ok_or("Invalid severity level")?;
-                    if severity_given > u64::from(SEVERITY_MAX) {
-                        return Err("Invalid severity level (too high)");
-                    }
-                    severity = Some(severity_given as u8)
+                Some(Field::Severity) => severity = Some(parse_severity(value)?),
+                Some(Field::Appname) => {
+                    appname = Some(as_string(value, "appname must be a string")?)
                 }
-                name => {
+                Some(Field::Procid) => procid = Some(as_string(value, "procid must be a string")?),
+                Some(Field::Msgid) => msgid = Some(as_string(value, "msgid must be a string")?),
+                None => {
                     let sd_value: SDValue = match *value {
                         Value::String(ref value) => SDValue::String(value.to_owned()),
                         Value::Bool(value) => SDValue::Bool(value),
@@ -86,23 +205,23 @@ impl Decoder for JsonDecoder {
                         Value::Null => SDValue::Null,
                         _ => return Err("Invalid value type in structured data"),
                     };
-                    let name = if name.starts_with('_') {
-                        name.to_owned()
+                    let name = if key.starts_with('_') {
+                        key.to_owned()
                     } else {
-                        format!("_{}", name)
+                        format!("_{}", key)
                     };
                     sd.pairs.push((name, sd_value));
                 }
             }
         }
         let record = Record {
-            ts,
-            hostname: hostname.unwrap_or(String::from("unknown")),
+            ts: ts.unwrap_or_else(|| utils::PreciseTimestamp::now().as_f64()),
+            hostname: hostname.unwrap_or_else(|| DEFAULT_HOSTNAME.to_owned()),
             facility: None,
             severity,
-            appname: None,
-            procid: None,
-            msgid: None,
+            appname,
+            procid,
+            msgid,
             sd: if sd.pairs.is_empty() { None } else { Some(sd) },
             msg,
             full_msg,
@@ -120,7 +239,9 @@ mod test {
     #[test]
     fn test_json_decoder() {
         let msg = r#"{"version":"1.1", "host": "example.org","short_message": "A short message that helps you identify what is going on", "full_message": "Backtrace here\n\nmore stuff", "timestamp": 1385053862.3072, "level": 1, "_user_id": 9001, "_some_info": "foo", "_some_env_var": "bar"}"#;
-        let res = JsonDecoder.decode(msg).unwrap();
+        let res = JsonDecoder::new(&Config::from_string("").unwrap())
+            .decode(msg)
+            .unwrap();
         assert!(res.ts == 1_385_053_862.307_2);
         assert!(res.hostname == "example.org");
         assert!(res.msg.unwrap() == "A short message that helps you identify what is going on");
@@ -159,27 +280,77 @@ mod test {
     #[should_panic(expected = "Invalid value type in structured data")]
     fn test_json_decoder_bad_key() {
         let msg = r#"{"some_key": []}"#;
-        let _res = JsonDecoder.decode(&msg).unwrap();
+        let _res = JsonDecoder::new(&Config::from_string("").unwrap())
+            .decode(&msg)
+            .unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid timestamp")]
     fn test_json_decoder_bad_timestamp() {
         let msg = r#"{"timestamp": "a string not a timestamp", "host": "anhostname"}"#;
-        let _res = JsonDecoder.decode(&msg).unwrap();
+        let _res = JsonDecoder::new(&Config::from_string("").unwrap())
+            .decode(&msg)
+            .unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid JSON input, unable to parse as a JSON object")]
     fn test_json_decoder_invalid_input() {
-        let _res = JsonDecoder.decode("{some_key = \"some_value\"}").unwrap();
+        let _res = JsonDecoder::new(&Config::from_string("").unwrap())
+            .decode("{some_key = \"some_value\"}")
+            .unwrap();
     }
 
     #[test]
     #[should_panic(expected = "Invalid severity level (too high)")]
     fn test_json_decoder_severity_to_high() {
-        let _res = JsonDecoder
+        let _res = JsonDecoder::new(&Config::from_string("").unwrap())
             .decode(format!("{{\"level\": {}}}", SEVERITY_MAX + 1).as_str())
             .unwrap();
     }
+
+    #[test]
+    fn test_json_decoder_fields() {
+        let config = Config::from_string(
+            "[input.json_fields]\nts = \"@timestamp\"\nhost = [\"hostname\", \"host\"]\nmsg = \
+             \"msg\"\nseverity = \"lvl\"\nappname = \"app\"\n",
+        )
+        .unwrap();
+        let decoder = JsonDecoder::new(&config);
+        let msg = r#"{"@timestamp": "2015-08-05T15:53:45.5Z", "hostname": "example.org", "msg": "hello", "lvl": "ERROR", "app": "api", "message": "kept"}"#;
+        let res = decoder.decode(msg).unwrap();
+        assert_eq!(res.ts, 1_438_790_025.5);
+        assert_eq!(res.hostname, "example.org");
+        assert_eq!(res.msg, Some("hello".to_owned()));
+        assert_eq!(res.severity, Some(3));
+        assert_eq!(res.appname, Some("api".to_owned()));
+        let pairs = res.sd.unwrap().pairs;
+        assert!(pairs
+            .iter()
+            .any(|(k, v)| k == "_message" && *v == SDValue::String("kept".to_owned())));
+
+        let res = decoder.decode(r#"{"host": "h", "lvl": "warn"}"#).unwrap();
+        assert_eq!(res.hostname, "h");
+        assert_eq!(res.severity, Some(4));
+        assert!(decoder.decode(r#"{"lvl": "verbose"}"#).is_err());
+    }
+
+    #[test]
+    fn test_json_decoder_ts_format() {
+        let config = Config::from_string("[input]\njson_ts_format = \"unix_ms\"").unwrap();
+        let res = JsonDecoder::new(&config)
+            .decode(r#"{"timestamp": 1438790025500}"#)
+            .unwrap();
+        assert_eq!(res.ts, 1_438_790_025.5);
+
+        let config =
+            Config::from_string("[input]\njson_ts_format = \"%d/%m/%Y %H:%M:%S\"").unwrap();
+        let decoder = JsonDecoder::new(&config);
+        let res = decoder
+            .decode(r#"{"timestamp": "05/08/2015 15:53:45"}"#)
+            .unwrap();
+        assert_eq!(res.ts, 1_438_790_025.0);
+        assert!(decoder.decode(r#"{"timestamp": 1438790025}"#).is_err());
+    }
 }
@@ -0,0 +1,308 @@
+use super::syslog_header::SyslogHeaderDecoder;
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use crate::flowgger::utils;
+
+const LEEF_MAGIC: &str = "LEEF:";
+const DEFAULT_DELIMITER: char = '\t';
+const DEFAULT_DEV_TIME_FORMAT: &str = "%b %d %Y %H:%M:%S%.f";
+
+#[derive(Clone)]
+pub struct LeefDecoder {
+    header_decoder: SyslogHeaderDecoder,
+}
+
+impl LeefDecoder {
+    /// LEEF events can be sent as is, or wrapped in a syslog header
+    pub fn new(config: &Config) -> LeefDecoder {
+        LeefDecoder {
+            header_decoder: SyslogHeaderDecoder::new(config),
+        }
+    }
+}
+
+impl Decoder for LeefDecoder {
+    /// Decode a `LEEF:1.0|Vendor|Product|Version|EventID|attributes` or
+    /// `LEEF:2.0|Vendor|Product|Version|EventID|Delimiter|attributes` event. The event ID is
+    /// mapped to the msgid. Attributes become structured data, except `devTime` (parsed
+    /// according to `devTimeFormat`), `sev`, `src` and `msg` that are mapped to the record.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let leef_start = line.find(LEEF_MAGIC).ok_or("Missing LEEF header")?;
+        let mut record = self.header_decoder.decode(&line[..leef_start])?;
+        record.full_msg = Some(line.to_owned());
+
+        let (header, mut attributes) = split_header(&line[leef_start + LEEF_MAGIC.len()..], 5)?;
+        let delimiter = match header[0].as_ref() {
+            "1.0" => DEFAULT_DELIMITER,
+            "2.0" => match attributes.find('|') {
+                Some(delimiter_end) => match parse_delimiter(&attributes[..delimiter_end]) {
+                    Some(delimiter) => {
+                        attributes = &attributes[delimiter_end + 1..];
+                        delimiter
+                    }
+                    None => DEFAULT_DELIMITER,
+                },
+                None => DEFAULT_DELIMITER,
+            },
+            _ => return Err("Unsupported LEEF version"),
+        };
+        let mut sd = record
+            .sd
+            .take()
+            .unwrap_or_else(|| StructuredData::new(None));
+        sd.pairs.push((
+            "_device_vendor".to_owned(),
+            SDValue::String(header[1].clone()),
+        ));
+        sd.pairs.push((
+            "_device_product".to_owned(),
+            SDValue::String(header[2].clone()),
+        ));
+        sd.pairs.push((
+            "_device_version".to_owned(),
+            SDValue::String(header[3].clone()),
+        ));
+        record.msgid = Some(header[4].clone());
+
+        let mut dev_time = None;
+        let mut dev_time_format = None;
+        for attribute in attributes.split(delimiter) {
+            if attribute.trim().is_empty() {
+                continue;
+            }
+            let eq = attribute.find('=').ok_or("Invalid LEEF attribute")?;
+            let (key, value) = (&attribute[..eq], &attribute[eq + 1..]);
+            match key {
+                "" => return Err("Missing key in a LEEF attribute"),
+                "devTime" => dev_time = Some(value),
+                "devTimeFormat" => dev_time_format = Some(value),
+                "sev" => record.severity = leef_severity_to_syslog(value)?,
+                "src" => record.hostname = value.to_owned(),
+                "msg" => record.msg = Some(value.to_owned()),
+                _ => sd
+                    .pairs
+                    .push((format!("_{}", key), SDValue::String(value.to_owned()))),
+            }
+        }
+        if let Some(dev_time) = dev_time {
+            record.ts = parse_dev_time(dev_time, dev_time_format)?;
+        }
+        record.sd = Some(sd);
+        Ok(record)
+    }
+}
+
+/// Split the first `count` pipe-separated header fields, unescaping `\|` and `\\`, and
+/// return them along with the rest of the event
+fn split_header(line: &str, count: usize) -> Result<(Vec<String>, &str), &'static str> {
+    let mut fields = Vec::with_capacity(count);
+    let mut field = String::new();
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some((_, c)) if c == '|' || c == '\\' => field.push(c),
+                Some((_, c)) => {
+                    field.push('\\');
+                    field.push(c);
+                }
+                None => field.push('\\'),
+            },
+            '|' => {
+                fields.push(field);
+                field = String::new();
+                if fields.len() == count {
+                    return Ok((fields, &line[i + 1..]));
+                }
+            }
+            c => field.push(c),
+        }
+    }
+    Err("Truncated LEEF header")
+}
+
+/// LEEF 2.0 delimiters are either a single character or an hexadecimal code point such
+/// as `x09` or `0x09`. An empty delimiter field stands for a tab.
+fn parse_delimiter(delimiter: &str) -> Option<char> {
+    let mut chars = delimiter.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => return Some(DEFAULT_DELIMITER),
+        (Some(c), None) => return Some(c),
+        _ => {}
+    }
+    let hex = delimiter
+        .strip_prefix("0x")
+        .or_else(|| delimiter.strip_prefix("x"))?;
+    if hex.is_empty() || hex.len() > 4 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16)
+        .ok()
+        .and_then(std::char::from_u32)
+}
+
+/// Map a LEEF severity (1-10) to a syslog severity
+fn leef_severity_to_syslog(severity: &str) -> Result<Option<u8>, &'static str> {
+    match severity.parse::<u8>() {
+        Ok(0..=3) => Ok(Some(6)),
+        Ok(4..=6) => Ok(Some(4)),
+        Ok(7..=8) => Ok(Some(3)),
+        Ok(9) => Ok(Some(2)),
+        Ok(10) => Ok(Some(1)),
+        _ => Err("Invalid LEEF severity"),
+    }
+}
+
+/// Translate a Java `SimpleDateFormat` pattern, as used by `devTimeFormat`, into a
+/// strftime format
+fn java_to_strftime(pattern: &str) -> Result<String, &'static str> {
+    let mut res = String::with_capacity(pattern.len() * 2);
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            if chars.peek() == Some(&'\'') {
+                chars.next();
+                res.push('\'');
+                continue;
+            }
+            for c in chars.by_ref() {
+                if c == '\'' {
+                    break;
+                }
+                match c {
+                    '%' => res.push_str("%%"),
+                    c => res.push(c),
+                }
+            }
+            continue;
+        }
+        if !c.is_ascii_alphabetic() {
+            match c {
+                '%' => res.push_str("%%"),
+                c => res.push(c),
+            }
+            continue;
+        }
+        let mut count = 1;
+        while chars.peek() == Some(&c) {
+            chars.next();
+            count += 1;
+        }
+        let spec = match (c, count) {
+            ('y', 2) => "%y",
+            ('y', _) => "%Y",
+            ('M', 1..=2) => "%m",
+            ('M', 3) => "%b",
+            ('M', _) => "%B",
+            ('d', _) => "%d",
+            ('H', _) => "%H",
+            ('h', _) => "%I",
+            ('m', _) => "%M",
+            ('s', _) => "%S",
+            ('S', 1..=3) => "%3f",
+            ('S', 4..=6) => "%6f",
+            ('S', _) => "%9f",
+            ('a', _) => "%p",
+            ('E', 1..=3) => "%a",
+            ('E', _) => "%A",
+            ('Z', _) => "%z",
+            ('X', 1..=2) => "%z",
+            ('X', _) => "%:z",
+            _ => return Err("Unsupported pattern in devTimeFormat"),
+        };
+        res.push_str(spec);
+    }
+    Ok(res)
+}
+
+/// `devTime` is a number of milliseconds since the epoch, or a date in the format given
+/// by `devTimeFormat`, `MMM dd yyyy HH:mm:ss` by default. Dates without a time zone are
+/// assumed to be UTC.
+fn parse_dev_time(dev_time: &str, dev_time_format: Option<&str>) -> Result<f64, &'static str> {
+    if let Ok(ms) = dev_time.parse::<f64>() {
+        return Ok(ms / 1000.0);
+    }
+    let format = match dev_time_format {
+        None => DEFAULT_DEV_TIME_FORMAT.to_owned(),
+        Some(dev_time_format) => java_to_strftime(dev_time_format)?,
+    };
+    utils::parse_ts_with_format(dev_time, &format)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    fn sd_value(record: &Record, name: &str) -> Option<String> {
+        record
+            .sd
+            .as_ref()?
+            .pairs
+            .iter()
+            .find(|(k, _)| k == name)
+            .and_then(|(_, v)| match v {
+                SDValue::String(ref v) => Some(v.clone()),
+                _ => None,
+            })
+    }
+
+    #[test]
+    fn test_leef1_decode() {
+        let config = Config::from_string("").unwrap();
+        let msg = "LEEF:1.0|Microsoft|MSExchange|4.0 SP1|15345|src=192.0.2.0\tdst=172.50.123.1\tsev=5\tcat=anomaly\tmsg=this is a message\tdevTime=Aug 05 2015 15:53:45";
+        let res = LeefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 0));
+        assert_eq!(res.hostname, "192.0.2.0");
+        assert_eq!(res.severity, Some(4));
+        assert_eq!(res.msgid, Some("15345".to_owned()));
+        assert_eq!(res.msg, Some("this is a message".to_owned()));
+        assert_eq!(res.full_msg, Some(msg.to_owned()));
+        assert_eq!(
+            sd_value(&res, "_device_product"),
+            Some("MSExchange".to_owned())
+        );
+        assert_eq!(
+            sd_value(&res, "_device_version"),
+            Some("4.0 SP1".to_owned())
+        );
+        assert_eq!(sd_value(&res, "_dst"), Some("172.50.123.1".to_owned()));
+        assert_eq!(sd_value(&res, "_cat"), Some("anomaly".to_owned()));
+    }
+
+    #[test]
+    fn test_leef2_decode_delimiter() {
+        let config = Config::from_string("").unwrap();
+        let msg = "<13>Aug  6 11:15:24 fw01 LEEF:2.0|Lancope|StealthWatch|1.0|41|^|src=10.0.1.8^dst=10.0.0.5^devTimeFormat=yyyy-MM-dd'T'HH:mm:ss.SSSZ^devTime=2015-08-05T17:53:45.250+0200^proto=tcp";
+        let res = LeefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2015, 8, 5, 15, 53, 45, 250));
+        assert_eq!(res.hostname, "10.0.1.8");
+        assert_eq!(res.facility, Some(1));
+        assert_eq!(res.msgid, Some("41".to_owned()));
+        assert_eq!(sd_value(&res, "_proto"), Some("tcp".to_owned()));
+
+        let msg = "LEEF:2.0|V|P|1|42|x09|src=h\tproto=udp";
+        let res = LeefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.hostname, "h");
+        assert_eq!(sd_value(&res, "_proto"), Some("udp".to_owned()));
+
+        let msg = "LEEF:2.0|V|P|1|42|src=h\tproto=udp";
+        let res = LeefDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.hostname, "h");
+    }
+
+    #[test]
+    fn test_leef_decode_invalid() {
+        let config = Config::from_string("").unwrap();
+        let decoder = LeefDecoder::new(&config);
+        assert!(decoder.decode("not a LEEF event").is_err());
+        assert!(decoder.decode("LEEF:1.0|V|P|1").is_err());
+        assert!(decoder.decode("LEEF:3.0|V|P|1|42|src=h").is_err());
+        assert!(decoder.decode("LEEF:1.0|V|P|1|42|sev=11").is_err());
+        assert!(decoder.decode("LEEF:1.0|V|P|1|42|src").is_err());
+        assert!(decoder
+            .decode("LEEF:1.0|V|P|1|42|devTime=yesterday\tdevTimeFormat=MMM dd yyyy")
+            .is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use super::schema::schema_from_config;
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, SDValueType, StructuredData};
+use crate::flowgger::utils;
+use chrono::DateTime;
+use std::collections::HashMap;
+
+const DEFAULT_HOSTNAME: &str = "unknown";
+
+#[derive(Clone)]
+pub struct LogfmtDecoder {
+    schema: Option<HashMap<String, SDValueType>>,
+}
+
+impl LogfmtDecoder {
+    /// The logfmt decoder accepts an optional `input.logfmt_schema` table, with the same
+    /// syntax as `input.ltsv_schema`, to convert values to booleans or numbers
+    pub fn new(config: &Config) -> LogfmtDecoder {
+        let schema = schema_from_config(config, "input.logfmt_schema");
+        LogfmtDecoder { schema }
+    }
+}
+
+impl Decoder for LogfmtDecoder {
+    /// Decode a line of `key=value` pairs. The `time`/`ts`, `level`, `msg`/`message` and
+    /// `host` keys are mapped to the record fields, other keys become structured data.
+    /// A key without a value is a boolean set to `true`.
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let mut sd = StructuredData::new(None);
+        let mut ts = None;
+        let mut hostname = None;
+        let mut msg = None;
+        let mut severity = None;
+
+        for pair in LogfmtPairs::new(line) {
+            let (name, value) = pair?;
+            match (name.as_ref(), value) {
+                ("time", Some(value)) | ("ts", Some(value)) => ts = Some(parse_ts(&value)?),
+                ("host", Some(value)) => hostname = Some(value),
+                ("msg", Some(value)) | ("message", Some(value)) => msg = Some(value),
+                ("level", Some(value)) => severity = Some(utils::parse_severity(&value)?),
+                (name, value) => {
+                    let sdtype = self.schema.as_ref().and_then(|schema| schema.get(name));
+                    let value = match (sdtype, value) {
+                        (None, Some(value)) => SDValue::String(value),
+                        (None, None) => SDValue::Bool(true),
+                        (Some(sdtype), Some(value)) => sdtype.parse_value(&value)?,
+                        (Some(sdtype), None) => sdtype.parse_value("true")?,
+                    };
+                    sd.pairs.push((format!("_{}", name), value));
+                }
+            }
+        }
+        let record = Record {
+            ts: ts.unwrap_or_else(|| utils::PreciseTimestamp::now().as_f64()),
+            hostname: hostname.unwrap_or_else(|| DEFAULT_HOSTNAME.to_owned()),
+            facility: None,
+            severity,
+            appname: None,
+            procid: None,
+            msgid: None,
+            sd: if sd.pairs.is_empty() { None } else { Some(sd) },
+            msg,
+            full_msg: None,
+        };
+        Ok(record)
+    }
+}
+
+/// Iterator over the `key=value` pairs of a logfmt line
+struct LogfmtPairs<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> LogfmtPairs<'a> {
+    fn new(line: &'a str) -> LogfmtPairs<'a> {
+        LogfmtPairs {
+            chars: line.chars().peekable(),
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String, &'static str> {
+        let mut key = String::new();
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                '=' | ' ' | '\t' => break,
+                '"' => return Err("Unexpected quote in a logfmt key"),
+                c => key.push(c),
+            }
+            self.chars.next();
+        }
+        Ok(key)
+    }
+
+    fn parse_quoted_value(&mut self) -> Result<String, &'static str> {
+        let mut value = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err("Unterminated quoted value in a logfmt record"),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some(c) => value.push(c),
+                    None => return Err("Unterminated quoted value in a logfmt record"),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<String, &'static str> {
+        if self.chars.peek() == Some(&'"') {
+            self.chars.next();
+            return self.parse_quoted_value();
+        }
+        let mut value = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == ' ' || c == '\t' {
+                break;
+            }
+            value.push(c);
+            self.chars.next();
+        }
+        Ok(value)
+    }
+}
+
+impl<'a> Iterator for LogfmtPairs<'a> {
+    type Item = Result<(String, Option<String>), &'static str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(&c) = self.chars.peek() {
+            if c != ' ' && c != '\t' {
+                break;
+            }
+            self.chars.next();
+        }
+        self.chars.peek()?;
+        let pair = self.parse_key().and_then(|key| {
+            if key.is_empty() {
+                return Err("Missing name in a logfmt record");
+            }
+            if self.chars.peek() == Some(&'=') {
+                self.chars.next();
+                Ok((key, Some(self.parse_value()?)))
+            } else {
+                Ok((key, None))
+            }
+        });
+        if pair.is_err() {
+            // Stop at the first syntax error
+            while self.chars.next().is_some() {}
+        }
+        Some(pair)
+    }
+}
+
+fn rfc3339_to_unix(rfc3339: &str) -> Result<f64, &'static str> {
+    match DateTime::parse_from_rfc3339(rfc3339) {
+        Ok(date) => Ok(utils::PreciseTimestamp::from_datetime(date).as_f64()),
+        Err(_) => Err("Unable to parse the date"),
+    }
+}
+
+fn unix_strtime_to_unix(et: &str) -> Result<f64, &'static str> {
+    match et.parse::<f64>() {
+        Ok(ts) => Ok(ts),
+        Err(_) => Err("Unable to parse the date"),
+    }
+}
+
+fn parse_ts(line: &str) -> Result<f64, &'static str> {
+    unix_strtime_to_unix(line).or_else(|_| rfc3339_to_unix(line))
+}
+
+#[test]
+fn test_logfmt() {
+    let config = Config::from_string(
+        "[input]\n[input.logfmt_schema]\ncounter = \"u64\"\nscore = \"i64\"\nmean = \
+         \"f64\"\ndone = \"bool\"\n",
+    );
+    let logfmt_decoder = LogfmtDecoder::new(&config.unwrap());
+    let msg = r#"time=2015-08-05T15:53:45.637824Z level=warn host=testhostname msg="this is a \"test\"" counter=42 score=-1 mean=0.42 done dur=12ms path="/a b""#;
+    let res = logfmt_decoder.decode(msg).unwrap();
+    assert!(res.ts == 1_438_790_025.637_824);
+    assert!(res.severity == Some(4));
+    assert!(res.hostname == "testhostname");
+    assert!(res.msg.unwrap() == "this is a \"test\"");
+    let pairs = res.sd.unwrap().pairs;
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::U64(v) = *v {
+        k == "_counter" && v == 42
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::I64(v) = *v {
+        k == "_score" && v == -1
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::F64(v) = *v {
+        k == "_mean" && f64::abs(v - 0.42) < 1e-5
+    } else {
+        false
+    }));
+    assert!(pairs.iter().any(|(k, v)| if let SDValue::Bool(v) = *v {
+        k == "_done" && v
+    } else {
+        false
+    }));
+    assert!(pairs
+        .iter()
+        .any(|(k, v)| if let SDValue::String(ref v) = *v {
+            k == "_dur" && v == "12ms"
+        } else {
+            false
+        }));
+    assert!(pairs
+        .iter()
+        .any(|(k, v)| if let SDValue::String(ref v) = *v {
+            k == "_path" && v == "/a b"
+        } else {
+            false
+        }));
+}
+
+#[test]
+fn test_logfmt_defaults() {
+    let config = Config::from_string("").unwrap();
+    let res = LogfmtDecoder::new(&config)
+        .decode("ts=1438790025.5 level=3 empty= msg=started")
+        .unwrap();
+    assert!(res.ts == 1_438_790_025.5);
+    assert!(res.severity == Some(3));
+    assert!(res.hostname == DEFAULT_HOSTNAME);
+    assert!(res.msg.unwrap() == "started");
+    let pairs = res.sd.unwrap().pairs;
+    assert!(pairs
+        .iter()
+        .any(|(k, v)| if let SDValue::String(ref v) = *v {
+            k == "_empty" && v.is_empty()
+        } else {
+            false
+        }));
+}
+
+#[test]
+fn test_logfmt_invalid() {
+    let config = Config::from_string("").unwrap();
+    let decoder = LogfmtDecoder::new(&config);
+    assert!(decoder.decode(r#"msg="unterminated"#).is_err());
+    assert!(decoder.decode("=value").is_err());
+    assert!(decoder.decode("level=verbose").is_err());
+    assert!(decoder.decode("time=yesterday").is_err());
+}
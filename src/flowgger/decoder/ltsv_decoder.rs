@@ -1,3 +1,4 @@
+use super::schema::schema_from_config;
 use super::Decoder;
 use crate::flowgger::config::Config;
 use crate::flowgger::record::{Record, SDValue, SDValueType, StructuredData};
@@ -21,35 +22,7 @@ pub struct LTSVDecoder {
 
 impl LTSVDecoder {
     pub fn new(config: &Config) -> LTSVDecoder {
-        let schema = match config.lookup("input.ltsv_schema") {
-            None => None,
-            Some(pairs) => {
-                let mut schema = HashMap::new();
-                for (name, sdtype) in pairs
-                    .as_table()
-                    .expect("input.ltsv_schema must be a list of key/type pairs")
-                {
-                    let sdtype = match sdtype
-                        .as_str()
-                        .expect("input.ltsv_schema types must be strings")
-                        .to_lowercase()
-                        .as_ref()
-                    {
-                        "string" => SDValueType::String,
-                        "bool" => SDValueType::Bool,
-                        "f64" => SDValueType::F64,
-                        "i64" => SDValueType::I64,
-                        "u64" => SDValueType::U64,
-                        _ => panic!(format!(
-                            "Unsupported type in input.ltsv_schema for name [{}]",
-                            name
-                        )),
-                    };
-                    schema.insert(name.to_owned(), sdtype);
-                }
-                Some(schema)
-            }
-        };
+        let schema = schema_from_config(config, "input.ltsv_schema");
         let mut suffixes = Suffixes {
             s_bool: None,
             s_f64: None,
@@ -122,80 +95,23 @@ impl Decoder for LTSVDecoder {
                             severity = Some(severity_given);
                         }
                         name => {
-                            let (final_name, value): (String, SDValue) = if let Some(ref schema) =
-                                self.schema
-                            {
-                                match schema.get(name) {
-                                    None | Some(&SDValueType::String) => {
-                                        (format!("_{}", name), SDValue::String(value.to_owned()))
-                                    }
-                                    Some(&SDValueType::Bool) => {
-                                        let final_name = match self.suffixes.s_bool {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::Bool(
-                                                value
-                                                    .parse::<bool>()
-                                                    .or(Err("Type error; boolean was expected"))?,
-                                            ),
-                                        )
-                                    }
-                                    Some(&SDValueType::F64) => {
-                                        let final_name = match self.suffixes.s_f64 {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::F64(
-                                                value
-                                                    .parse::<f64>()
-                                                    .or(Err("Type error; f64 was expected"))?,
-                                            ),
-                                        )
-                                    }
-                                    Some(&SDValueType::I64) => {
-                                        let final_name = match self.suffixes.s_i64 {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::I64(
-                                                value
-                                                    .parse::<i64>()
-                                                    .or(Err("Type error; i64 was expected"))?,
-                                            ),
-                                        )
-                                    }
-                                    Some(&SDValueType::U64) => {
-                                        let final_name = match self.suffixes.s_u64 {
-                                            Some(ref suffix) if !name.ends_with(suffix) => {
-                                                format!("_{}{}", name, suffix)
-                                            }
-                                            _ => format!("_{}", name),
-                                        };
-                                        (
-                                            final_name,
-                                            SDValue::U64(
-                                                value
-                                                    .parse::<u64>()
-                                                    .or(Err("Type error; u64 was expected"))?,
-                                            ),
-                                        )
-                                    }
+                            let sdtype = self.schema.as_ref().and_then(|schema| schema.get(name));
+                            let value = match sdtype {
+                                None => SDValue::String(value.to_owned()),
+                                Some(sdtype) => sdtype.parse_value(value)?,
+                            };
+                            let suffix = match sdtype {
+                                Some(&SDValueType::Bool) => &self.suffixes.s_bool,
+                                Some(&SDValueType::F64) => &self.suffixes.s_f64,
+                                Some(&SDValueType::I64) => &self.suffixes.s_i64,
+                                Some(&SDValueType::U64) => &self.suffixes.s_u64,
+                                None | Some(&SDValueType::String) => &None,
+                            };
+                            let final_name = match *suffix {
+                                Some(ref suffix) if !name.ends_with(suffix) => {
+                                    format!("_{}{}", name, suffix)
                                 }
-                            } else {
-                                (format!("_{}", name), SDValue::String(value.to_owned()))
+                                _ => format!("_{}", name),
                             };
                             sd.pairs.push((final_name, value));
                         }
@@ -1,28 +1,72 @@
+#[cfg(feature = "access-log")]
+mod access_log_decoder;
+mod auto_decoder;
+#[cfg(feature = "capnp-recompile")]
+mod capnp_decoder;
+#[cfg(feature = "cef")]
+mod cef_decoder;
+#[cfg(any(feature = "cri", feature = "docker"))]
+mod container_path;
+#[cfg(feature = "cri")]
+mod cri_decoder;
+#[cfg(feature = "csv")]
+mod csv_decoder;
+#[cfg(feature = "docker")]
+mod docker_decoder;
 #[cfg(feature = "gelf")]
 mod gelf_decoder;
-mod invalid_decoder;
 #[cfg(feature = "json")]
 mod json_decoder;
+#[cfg(feature = "leef")]
+mod leef_decoder;
+#[cfg(feature = "logfmt")]
+mod logfmt_decoder;
 #[cfg(feature = "ltsv")]
 mod ltsv_decoder;
+#[cfg(feature = "regex")]
+mod regex_decoder;
 #[cfg(feature = "rfc3164")]
 mod rfc3164_decoder;
 #[cfg(feature = "rfc5424")]
 mod rfc5424_decoder;
+#[cfg(any(feature = "ltsv", feature = "logfmt", feature = "regex"))]
+mod schema;
+#[cfg(any(feature = "cef", feature = "leef"))]
+mod syslog_header;
 
+#[cfg(feature = "access-log")]
+pub use self::access_log_decoder::AccessLogDecoder;
+pub use self::auto_decoder::AutoDecoder;
+#[cfg(feature = "capnp-recompile")]
+pub use self::capnp_decoder::{decode_capnp_message, CapnpDecoder};
+#[cfg(feature = "cef")]
+pub use self::cef_decoder::CefDecoder;
+#[cfg(feature = "cri")]
+pub use self::cri_decoder::CriDecoder;
+#[cfg(feature = "csv")]
+pub use self::csv_decoder::CsvDecoder;
+#[cfg(feature = "docker")]
+pub use self::docker_decoder::DockerDecoder;
 #[cfg(feature = "gelf")]
 pub use self::gelf_decoder::GelfDecoder;
-pub use self::invalid_decoder::InvalidDecoder;
 #[cfg(feature = "json")]
 pub use self::json_decoder::JsonDecoder;
+#[cfg(feature = "leef")]
+pub use self::leef_decoder::LeefDecoder;
+#[cfg(feature = "logfmt")]
+pub use self::logfmt_decoder::LogfmtDecoder;
 #[cfg(feature = "ltsv")]
 pub use self::ltsv_decoder::LTSVDecoder;
+#[cfg(feature = "regex")]
+pub use self::regex_decoder::RegexDecoder;
 #[cfg(feature = "rfc3164")]
 pub use self::rfc3164_decoder::RFC3164Decoder;
 #[cfg(feature = "rfc5424")]
 pub use self::rfc5424_decoder::RFC5424Decoder;
 
 use crate::flowgger::record::Record;
+use std::path::Path;
+use std::str;
 
 pub trait CloneBoxedDecoder {
     fn clone_boxed<'a>(&self) -> Box<dyn Decoder + Send + 'a>
@@ -45,6 +89,47 @@ impl Clone for Box<dyn Decoder> {
     }
 }
 
+impl Clone for Box<dyn Decoder + Send> {
+    fn clone(&self) -> Box<dyn Decoder + Send> {
+        self.clone_boxed()
+    }
+}
+
+/// Size of the first record in a buffer, or `None` if it hasn't been fully received yet
+pub type FrameSize = fn(&[u8]) -> Result<Option<usize>, &'static str>;
+
 pub trait Decoder: CloneBoxedDecoder {
     fn decode(&self, line: &str) -> Result<Record, &'static str>;
+
+    /// Decode a record received as raw bytes, such as a UDP datagram. Text decoders
+    /// require valid UTF-8.
+    fn decode_bytes(&self, bytes: &[u8]) -> Result<Record, &'static str> {
+        match str::from_utf8(bytes) {
+            Ok(line) => self.decode(line),
+            Err(_) => Err("Invalid UTF-8 input"),
+        }
+    }
+
+    /// Decoders for binary records return the function delimiting them. Inputs reading
+    /// from streams then pass each record to `decode_bytes` instead of splitting lines.
+    fn binary_framing(&self) -> Option<FrameSize> {
+        None
+    }
+
+    /// Whether `line` holds a complete record. Inputs that support records spanning
+    /// several lines keep appending lines until this returns `true`.
+    fn is_complete(&self, _line: &str) -> bool {
+        true
+    }
+
+    /// The stream a line belongs to, when records of several streams are interleaved,
+    /// such as the stdout and stderr of a container. A line is only appended to an
+    /// incomplete record of the same stream.
+    fn record_stream(&self, _line: &str) -> Option<String> {
+        None
+    }
+
+    /// Called by inputs reading from files with the path of the file the following
+    /// records are read from
+    fn set_source(&mut self, _path: &Path) {}
 }
@@ -0,0 +1,395 @@
+use super::schema::schema_from_config;
+use super::Decoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue, SDValueType, StructuredData};
+use crate::flowgger::utils;
+use chrono::{DateTime, NaiveDateTime};
+use regex::Regex;
+use std::collections::HashMap;
+
+const DEFAULT_HOSTNAME: &str = "unknown";
+const MAX_ALIAS_DEPTH: usize = 16;
+
+/// Grok-style aliases usable in patterns as `%{NAME}` or `%{NAME:field}`
+const GROK_ALIASES: &[(&str, &str)] = &[
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    ("INT", r"[+-]?[0-9]+"),
+    ("POSINT", r"\b[1-9][0-9]*\b"),
+    ("NONNEGINT", r"\b[0-9]+\b"),
+    ("BASE10NUM", r"[+-]?(?:[0-9]+(?:\.[0-9]+)?|\.[0-9]+)"),
+    ("NUMBER", r"%{BASE10NUM}"),
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*"|'(?:[^'\\]|\\.)*'"#),
+    (
+        "UUID",
+        r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}",
+    ),
+    (
+        "IPV4",
+        r"(?:(?:25[0-5]|2[0-4][0-9]|[01]?[0-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|[01]?[0-9]?[0-9])",
+    ),
+    (
+        "IPV6",
+        r"(?:[0-9A-Fa-f]{0,4}:){2,7}(?:%{IPV4}|[0-9A-Fa-f]{1,4})?",
+    ),
+    ("IP", r"(?:%{IPV6}|%{IPV4})"),
+    (
+        "HOSTNAME",
+        r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?",
+    ),
+    ("IPORHOST", r"(?:%{IP}|%{HOSTNAME})"),
+    ("HOSTPORT", r"%{IPORHOST}:%{POSINT}"),
+    ("PATH", r"(?:/[\w_%!$@:.,+~-]*)+"),
+    ("URIPATHPARAM", r"/[^\s?]*(?:\?[^\s]*)?"),
+    (
+        "MONTH",
+        r"\b(?:[Jj]an(?:uary)?|[Ff]eb(?:ruary)?|[Mm]ar(?:ch)?|[Aa]pr(?:il)?|[Mm]ay|[Jj]une?|[Jj]uly?|[Aa]ug(?:ust)?|[Ss]ep(?:tember)?|[Oo]ct(?:ober)?|[Nn]ov(?:ember)?|[Dd]ec(?:ember)?)\b",
+    ),
+    ("MONTHNUM", r"(?:0?[1-9]|1[0-2])"),
+    ("MONTHDAY", r"(?:0[1-9]|[12][0-9]|3[01]|[1-9])"),
+    (
+        "DAY",
+        r"(?:Mon(?:day)?|Tue(?:sday)?|Wed(?:nesday)?|Thu(?:rsday)?|Fri(?:day)?|Sat(?:urday)?|Sun(?:day)?)",
+    ),
+    ("YEAR", r"(?:[0-9]{2}){1,2}"),
+    ("HOUR", r"(?:2[0123]|[01]?[0-9])"),
+    ("MINUTE", r"(?:[0-5][0-9])"),
+    ("SECOND", r"(?:(?:[0-5]?[0-9]|60)(?:[:.,][0-9]+)?)"),
+    ("TIME", r"%{HOUR}:%{MINUTE}:%{SECOND}"),
+    ("ISO8601_TIMEZONE", r"(?:Z|[+-]%{HOUR}(?::?%{MINUTE}))"),
+    (
+        "TIMESTAMP_ISO8601",
+        r"%{YEAR}-%{MONTHNUM}-%{MONTHDAY}[T ]%{HOUR}:?%{MINUTE}(?::?%{SECOND})?%{ISO8601_TIMEZONE}?",
+    ),
+    ("HTTPDATE", r"%{MONTHDAY}/%{MONTH}/%{YEAR}:%{TIME} %{INT}"),
+    ("SYSLOGTIMESTAMP", r"%{MONTH} +%{MONTHDAY} %{TIME}"),
+    (
+        "LOGLEVEL",
+        r"(?:[Aa]lert|ALERT|[Tt]race|TRACE|[Dd]ebug|DEBUG|[Nn]otice|NOTICE|[Ii]nfo|INFO|[Ww]arn?(?:ing)?|WARN?(?:ING)?|[Ee]rr?(?:or)?|ERR?(?:OR)?|[Cc]rit?(?:ical)?|CRIT?(?:ICAL)?|[Ff]atal|FATAL|[Ss]evere|SEVERE|EMERG(?:ENCY)?|[Ee]merg(?:ency)?)",
+    ),
+];
+
+#[derive(Clone)]
+pub struct RegexDecoder {
+    patterns: Vec<Regex>,
+    time_format: Option<String>,
+    schema: HashMap<String, SDValueType>,
+}
+
+impl RegexDecoder {
+    /// Build a decoder from the following settings:
+    ///
+    /// - `input.regex_patterns`: a pattern or a list of patterns, tried in order. Named
+    ///   captures and grok aliases such as `%{IP:client}` or `%{INT:bytes:u64}` are supported.
+    /// - `input.regex_aliases`: optional table of additional `NAME = "pattern"` aliases
+    /// - `input.regex_time_format`: optional strftime format of the `ts` capture
+    /// - `input.regex_schema`: optional table of `name = "type"` pairs, as `input.ltsv_schema`
+    ///
+    /// The `ts`, `host`, `severity`, `facility`, `appname`, `procid`, `msgid` and `msg`
+    /// captures are mapped to the record fields, every other capture becomes a structured
+    /// data pair.
+    ///
+    /// # Panics
+    ///
+    /// - `Missing input.regex_patterns`
+    /// - `input.regex_patterns must be a string or a list of strings`
+    /// - `input.regex_aliases must be a list of name/pattern pairs`
+    /// - `input.regex_time_format must be a string`
+    /// - `Invalid pattern in input.regex_patterns`
+    pub fn new(config: &Config) -> RegexDecoder {
+        let patterns: Vec<String> = match config.lookup("input.regex_patterns") {
+            None => panic!("Missing input.regex_patterns"),
+            Some(patterns) => match patterns.as_str() {
+                Some(pattern) => vec![pattern.to_owned()],
+                None => patterns
+                    .as_array()
+                    .expect("input.regex_patterns must be a string or a list of strings")
+                    .iter()
+                    .map(|x| {
+                        x.as_str()
+                            .expect("input.regex_patterns must be a string or a list of strings")
+                            .to_owned()
+                    })
+                    .collect(),
+            },
+        };
+        let mut aliases: HashMap<String, String> = GROK_ALIASES
+            .iter()
+            .map(|&(name, pattern)| (name.to_owned(), pattern.to_owned()))
+            .collect();
+        if let Some(extra) = config.lookup("input.regex_aliases") {
+            for (name, pattern) in extra
+                .as_table()
+                .expect("input.regex_aliases must be a list of name/pattern pairs")
+            {
+                let pattern = pattern
+                    .as_str()
+                    .expect("input.regex_aliases must be a list of name/pattern pairs");
+                aliases.insert(name.to_owned(), pattern.to_owned());
+            }
+        }
+        let time_format = config.lookup("input.regex_time_format").map(|x| {
+            x.as_str()
+                .expect("input.regex_time_format must be a string")
+                .to_owned()
+        });
+        let mut schema = schema_from_config(config, "input.regex_schema").unwrap_or_default();
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                let expanded = expand_aliases(pattern, &aliases, &mut schema, 0)
+                    .unwrap_or_else(|e| panic!("Invalid pattern in input.regex_patterns: {}", e));
+                Regex::new(&expanded)
+                    .unwrap_or_else(|e| panic!("Invalid pattern in input.regex_patterns: {}", e))
+            })
+            .collect();
+        RegexDecoder {
+            patterns,
+            time_format,
+            schema,
+        }
+    }
+}
+
+impl Decoder for RegexDecoder {
+    /// Decode a line using the first configured pattern that matches it
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let (pattern, captures) = self
+            .patterns
+            .iter()
+            .find_map(|pattern| pattern.captures(line).map(|captures| (pattern, captures)))
+            .ok_or("No pattern matched the input")?;
+        let mut sd = StructuredData::new(None);
+        let mut ts = None;
+        let mut hostname = None;
+        let mut facility = None;
+        let mut severity = None;
+        let mut appname = None;
+        let mut procid = None;
+        let mut msgid = None;
+        let mut msg = None;
+
+        for name in pattern.capture_names().flatten() {
+            let value = match captures.name(name) {
+                Some(value) => value.as_str(),
+                None => continue,
+            };
+            match name {
+                "ts" | "timestamp" => ts = Some(self.parse_ts(value)?),
+                "host" | "hostname" => hostname = Some(value.to_owned()),
+                "facility" => {
+                    facility = Some(value.parse::<u8>().or(Err("Invalid facility"))?);
+                }
+                "severity" | "level" => severity = Some(utils::parse_severity(value)?),
+                "appname" => appname = Some(value.to_owned()),
+                "procid" => procid = Some(value.to_owned()),
+                "msgid" => msgid = Some(value.to_owned()),
+                "msg" | "message" => msg = Some(value.to_owned()),
+                name => {
+                    let value = match self.schema.get(name) {
+                        None => SDValue::String(value.to_owned()),
+                        Some(sdtype) => sdtype.parse_value(value)?,
+                    };
+                    sd.pairs.push((format!("_{}", name), value));
+                }
+            }
+        }
+        let record = Record {
+            ts: ts.unwrap_or_else(|| utils::PreciseTimestamp::now().as_f64()),
+            hostname: hostname.unwrap_or_else(|| DEFAULT_HOSTNAME.to_owned()),
+            facility,
+            severity,
+            appname,
+            procid,
+            msgid,
+            sd: if sd.pairs.is_empty() { None } else { Some(sd) },
+            msg: msg.or_else(|| Some(line.to_owned())),
+            full_msg: Some(line.to_owned()),
+        };
+        Ok(record)
+    }
+}
+
+impl RegexDecoder {
+    /// Parse the `ts` capture with the configured format, or guess the format if none was set.
+    /// Dates without an offset are assumed to be UTC.
+    fn parse_ts(&self, value: &str) -> Result<f64, &'static str> {
+        match self.time_format {
+            Some(ref format) => utils::parse_ts_with_format(value, format),
+            None => value
+                .parse::<f64>()
+                .or_else(|_| {
+                    DateTime::parse_from_rfc3339(value)
+                        .map(utils::PreciseTimestamp::from_datetime)
+                        .map(|x| x.as_f64())
+                })
+                .or_else(|_| {
+                    DateTime::parse_from_str(value, "%d/%b/%Y:%H:%M:%S%.f %z")
+                        .map(|date| utils::PreciseTimestamp::from_datetime(date).as_f64())
+                })
+                .or_else(|_| {
+                    NaiveDateTime::parse_from_str(
+                        &value.replacen(' ', "T", 1),
+                        "%Y-%m-%dT%H:%M:%S%.f",
+                    )
+                    .map(|date| utils::PreciseTimestamp::from_naive_datetime(date).as_f64())
+                })
+                .or(Err("Unable to parse the date")),
+        }
+    }
+}
+
+/// Replace `%{NAME}`, `%{NAME:field}` and `%{NAME:field:type}` references with the aliased
+/// patterns. Typed fields are added to the schema.
+fn expand_aliases(
+    pattern: &str,
+    aliases: &HashMap<String, String>,
+    schema: &mut HashMap<String, SDValueType>,
+    depth: usize,
+) -> Result<String, String> {
+    if depth > MAX_ALIAS_DEPTH {
+        return Err("Too many nested aliases".to_owned());
+    }
+    let mut res = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find("%{") {
+        res.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unterminated alias in [{}]", pattern))?
+            + start;
+        let mut parts = rest[start + 2..end].splitn(3, ':');
+        let alias = parts.next().unwrap_or("");
+        let aliased = aliases
+            .get(alias)
+            .ok_or_else(|| format!("Unknown alias [{}]", alias))?;
+        let expanded = expand_aliases(aliased, aliases, schema, depth + 1)?;
+        match (parts.next(), parts.next()) {
+            (Some(field), sdtype) => {
+                if let Some(sdtype) = sdtype {
+                    let sdtype = match sdtype {
+                        "int" => SDValueType::I64,
+                        "float" => SDValueType::F64,
+                        sdtype => SDValueType::from_name(sdtype)
+                            .ok_or_else(|| format!("Unsupported type [{}]", sdtype))?,
+                    };
+                    schema.insert(field.to_owned(), sdtype);
+                }
+                res.push_str(&format!("(?P<{}>{})", field, expanded));
+            }
+            (None, _) => res.push_str(&format!("(?:{})", expanded)),
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_date_time;
+
+    #[test]
+    fn test_regex_decoder_grok() {
+        let config = Config::from_string(
+            r#"[input]
+regex_patterns = ['^%{IPORHOST:host} %{USER:ident} \[%{HTTPDATE:ts}\] "%{WORD:method} %{URIPATHPARAM:path}" %{INT:status:u64} %{NUMBER:duration:float} %{LOGLEVEL:level} %{GREEDYDATA:msg}$']
+"#,
+        )
+        .unwrap();
+        let msg = r#"10.0.0.1 frank [10/Oct/2000:13:55:36 -0700] "GET /index.html?a=b" 200 0.25 WARN slow request"#;
+        let res = RegexDecoder::new(&config).decode(msg).unwrap();
+        assert_eq!(res.hostname, "10.0.0.1");
+        assert_eq!(res.ts, 971_211_336.0);
+        assert_eq!(res.severity, Some(4));
+        assert_eq!(res.msg, Some("slow request".to_owned()));
+        assert_eq!(res.full_msg, Some(msg.to_owned()));
+        let pairs = res.sd.unwrap().pairs;
+        assert!(pairs
+            .iter()
+            .cloned()
+            .any(|(k, v)| if let SDValue::String(v) = v {
+                k == "_ident" && v == "frank"
+            } else {
+                false
+            }));
+        assert!(pairs
+            .iter()
+            .cloned()
+            .any(|(k, v)| if let SDValue::String(v) = v {
+                k == "_path" && v == "/index.html?a=b"
+            } else {
+                false
+            }));
+        assert!(pairs.iter().any(|(k, v)| if let SDValue::U64(v) = v {
+            k == "_status" && *v == 200
+        } else {
+            false
+        }));
+        assert!(pairs.iter().any(|(k, v)| if let SDValue::F64(v) = v {
+            k == "_duration" && f64::abs(*v - 0.25) < 1e-5
+        } else {
+            false
+        }));
+    }
+
+    #[test]
+    fn test_regex_decoder_fallback_patterns() {
+        let config = Config::from_string(
+            r#"[input]
+regex_patterns = [
+    '^(?P<ts>\S+ \S+) \[%{THREAD:thread}\] (?P<level>\w+) (?P<msg>.*)$',
+    '^%{GREEDYDATA:msg}$',
+]
+regex_time_format = "%Y-%m-%d %H:%M:%S%.f"
+[input.regex_aliases]
+THREAD = '[a-z-]+'
+[input.regex_schema]
+thread = "string"
+"#,
+        )
+        .unwrap();
+        let decoder = RegexDecoder::new(&config);
+        let res = decoder
+            .decode("2020-08-06 11:15:24.500 [main] error Something broke")
+            .unwrap();
+        assert_eq!(res.ts, ts_from_date_time(2020, 8, 6, 11, 15, 24, 500));
+        assert_eq!(res.severity, Some(3));
+        assert_eq!(res.hostname, DEFAULT_HOSTNAME);
+        assert_eq!(res.msg, Some("Something broke".to_owned()));
+
+        let res = decoder.decode("free-form line").unwrap();
+        assert_eq!(res.msg, Some("free-form line".to_owned()));
+        assert!(res.sd.is_none());
+    }
+
+    #[test]
+    fn test_regex_decoder_no_match() {
+        let config = Config::from_string("[input]\nregex_patterns = '^%{INT:count}$'").unwrap();
+        let res = RegexDecoder::new(&config).decode("not a number");
+        assert!(res.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Type error; u64 was expected")]
+    fn test_regex_decoder_type_error() {
+        let config = Config::from_string(
+            "[input]\nregex_patterns = '^%{NOTSPACE:count}$'\n[input.regex_schema]\ncount = \"u64\"",
+        )
+        .unwrap();
+        RegexDecoder::new(&config).decode("-1").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid pattern in input.regex_patterns: Unknown alias [NOPE]")]
+    fn test_regex_decoder_unknown_alias() {
+        let config = Config::from_string("[input]\nregex_patterns = '%{NOPE:x}'").unwrap();
+        let _ = RegexDecoder::new(&config);
+    }
+}
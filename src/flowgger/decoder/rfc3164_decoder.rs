@@ -2,20 +2,98 @@ use super::Decoder;
 use crate::flowgger::config::Config;
 use crate::flowgger::record::Record;
 use crate::flowgger::utils;
-use chrono::{Datelike, NaiveDateTime, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
 
+/// Time zone used to interpret timestamps that don't carry an offset
 #[derive(Clone)]
-pub struct RFC3164Decoder {}
+enum TimeZoneSpec {
+    Fixed(FixedOffset),
+    Named(Tz),
+}
+
+impl TimeZoneSpec {
+    /// Parse either a fixed offset (`Z`, `UTC`, `+02:00`, `-0500`) or an IANA time zone name
+    fn parse(tz: &str) -> Option<TimeZoneSpec> {
+        match tz {
+            "Z" | "z" | "UTC" | "utc" => return Some(TimeZoneSpec::Fixed(FixedOffset::east(0))),
+            _ => {}
+        }
+        if let Some(offset) = parse_fixed_offset(tz) {
+            return Some(TimeZoneSpec::Fixed(offset));
+        }
+        tz.parse::<Tz>().ok().map(TimeZoneSpec::Named)
+    }
+
+    /// Convert a local date to UTC, picking the earliest instant on ambiguous DST transitions
+    fn to_utc(&self, local: &NaiveDateTime) -> Option<NaiveDateTime> {
+        match *self {
+            TimeZoneSpec::Fixed(offset) => offset
+                .from_local_datetime(local)
+                .earliest()
+                .map(|dt| dt.naive_utc()),
+            TimeZoneSpec::Named(tz) => tz
+                .from_local_datetime(local)
+                .earliest()
+                .map(|dt| dt.naive_utc()),
+        }
+    }
+}
+
+fn parse_fixed_offset(tz: &str) -> Option<FixedOffset> {
+    let sign = match tz.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = tz[1..].replace(':', "");
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[0..2].parse().ok()?;
+    let minutes: i32 = digits[2..4].parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[derive(Clone)]
+pub struct RFC3164Decoder {
+    timezone: TimeZoneSpec,
+}
 
 impl RFC3164Decoder {
-    pub fn new(_config: &Config) -> RFC3164Decoder {
-        RFC3164Decoder {}
+    /// The RFC3164 decoder accepts an optional `input.rfc3164_timezone` setting, used to
+    /// interpret timestamps that don't include an offset. It can be an IANA time zone name
+    /// (`Europe/Paris`) or a fixed offset (`+02:00`), and defaults to UTC.
+    ///
+    /// # Panics
+    ///
+    /// - `input.rfc3164_timezone must be a string`
+    /// - `input.rfc3164_timezone must be an IANA time zone name or a fixed offset such as +02:00`
+    pub fn new(config: &Config) -> RFC3164Decoder {
+        let timezone = match config.lookup("input.rfc3164_timezone") {
+            None => TimeZoneSpec::Fixed(FixedOffset::east(0)),
+            Some(tz) => TimeZoneSpec::parse(
+                tz.as_str()
+                    .expect("input.rfc3164_timezone must be a string"),
+            )
+            .expect(
+                "input.rfc3164_timezone must be an IANA time zone name or a fixed offset such \
+                 as +02:00",
+            ),
+        };
+        RFC3164Decoder { timezone }
     }
 }
 
 impl Decoder for RFC3164Decoder {
     /// Implementation of the RF3164 decoder. Decode a string into a record object
     ///
+    /// Besides the BSD `Mmm dd hh:mm:ss` timestamp, RFC3339 timestamps and
+    /// `YYYY-MM-DD hh:mm:ss` timestamps are accepted in the same position.
+    ///
     /// # Arguments
     /// * `line` - String to decode
     ///
@@ -29,16 +107,14 @@ impl Decoder for RFC3164Decoder {
         // The event may have several consecutive spaces as separator
         let tokens_vec = _msg.split_whitespace().collect::<Vec<&str>>();
 
-        // If we have less than 4 tokens, the input can't be valid
-        if tokens_vec.len() > 3 {
-            // Date is made of the first 3 space separated tokens, rebuild it
-            let _date_str = tokens_vec[0..3].join(" ");
-            let _hostname = tokens_vec[3];
+        // The timestamp spans 1 to 3 tokens and must be followed by a hostname
+        let (ts, ts_len) = parse_ts(&tokens_vec, &self.timezone, Utc::now())?;
+        if tokens_vec.len() > ts_len {
+            let _hostname = tokens_vec[ts_len];
 
             // All that remains is the message that may contain several spaces, so rebuild it
-            let _message = tokens_vec[4..].join(" ");
+            let _message = tokens_vec[ts_len + 1..].join(" ");
 
-            let ts = parse_ts(&_date_str)?;
             let record = Record {
                 ts,
                 hostname: _hostname.to_owned(),
@@ -92,19 +168,84 @@ fn parse_strip_pri(event: &str) -> Result<(Pri, &str), &'static str> {
     }
 }
 
-fn parse_ts(ts_str: &str) -> Result<f64, &'static str> {
-    // Append the year to parse a full ts
-    let current_year = Utc::now().year();
-    let ts = format!("{} {}", current_year, ts_str);
+/// Parse the timestamp found at the beginning of `tokens`
+///
+/// # Returns
+/// The timestamp and the number of tokens it was made of
+fn parse_ts(
+    tokens: &[&str],
+    timezone: &TimeZoneSpec,
+    now: DateTime<Utc>,
+) -> Result<(f64, usize), &'static str> {
+    let first = tokens
+        .first()
+        .ok_or("Malformed RFC3164 event: Missing timestamp")?;
 
-    match NaiveDateTime::parse_from_str(&ts, "%Y %b %d %H:%M:%S") {
-        Ok(date) => Ok(utils::PreciseTimestamp::from_naive_datetime(date).as_f64()),
-        Err(_) => Err("Unable to parse the date"),
+    // RFC3339, e.g. 2020-08-06T11:15:24.123+02:00
+    if let Ok(date) = DateTime::parse_from_rfc3339(first) {
+        return Ok((utils::PreciseTimestamp::from_datetime(date).as_f64(), 1));
+    }
+
+    // ISO date without a T separator nor offset, e.g. 2020-08-06 11:15:24
+    if tokens.len() > 1 {
+        let date_str = format!("{} {}", tokens[0], tokens[1]);
+        if let Ok(date) = NaiveDateTime::parse_from_str(&date_str, "%Y-%m-%d %H:%M:%S%.f") {
+            let date = timezone.to_utc(&date).ok_or("Invalid local time")?;
+            return Ok((
+                utils::PreciseTimestamp::from_naive_datetime(date).as_f64(),
+                2,
+            ));
+        }
+    }
+
+    // BSD timestamp, e.g. Aug  6 11:15:24
+    if tokens.len() > 2 {
+        let date_str = tokens[0..3].join(" ");
+        return Ok((parse_bsd_ts(&date_str, timezone, now)?, 3));
+    }
+    Err("Malformed RFC3164 event: Invalid timestamp or hostname")
+}
+
+/// BSD timestamps don't include a year. Among the previous, current and next year, pick the
+/// one that puts the event closest to `now`, so that events sent around new year don't end up
+/// twelve months away.
+fn parse_bsd_ts(
+    ts_str: &str,
+    timezone: &TimeZoneSpec,
+    now: DateTime<Utc>,
+) -> Result<f64, &'static str> {
+    let now_ts = now.timestamp() as f64;
+    let mut best: Option<f64> = None;
+    let mut parsed = false;
+    for year in &[now.year() - 1, now.year(), now.year() + 1] {
+        let ts = format!("{} {}", year, ts_str);
+        let date = match NaiveDateTime::parse_from_str(&ts, "%Y %b %d %H:%M:%S%.f") {
+            Ok(date) => date,
+            // Feb 29 only exists on leap years
+            Err(_) => continue,
+        };
+        parsed = true;
+        let date = match timezone.to_utc(&date) {
+            Some(date) => date,
+            None => continue,
+        };
+        let ts = utils::PreciseTimestamp::from_naive_datetime(date).as_f64();
+        best = match best {
+            Some(best) if (best - now_ts).abs() <= (ts - now_ts).abs() => Some(best),
+            _ => Some(ts),
+        };
+    }
+    match (best, parsed) {
+        (Some(ts), _) => Ok(ts),
+        (None, true) => Err("Invalid local time"),
+        (None, false) => Err("Unable to parse the date"),
     }
 }
 
 #[cfg(test)]
-use crate::flowgger::utils::test_utils::rfc_test_utils::ts_from_partial_date_time;
+use crate::flowgger::utils::test_utils::rfc_test_utils::{
+    ts_from_date_time, ts_from_partial_date_time, utc_from_date_time,
+};
 
 #[test]
 fn test_rfc3164_decode() {
@@ -163,3 +304,84 @@ fn test_rfc3164_decode_invalid_date() {
     let res = decoder.decode(msg);
     assert!(res.is_err());
 }
+
+#[test]
+fn test_rfc3164_decode_timezone() {
+    let msg = "<13>Aug  6 11:15:24 testhostname test message";
+    let cfg = Config::from_string("[input]\nrfc3164_timezone = \"+02:00\"\n").unwrap();
+    let expected_ts = ts_from_partial_date_time(8, 6, 9, 15, 24);
+
+    let decoder = RFC3164Decoder::new(&cfg);
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.ts, expected_ts);
+    assert_eq!(res.hostname, "testhostname");
+    assert_eq!(res.msg, Some("test message".to_string()));
+}
+
+#[test]
+fn test_rfc3164_decode_named_timezone() {
+    let msg = "2020-01-15 11:15:24 testhostname test message";
+    let cfg = Config::from_string("[input]\nrfc3164_timezone = \"America/New_York\"\n").unwrap();
+
+    let decoder = RFC3164Decoder::new(&cfg);
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.ts, ts_from_date_time(2020, 1, 15, 16, 15, 24, 0));
+
+    // Daylight saving time applies in summer
+    let msg = "2020-07-15 11:15:24 testhostname test message";
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.ts, ts_from_date_time(2020, 7, 15, 15, 15, 24, 0));
+}
+
+#[test]
+#[should_panic(
+    expected = "input.rfc3164_timezone must be an IANA time zone name or a fixed offset such as +02:00"
+)]
+fn test_rfc3164_decode_invalid_timezone() {
+    let cfg = Config::from_string("[input]\nrfc3164_timezone = \"Mars/Olympus_Mons\"\n").unwrap();
+    let _ = RFC3164Decoder::new(&cfg);
+}
+
+#[test]
+fn test_rfc3164_decode_rfc3339() {
+    let msg = "<13>2020-08-06T11:15:24.500+02:00 testhostname appname: test message";
+    let cfg = Config::from_string("[input]\nrfc3164_timezone = \"Asia/Tokyo\"\n").unwrap();
+
+    let decoder = RFC3164Decoder::new(&cfg);
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.ts, ts_from_date_time(2020, 8, 6, 9, 15, 24, 500));
+    assert_eq!(res.hostname, "testhostname");
+    assert_eq!(res.msg, Some("appname: test message".to_string()));
+}
+
+#[test]
+fn test_rfc3164_decode_iso_date() {
+    let msg = "<13>2020-08-06 11:15:24.250 testhostname test message";
+    let cfg = Config::from_string("").unwrap();
+
+    let decoder = RFC3164Decoder::new(&cfg);
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.ts, ts_from_date_time(2020, 8, 6, 11, 15, 24, 250));
+    assert_eq!(res.hostname, "testhostname");
+    assert_eq!(res.msg, Some("test message".to_string()));
+}
+
+#[test]
+fn test_rfc3164_year_rollover() {
+    let utc = TimeZoneSpec::Fixed(FixedOffset::east(0));
+
+    // An event from the last day of the year received just after new year
+    let now = utc_from_date_time(2021, 1, 1, 0, 0, 5, 0);
+    let ts = parse_bsd_ts("Dec 31 23:59:58", &utc, now).unwrap();
+    assert_eq!(ts, ts_from_date_time(2020, 12, 31, 23, 59, 58, 0));
+
+    // An event from a sender whose clock is slightly ahead, just before new year
+    let now = utc_from_date_time(2020, 12, 31, 23, 59, 58, 0);
+    let ts = parse_bsd_ts("Jan  1 00:00:03", &utc, now).unwrap();
+    assert_eq!(ts, ts_from_date_time(2021, 1, 1, 0, 0, 3, 0));
+
+    // Leap days only resolve to leap years
+    let now = utc_from_date_time(2021, 1, 10, 0, 0, 0, 0);
+    let ts = parse_bsd_ts("Feb 29 12:00:00", &utc, now).unwrap();
+    assert_eq!(ts, ts_from_date_time(2020, 2, 29, 12, 0, 0, 0));
+}
@@ -2,39 +2,88 @@ use super::Decoder;
 use crate::flowgger::config::Config;
 use crate::flowgger::record::{Record, SDValue, StructuredData};
 use crate::flowgger::utils;
-use chrono::DateTime;
+use chrono::{DateTime, NaiveDateTime};
+
+const DEFAULT_MODE: &str = "lenient";
+const PRI_MAX: u8 = 191;
+const HOSTNAME_MAX_LEN: usize = 255;
+const APPNAME_MAX_LEN: usize = 48;
+const PROCID_MAX_LEN: usize = 128;
+const MSGID_MAX_LEN: usize = 32;
+const SD_NAME_MAX_LEN: usize = 32;
+const SECFRAC_MAX_LEN: usize = 6;
+
+/// How closely incoming messages must follow RFC5424
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    /// Enforce the field lengths and character sets of the RFC
+    Strict,
+    /// Accept common vendor deviations: missing priority or version, repeated spaces and ISO
+    /// timestamps without a `T` separator or offset
+    Lenient,
+}
 
 #[derive(Clone)]
-pub struct RFC5424Decoder;
+pub struct RFC5424Decoder {
+    mode: Mode,
+}
 
 impl RFC5424Decoder {
-    pub fn new(_config: &Config) -> RFC5424Decoder {
-        RFC5424Decoder
+    /// The RFC5424 decoder accepts an optional `input.rfc5424_mode` setting, either `"strict"`
+    /// or `"lenient"`. The default is `"lenient"`, that accepts everything previous versions
+    /// accepted.
+    ///
+    /// # Panics
+    ///
+    /// - `input.rfc5424_mode must be a string set to "strict" or "lenient"`
+    pub fn new(config: &Config) -> RFC5424Decoder {
+        let mode = match config
+            .lookup("input.rfc5424_mode")
+            .map_or(DEFAULT_MODE, |x| {
+                x.as_str()
+                    .expect(r#"input.rfc5424_mode must be a string set to "strict" or "lenient""#)
+            })
+            .to_lowercase()
+            .as_ref()
+        {
+            "strict" => Mode::Strict,
+            "lenient" => Mode::Lenient,
+            _ => panic!(r#"input.rfc5424_mode must be a string set to "strict" or "lenient""#),
+        };
+        RFC5424Decoder { mode }
     }
 }
 
 impl Decoder for RFC5424Decoder {
     fn decode(&self, line: &str) -> Result<Record, &'static str> {
-        let (_bom, line) = match BOM::parse(line, "<") {
-            Ok(bom_line) => bom_line,
-            Err(err) => return Err(err),
-        };
-        let mut parts = line.splitn(7, ' ');
-        let pri_version = parse_pri_version(parts.next().ok_or("Missing priority and version")?)?;
-        let ts = parse_ts(parts.next().ok_or("Missing timestamp")?)?;
-        let hostname = parts.next().ok_or("Missing hostname")?;
-        let appname = parts.next().ok_or("Missing application name")?;
-        let procid = parts.next().ok_or("Missing process id")?;
-        let msgid = parts.next().ok_or("Missing message id")?;
-        let (sd, msg) = parse_data(parts.next().ok_or("Missing message data")?)?;
+        let mode = self.mode;
+        let (_bom, line) = BOM::parse(line, "<", mode)?;
+        let (pri, rest) = parse_pri(line, mode)?;
+        let mut rest = parse_version(rest, mode)?;
+        let ts = parse_ts(&mut rest, mode)?;
+        let hostname = next_field(&mut rest, mode).ok_or("Missing hostname")?;
+        let hostname = check_field(hostname, HOSTNAME_MAX_LEN, mode, "Invalid hostname")?;
+        let appname = next_field(&mut rest, mode).ok_or("Missing application name")?;
+        let appname = check_field(appname, APPNAME_MAX_LEN, mode, "Invalid application name")?;
+        let procid = next_field(&mut rest, mode).ok_or("Missing process id")?;
+        let procid = check_field(procid, PROCID_MAX_LEN, mode, "Invalid process id")?;
+        let msgid = next_field(&mut rest, mode).ok_or("Missing message id")?;
+        let msgid = check_field(msgid, MSGID_MAX_LEN, mode, "Invalid message id")?;
+        if mode == Mode::Lenient {
+            rest = rest.trim_start_matches(' ');
+        }
+        if rest.is_empty() {
+            return Err("Missing message data");
+        }
+        let (sd, msg) = parse_data(rest, mode)?;
         let record = Record {
             ts,
             hostname: hostname.to_owned(),
-            facility: Some(pri_version.facility),
-            severity: Some(pri_version.severity),
-            appname: Some(appname.to_owned()),
-            procid: Some(procid.to_owned()),
-            msgid: Some(msgid.to_owned()),
+            facility: pri.as_ref().map(|pri| pri.facility),
+            severity: pri.as_ref().map(|pri| pri.severity),
+            appname: nil_to_none(appname),
+            procid: nil_to_none(procid),
+            msgid: nil_to_none(msgid),
             sd,
             msg,
             full_msg: Some(line.to_owned()),
@@ -54,10 +103,10 @@ enum BOM {
 }
 
 impl BOM {
-    fn parse<'a>(line: &'a str, sep: &str) -> Result<(BOM, &'a str), &'static str> {
+    fn parse<'a>(line: &'a str, sep: &str, mode: Mode) -> Result<(BOM, &'a str), &'static str> {
         if line.starts_with('\u{feff}') {
             Ok((BOM::UTF8, &line[3..]))
-        } else if line.starts_with(sep) {
+        } else if line.starts_with(sep) || mode == Mode::Lenient {
             Ok((BOM::NONE, line))
         } else {
             Err("Unsupported BOM")
@@ -65,24 +114,109 @@ impl BOM {
     }
 }
 
-fn parse_pri_version(line: &str) -> Result<Pri, &'static str> {
+/// Strip the `<PRI>` part. It is mandatory in strict mode, where it can't exceed 191.
+fn parse_pri(line: &str, mode: Mode) -> Result<(Option<Pri>, &str), &'static str> {
     if !line.starts_with('<') {
-        return Err("The priority should be inside brackets");
+        return match mode {
+            Mode::Strict => Err("The priority should be inside brackets"),
+            Mode::Lenient => Ok((None, line)),
+        };
     }
     let mut parts = line[1..].splitn(2, '>');
-    let pri_encoded: u8 = parts
+    let pri_str = parts.next().ok_or("Empty priority")?;
+    let rest = parts
         .next()
-        .ok_or("Empty priority")?
-        .parse()
-        .or(Err("Invalid priority"))?;
-    let version = parts.next().ok_or("Missing version")?;
-    if version != "1" {
-        return Err("Unsupported version");
+        .ok_or("The priority should be inside brackets")?;
+    if pri_str.is_empty() || pri_str.len() > 3 || !pri_str.bytes().all(|c| c.is_ascii_digit()) {
+        return Err("Invalid priority");
     }
-    Ok(Pri {
+    let pri_encoded: u8 = pri_str.parse().or(Err("Invalid priority"))?;
+    if mode == Mode::Strict && pri_encoded > PRI_MAX {
+        return Err("Invalid priority (too high)");
+    }
+    let pri = Pri {
         facility: pri_encoded >> 3,
         severity: pri_encoded & 7,
-    })
+    };
+    Ok((Some(pri), rest))
+}
+
+/// Strip the version and the space that follows it. Only version 1 is supported in strict
+/// mode, whereas the version can be anything numeric or be missing in lenient mode.
+fn parse_version(line: &str, mode: Mode) -> Result<&str, &'static str> {
+    let mut parts = line.splitn(2, ' ');
+    let version = parts.next().ok_or("Missing version")?;
+    match mode {
+        Mode::Strict => {
+            if version.is_empty() {
+                return Err("Missing version");
+            }
+            if version != "1" {
+                return Err("Unsupported version");
+            }
+            parts.next().ok_or("Missing timestamp")
+        }
+        Mode::Lenient => {
+            if !version.is_empty() && version.bytes().all(|c| c.is_ascii_digit()) {
+                Ok(parts.next().unwrap_or(""))
+            } else {
+                Ok(line)
+            }
+        }
+    }
+}
+
+/// Extract the next space-separated header field. In strict mode, fields are separated by
+/// exactly one space; in lenient mode, any number of spaces is accepted.
+fn next_field<'a>(line: &mut &'a str, mode: Mode) -> Option<&'a str> {
+    if mode == Mode::Lenient {
+        *line = line.trim_start_matches(' ');
+    }
+    if line.is_empty() {
+        return None;
+    }
+    let mut parts = line.splitn(2, ' ');
+    let field = parts.next()?;
+    *line = parts.next().unwrap_or("");
+    if field.is_empty() {
+        None
+    } else {
+        Some(field)
+    }
+}
+
+fn is_printusascii(c: char) -> bool {
+    matches!(c as u32, 33..=126)
+}
+
+/// In strict mode, header fields must be made of at most `max_len` printable US-ASCII
+/// characters
+fn check_field<'a>(
+    field: &'a str,
+    max_len: usize,
+    mode: Mode,
+    err: &'static str,
+) -> Result<&'a str, &'static str> {
+    if mode == Mode::Strict && (field.len() > max_len || !field.chars().all(is_printusascii)) {
+        return Err(err);
+    }
+    Ok(field)
+}
+
+fn nil_to_none(field: &str) -> Option<String> {
+    match field {
+        "-" => None,
+        field => Some(field.to_owned()),
+    }
+}
+
+/// SD-NAME = 1*32PRINTUSASCII except '=', SP, ']', '"'
+fn is_sd_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= SD_NAME_MAX_LEN
+        && name
+            .chars()
+            .all(|c| is_printusascii(c) && c != '=' && c != ']' && c != '"')
 }
 
 fn rfc3339_to_unix(rfc3339: &str) -> Result<f64, &'static str> {
@@ -92,8 +226,55 @@ fn rfc3339_to_unix(rfc3339: &str) -> Result<f64, &'static str> {
     }
 }
 
-fn parse_ts(line: &str) -> Result<f64, &'static str> {
-    rfc3339_to_unix(line)
+/// Dates without an offset are assumed to be UTC
+fn naive_iso_to_unix(iso: &str) -> Result<f64, &'static str> {
+    match NaiveDateTime::parse_from_str(iso, "%Y-%m-%dT%H:%M:%S%.f") {
+        Ok(date) => Ok(utils::PreciseTimestamp::from_naive_datetime(date).as_f64()),
+        Err(_) => Err("Unable to parse the date"),
+    }
+}
+
+fn check_secfrac(ts: &str) -> Result<(), &'static str> {
+    if let Some(dot) = ts.find('.') {
+        let secfrac_len = ts[dot + 1..]
+            .bytes()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if secfrac_len == 0 || secfrac_len > SECFRAC_MAX_LEN {
+            return Err("Invalid fractional seconds in the timestamp");
+        }
+    }
+    Ok(())
+}
+
+/// Parse the timestamp, or use the current time if it is the NILVALUE. In lenient mode, the
+/// date and time can also be separated by a space or by a `T`, and the offset can be missing.
+fn parse_ts(line: &mut &str, mode: Mode) -> Result<f64, &'static str> {
+    let ts = next_field(line, mode).ok_or("Missing timestamp")?;
+    if ts == "-" {
+        return Ok(utils::PreciseTimestamp::now().as_f64());
+    }
+    match mode {
+        Mode::Strict => {
+            if !ts.contains('T') {
+                return Err("Unable to parse the date");
+            }
+            check_secfrac(ts)?;
+            rfc3339_to_unix(ts)
+        }
+        Mode::Lenient => {
+            if let Ok(ts) = rfc3339_to_unix(ts).or_else(|_| naive_iso_to_unix(ts)) {
+                return Ok(ts);
+            }
+            // The date and the time may have been split by a space
+            let mut rest = *line;
+            let time = next_field(&mut rest, mode).ok_or("Unable to parse the date")?;
+            let iso = format!("{}T{}", ts, time);
+            let ts = rfc3339_to_unix(&iso).or_else(|_| naive_iso_to_unix(&iso))?;
+            *line = rest;
+            Ok(ts)
+        }
+    }
 }
 
 fn unescape_sd_value(value: &str) -> String {
@@ -129,7 +310,10 @@ fn parse_msg(line: &str, offset: usize) -> Option<String> {
     }
 }
 
-fn parse_data(line: &str) -> Result<(Option<StructuredData>, Option<String>), &'static str> {
+fn parse_data(
+    line: &str,
+    mode: Mode,
+) -> Result<(Option<StructuredData>, Option<String>), &'static str> {
     match line.chars().next().ok_or("Short message")? {
         '-' => {
             return Ok((None, parse_msg(line, 1)));
@@ -137,9 +321,21 @@ fn parse_data(line: &str) -> Result<(Option<StructuredData>, Option<String>), &'
         '[' => {}
         _ => return Err("Short message"),
     };
-    let mut parts = line[1..].splitn(2, ' ');
-    let sd_id = parts.next().ok_or("Missing structured data id")?;
-    let sd = parts.next().ok_or("Missing structured data")?;
+    let sd_id_end = line[1..]
+        .find(&[' ', ']'][..])
+        .ok_or("Missing ] after structured data")?
+        + 1;
+    let sd_id = &line[1..sd_id_end];
+    if mode == Mode::Strict && !is_sd_name(sd_id) {
+        return Err("Invalid structured data id");
+    }
+    if line[sd_id_end..].starts_with(']') {
+        return Ok((
+            Some(StructuredData::new(Some(sd_id))),
+            parse_msg(line, sd_id_end + 1),
+        ));
+    }
+    let sd = &line[sd_id_end + 1..];
     let mut in_name = false;
     let mut in_value = false;
     let mut name_start = 0;
@@ -173,6 +369,9 @@ fn parse_data(line: &str) -> Result<(Option<StructuredData>, Option<String>), &'
             ('=', false, _, true, ..) => {
                 name = Some(&sd[name_start..i]);
                 in_name = false;
+                if mode == Mode::Strict && name.map_or(0, str::len) > SD_NAME_MAX_LEN {
+                    return Err("Structured data parameter name is too long");
+                }
             }
             ('"', false, _, _, true, false) => {
                 in_value = true;
@@ -209,7 +408,8 @@ fn parse_data(line: &str) -> Result<(Option<StructuredData>, Option<String>), &'
 #[test]
 fn test_rfc5424() {
     let msg = r#"<23>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 [origin@123 software="te\st sc\"ript" swVersion="0.0.1"] test message"#;
-    let res = RFC5424Decoder.decode(msg).unwrap();
+    let cfg = Config::from_string("").unwrap();
+    let res = RFC5424Decoder::new(&cfg).decode(msg).unwrap();
     assert!(res.facility.unwrap() == 2);
     assert!(res.severity.unwrap() == 7);
     assert!(res.ts == 1438790025.637824);
@@ -239,3 +439,106 @@ fn test_rfc5424() {
             false
         }));
 }
+
+#[test]
+fn test_rfc5424_nilvalues() {
+    let msg = "<23>1 2015-08-05T15:53:45.637824Z testhostname - - - - test message";
+    let cfg = Config::from_string("").unwrap();
+    let res = RFC5424Decoder::new(&cfg).decode(msg).unwrap();
+    assert_eq!(res.appname, None);
+    assert_eq!(res.procid, None);
+    assert_eq!(res.msgid, None);
+    assert!(res.sd.is_none());
+    assert_eq!(res.msg, Some("test message".to_owned()));
+}
+
+#[test]
+fn test_rfc5424_sd_without_params() {
+    let msg = "<23>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 [exampleSDID@32473] test message";
+    let cfg = Config::from_string("").unwrap();
+    let res = RFC5424Decoder::new(&cfg).decode(msg).unwrap();
+    let sd = res.sd.unwrap();
+    assert_eq!(sd.sd_id, Some("exampleSDID@32473".to_owned()));
+    assert!(sd.pairs.is_empty());
+    assert_eq!(res.msg, Some("test message".to_owned()));
+}
+
+#[test]
+fn test_rfc5424_strict() {
+    let cfg = Config::from_string("[input]\nrfc5424_mode = \"strict\"\n").unwrap();
+    let decoder = RFC5424Decoder::new(&cfg);
+    let invalid = [
+        // PRI above 191
+        "<192>1 2015-08-05T15:53:45Z testhostname appname 69 42 - test message",
+        // Missing PRI
+        "1 2015-08-05T15:53:45Z testhostname appname 69 42 - test message",
+        // Unsupported version
+        "<23>2 2015-08-05T15:53:45Z testhostname appname 69 42 - test message",
+        // Timestamp without a T
+        "<23>1 2015-08-05 15:53:45Z testhostname appname 69 42 - test message",
+        // Too many fractional digits
+        "<23>1 2015-08-05T15:53:45.1234567Z testhostname appname 69 42 - test message",
+        // Extra space
+        "<23>1 2015-08-05T15:53:45Z  testhostname appname 69 42 - test message",
+        // MSGID longer than 32 characters
+        "<23>1 2015-08-05T15:53:45Z testhostname appname 69 0123456789abcdef0123456789abcdefg - test message",
+        // Non-ASCII application name
+        "<23>1 2015-08-05T15:53:45Z testhostname appnàme 69 42 - test message",
+        // Invalid SD-ID
+        r#"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 [orig"in a="b"] test message"#,
+    ];
+    for msg in invalid.iter() {
+        assert!(decoder.decode(msg).is_err(), "{} should be rejected", msg);
+    }
+    let msg = "<191>1 2015-08-05T15:53:45Z testhostname appname 69 42 - test message";
+    assert!(decoder.decode(msg).is_ok());
+}
+
+#[test]
+fn test_rfc5424_lenient() {
+    let cfg = Config::from_string("[input]\nrfc5424_mode = \"lenient\"\n").unwrap();
+    let decoder = RFC5424Decoder::new(&cfg);
+
+    // Missing version, extra spaces and a timestamp without a T nor an offset
+    let msg = "<23>2015-08-05 15:53:45.637824  testhostname  appname - 42   - test message";
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.facility, Some(2));
+    assert_eq!(res.severity, Some(7));
+    assert_eq!(res.ts, 1_438_790_025.637_824);
+    assert_eq!(res.hostname, "testhostname");
+    assert_eq!(res.appname, Some("appname".to_owned()));
+    assert_eq!(res.procid, None);
+    assert_eq!(res.msgid, Some("42".to_owned()));
+    assert_eq!(res.msg, Some("test message".to_owned()));
+
+    // Missing PRI, other version and a PRI above 191
+    let msg = "2 2015-08-05T15:53:45.637824Z testhostname appname 69 42 - test message";
+    let res = decoder.decode(msg).unwrap();
+    assert_eq!(res.facility, None);
+    assert_eq!(res.ts, 1_438_790_025.637_824);
+    let msg = "<200>1 2015-08-05T15:53:45.637824Z testhostname appname 69 42 - test message";
+    assert!(decoder.decode(msg).is_ok());
+}
+
+#[test]
+fn test_rfc5424_default_mode() {
+    let cfg = Config::from_string("").unwrap();
+    let decoder = RFC5424Decoder::new(&cfg);
+    let valid = [
+        "<200>1 2015-08-05T15:53:45Z testhostname appname 69 42 - test message",
+        "<23>1 2015-08-05T15:53:45.1234567Z testhostname appname 69 42 - test message",
+        "<23>1 2015-08-05T15:53:45Z testhostname appname 69 0123456789abcdef0123456789abcdefg - test message",
+        "<23>1 2015-08-05T15:53:45Z testhostname appnàme 69 42 - test message",
+        r#"<23>1 2015-08-05T15:53:45Z testhostname appname 69 42 [orig"in a="b"] test message"#,
+    ];
+    for msg in valid.iter() {
+        assert!(decoder.decode(msg).is_ok(), "{} should be accepted", msg);
+    }
+}
+
+#[test]
+#[should_panic(expected = r#"input.rfc5424_mode must be a string set to "strict" or "lenient""#)]
+fn test_rfc5424_invalid_mode() {
+    let cfg = Config::from_string("[input]\nrfc5424_mode = \"relaxed\"\n").unwrap();
+    let _ = RFC5424Decoder::new(&cfg);
+}
@@ -0,0 +1,30 @@
+use crate::flowgger::config::Config;
+use crate::flowgger::record::SDValueType;
+use std::collections::HashMap;
+
+/// Read a table of `name = "type"` pairs describing the type of structured data values
+///
+/// # Parameters
+/// - `config`: the configuration to read the schema from
+/// - `key`: the dotted path of the table, for example `input.ltsv_schema`
+///
+/// # Panics
+/// - `<key> must be a list of key/type pairs`
+/// - `<key> types must be strings`
+/// - `Unsupported type in <key> for name [<name>]`
+pub fn schema_from_config(config: &Config, key: &str) -> Option<HashMap<String, SDValueType>> {
+    let pairs = config.lookup(key)?;
+    let mut schema = HashMap::new();
+    for (name, sdtype) in pairs
+        .as_table()
+        .unwrap_or_else(|| panic!("{} must be a list of key/type pairs", key))
+    {
+        let sdtype = sdtype
+            .as_str()
+            .unwrap_or_else(|| panic!("{} types must be strings", key));
+        let sdtype = SDValueType::from_name(sdtype)
+            .unwrap_or_else(|| panic!("Unsupported type in {} for name [{}]", key, name));
+        schema.insert(name.to_owned(), sdtype);
+    }
+    Some(schema)
+}
@@ -0,0 +1,65 @@
+use super::{Decoder, RFC3164Decoder, RFC5424Decoder};
+use crate::flowgger::config::Config;
+use crate::flowgger::record::Record;
+use crate::flowgger::utils;
+
+const DEFAULT_HOSTNAME: &str = "unknown";
+
+/// Decoder for the optional RFC3164 or RFC5424 header that formats such as CEF and LEEF
+/// can be wrapped in
+#[derive(Clone)]
+pub struct SyslogHeaderDecoder {
+    rfc3164_decoder: RFC3164Decoder,
+    rfc5424_decoder: RFC5424Decoder,
+}
+
+impl SyslogHeaderDecoder {
+    /// The header is decoded using the same options as the `rfc3164` and `rfc5424` decoders
+    pub fn new(config: &Config) -> SyslogHeaderDecoder {
+        SyslogHeaderDecoder {
+            rfc3164_decoder: RFC3164Decoder::new(config),
+            rfc5424_decoder: RFC5424Decoder::new(config),
+        }
+    }
+
+    /// Decode the header preceding the payload. An empty header gives a record received
+    /// now from an unknown host.
+    pub fn decode(&self, header: &str) -> Result<Record, &'static str> {
+        let header = header.trim_end();
+        if header.is_empty() {
+            return Ok(Record {
+                ts: utils::PreciseTimestamp::now().as_f64(),
+                hostname: DEFAULT_HOSTNAME.to_owned(),
+                facility: None,
+                severity: None,
+                appname: None,
+                procid: None,
+                msgid: None,
+                msg: None,
+                full_msg: None,
+                sd: None,
+            });
+        }
+        let after_pri = match header.find('>') {
+            Some(pri_end) if header.starts_with('<') => &header[pri_end + 1..],
+            _ => header,
+        };
+        if after_pri.starts_with("1 ") {
+            return self.rfc5424_decoder.decode(header);
+        }
+        let mut record = self.rfc3164_decoder.decode(header)?;
+        // The remaining token, if any, is a tag such as `appname[procid]:`
+        if let Some(tag) = record.msg.take() {
+            let tag = tag.trim_end_matches(':');
+            match tag.find('[') {
+                Some(procid_start) if tag.ends_with(']') => {
+                    record.appname = Some(tag[..procid_start].to_owned());
+                    record.procid = Some(tag[procid_start + 1..tag.len() - 1].to_owned());
+                }
+                _ if !tag.is_empty() => record.appname = Some(tag.to_owned()),
+                _ => {}
+            }
+        }
+        Ok(record)
+    }
+}
@@ -0,0 +1,232 @@
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+
+const DEFAULT_DEVICE_VENDOR: &str = "flowgger";
+const DEFAULT_DEVICE_PRODUCT: &str = "flowgger";
+const DEFAULT_DEVICE_VERSION: &str = env!("CARGO_PKG_VERSION");
+const DEFAULT_SIGNATURE_ID: &str = "0";
+
+#[derive(Clone)]
+pub struct CefEncoder {
+    device_vendor: String,
+    device_product: String,
+    device_version: String,
+    extra: Vec<(String, String)>,
+}
+
+impl CefEncoder {
+    /// The device vendor, product and version are read from the `_device_vendor`,
+    /// `_device_product` and `_device_version` structured data pairs set by the CEF
+    /// decoder, and default to `output.cef_device_vendor`, `output.cef_device_product`
+    /// and `output.cef_device_version`
+    pub fn new(config: &Config) -> CefEncoder {
+        let lookup_str = |key: &str, default: &str| {
+            config.lookup(key).map_or(default.to_owned(), |x| {
+                x.as_str()
+                    .unwrap_or_else(|| panic!("{} must be a string", key))
+                    .to_owned()
+            })
+        };
+        let device_vendor = lookup_str("output.cef_device_vendor", DEFAULT_DEVICE_VENDOR);
+        let device_product = lookup_str("output.cef_device_product", DEFAULT_DEVICE_PRODUCT);
+        let device_version = lookup_str("output.cef_device_version", DEFAULT_DEVICE_VERSION);
+        let extra = match config.lookup("output.cef_extra") {
+            None => Vec::new(),
+            Some(extra) => extra
+                .as_table()
+                .expect("output.cef_extra must be a list of key/value pairs")
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.to_owned(),
+                        v.as_str()
+                            .expect("output.cef_extra values must be strings")
+                            .to_owned(),
+                    )
+                })
+                .collect(),
+        };
+        CefEncoder {
+            device_vendor,
+            device_product,
+            device_version,
+            extra,
+        }
+    }
+}
+
+fn escape_header(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\n', '\r'], " ")
+}
+
+fn escape_extension(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('=', "\\=")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Extension keys must be alphanumeric, other characters are replaced with `_`
+fn sanitize_key(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Map a syslog severity to a CEF severity between 0 and 10
+fn syslog_severity_to_cef(severity: u8) -> &'static str {
+    match severity {
+        0 | 1 => "10",
+        2 => "9",
+        3 => "7",
+        4 => "5",
+        5 => "3",
+        6 => "2",
+        _ => "0",
+    }
+}
+
+struct Extension {
+    out: String,
+}
+
+impl Extension {
+    fn insert(&mut self, key: &str, value: &str) {
+        if !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        self.out.push_str(&sanitize_key(key));
+        self.out.push('=');
+        self.out.push_str(&escape_extension(value));
+    }
+}
+
+impl Encoder for CefEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let mut device_vendor = self.device_vendor.as_str();
+        let mut device_product = self.device_product.as_str();
+        let mut device_version = self.device_version.as_str();
+        let mut cef_severity = None;
+        let mut ext = Extension { out: String::new() };
+
+        ext.insert("rt", &format!("{}", (record.ts * 1000.0).round() as u64));
+        ext.insert("dvchost", &record.hostname);
+        if let Some(ref appname) = record.appname {
+            ext.insert("deviceProcessName", appname);
+        }
+        if let Some(ref procid) = record.procid {
+            ext.insert("dvcpid", procid);
+        }
+        if let Some(ref sd) = record.sd {
+            for (name, value) in &sd.pairs {
+                let name = name.strip_prefix('_').unwrap_or(name);
+                match (name, value) {
+                    ("device_vendor", SDValue::String(value)) => device_vendor = value,
+                    ("device_product", SDValue::String(value)) => device_product = value,
+                    ("device_version", SDValue::String(value)) => device_version = value,
+                    ("cef_severity", SDValue::String(value)) => cef_severity = Some(value.as_str()),
+                    (name, SDValue::String(value)) => ext.insert(name, value),
+                    (name, SDValue::Bool(value)) => ext.insert(name, &value.to_string()),
+                    (name, SDValue::F64(value)) => ext.insert(name, &value.to_string()),
+                    (name, SDValue::I64(value)) => ext.insert(name, &value.to_string()),
+                    (name, SDValue::U64(value)) => ext.insert(name, &value.to_string()),
+                    (name, SDValue::Null) => ext.insert(name, ""),
+                }
+            }
+        }
+        for (name, value) in &self.extra {
+            ext.insert(name, value);
+        }
+        let severity = cef_severity
+            .or_else(|| record.severity.map(syslog_severity_to_cef))
+            .unwrap_or("Unknown");
+        let signature_id = record.msgid.as_deref().unwrap_or(DEFAULT_SIGNATURE_ID);
+        let name = record.msg.as_deref().unwrap_or("");
+        let res = format!(
+            "CEF:0|{}|{}|{}|{}|{}|{}|{}",
+            escape_header(device_vendor),
+            escape_header(device_product),
+            escape_header(device_version),
+            escape_header(signature_id),
+            escape_header(name),
+            escape_header(severity),
+            ext.out
+        );
+        Ok(res.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+
+    #[test]
+    fn test_cef_encode() {
+        let config = Config::from_string(
+            "[output]\ncef_device_vendor = \"Acme\"\n[output.cef_extra]\ncs1Label = \"env\"",
+        )
+        .unwrap();
+        let record = Record {
+            ts: 1_438_790_025.637,
+            hostname: "example.org".to_owned(),
+            facility: None,
+            severity: Some(3),
+            appname: Some("app".to_owned()),
+            procid: None,
+            msgid: Some("100".to_owned()),
+            msg: Some("Blocked | denied".to_owned()),
+            full_msg: None,
+            sd: Some(StructuredData {
+                sd_id: None,
+                pairs: vec![
+                    (
+                        "_device_product".to_owned(),
+                        SDValue::String("fw".to_owned()),
+                    ),
+                    ("_act".to_owned(), SDValue::String("a=b\\c\nd".to_owned())),
+                    ("_spt".to_owned(), SDValue::U64(1232)),
+                ],
+            }),
+        };
+        let res = CefEncoder::new(&config).encode(record).unwrap();
+        let expected = format!(
+            "CEF:0|Acme|fw|{}|100|Blocked \\| denied|7|rt=1438790025637 dvchost=example.org \
+             deviceProcessName=app act=a\\=b\\\\c\\nd spt=1232 cs1Label=env",
+            DEFAULT_DEVICE_VERSION
+        );
+        assert_eq!(String::from_utf8(res).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_cef_encode_cef_severity() {
+        let config = Config::from_string("").unwrap();
+        let record = Record {
+            ts: 1.0,
+            hostname: "h".to_owned(),
+            facility: None,
+            severity: Some(3),
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: None,
+            full_msg: None,
+            sd: Some(StructuredData {
+                sd_id: None,
+                pairs: vec![(
+                    "_cef_severity".to_owned(),
+                    SDValue::String("High".to_owned()),
+                )],
+            }),
+        };
+        let res = CefEncoder::new(&config).encode(record).unwrap();
+        assert!(String::from_utf8(res)
+            .unwrap()
+            .ends_with("|0||High|rt=1000 dvchost=h"));
+    }
+}
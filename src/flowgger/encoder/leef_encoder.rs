@@ -0,0 +1,261 @@
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+const DEFAULT_VERSION: &str = "2.0";
+const DEFAULT_DELIMITER: char = '\t';
+const DEFAULT_DEVICE_VENDOR: &str = "flowgger";
+const DEFAULT_DEVICE_PRODUCT: &str = "flowgger";
+const DEFAULT_DEVICE_VERSION: &str = env!("CARGO_PKG_VERSION");
+const DEFAULT_EVENT_ID: &str = "0";
+const DEV_TIME_FORMAT: &str = "MMM dd yyyy HH:mm:ss.SSS";
+
+#[derive(Clone)]
+pub struct LeefEncoder {
+    version: String,
+    delimiter: char,
+    device_vendor: String,
+    device_product: String,
+    device_version: String,
+    extra: Vec<(String, String)>,
+}
+
+impl LeefEncoder {
+    /// `output.leef_version` is either `1.0` or `2.0`. LEEF 2.0 events can use a custom
+    /// `output.leef_delimiter` instead of tabs. The device vendor, product and version are
+    /// read from the `_device_vendor`, `_device_product` and `_device_version` structured
+    /// data pairs, and default to `output.leef_device_vendor`, `output.leef_device_product`
+    /// and `output.leef_device_version`.
+    pub fn new(config: &Config) -> LeefEncoder {
+        let lookup_str = |key: &str, default: &str| {
+            config.lookup(key).map_or(default.to_owned(), |x| {
+                x.as_str()
+                    .unwrap_or_else(|| panic!("{} must be a string", key))
+                    .to_owned()
+            })
+        };
+        let version = lookup_str("output.leef_version", DEFAULT_VERSION);
+        if version != "1.0" && version != "2.0" {
+            panic!(r#"output.leef_version must be "1.0" or "2.0""#);
+        }
+        let delimiter = match config.lookup("output.leef_delimiter") {
+            None => DEFAULT_DELIMITER,
+            Some(delimiter) => {
+                let delimiter = delimiter
+                    .as_str()
+                    .expect("output.leef_delimiter must be a single character");
+                let mut chars = delimiter.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c != '=' && c != '|' => c,
+                    _ => panic!("output.leef_delimiter must be a single character"),
+                }
+            }
+        };
+        if version == "1.0" && delimiter != DEFAULT_DELIMITER {
+            panic!("output.leef_delimiter requires output.leef_version = \"2.0\"");
+        }
+        let device_vendor = lookup_str("output.leef_device_vendor", DEFAULT_DEVICE_VENDOR);
+        let device_product = lookup_str("output.leef_device_product", DEFAULT_DEVICE_PRODUCT);
+        let device_version = lookup_str("output.leef_device_version", DEFAULT_DEVICE_VERSION);
+        let extra = match config.lookup("output.leef_extra") {
+            None => Vec::new(),
+            Some(extra) => extra
+                .as_table()
+                .expect("output.leef_extra must be a list of key/value pairs")
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.to_owned(),
+                        v.as_str()
+                            .expect("output.leef_extra values must be strings")
+                            .to_owned(),
+                    )
+                })
+                .collect(),
+        };
+        LeefEncoder {
+            version,
+            delimiter,
+            device_vendor,
+            device_product,
+            device_version,
+            extra,
+        }
+    }
+}
+
+fn escape_header(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\n', '\r'], " ")
+}
+
+/// Map a syslog severity to a LEEF severity between 1 and 10
+fn syslog_severity_to_leef(severity: u8) -> &'static str {
+    match severity {
+        0 | 1 => "10",
+        2 => "9",
+        3 => "7",
+        4 => "5",
+        5 => "3",
+        6 => "2",
+        _ => "1",
+    }
+}
+
+struct Attributes {
+    delimiter: char,
+    out: String,
+}
+
+impl Attributes {
+    /// Attribute values cannot be escaped, so delimiters and line breaks are replaced with
+    /// spaces, and keys are restricted to alphanumeric characters
+    fn insert(&mut self, key: &str, value: &str) {
+        if !self.out.is_empty() {
+            self.out.push(self.delimiter);
+        }
+        self.out.extend(
+            key.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }),
+        );
+        self.out.push('=');
+        let delimiter = self.delimiter;
+        self.out.extend(value.chars().map(|c| {
+            if c == delimiter || c == '\n' || c == '\r' {
+                ' '
+            } else {
+                c
+            }
+        }));
+    }
+}
+
+impl Encoder for LeefEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let mut device_vendor = self.device_vendor.as_str();
+        let mut device_product = self.device_product.as_str();
+        let mut device_version = self.device_version.as_str();
+        let mut attributes = Attributes {
+            delimiter: self.delimiter,
+            out: String::new(),
+        };
+
+        let ts_s = record.ts.floor();
+        let ts_ns = ((record.ts - ts_s) * 1e9).round().min(999_999_999.0) as u32;
+        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(ts_s as i64, ts_ns), Utc);
+        attributes.insert("devTime", &dt.format("%b %d %Y %H:%M:%S%.3f").to_string());
+        attributes.insert("devTimeFormat", DEV_TIME_FORMAT);
+        if let Some(severity) = record.severity {
+            attributes.insert("sev", syslog_severity_to_leef(severity));
+        }
+        attributes.insert("src", &record.hostname);
+        if let Some(ref msg) = record.msg {
+            attributes.insert("msg", msg);
+        }
+        if let Some(ref sd) = record.sd {
+            for (name, value) in &sd.pairs {
+                let name = name.strip_prefix('_').unwrap_or(name);
+                match (name, value) {
+                    ("device_vendor", SDValue::String(value)) => device_vendor = value,
+                    ("device_product", SDValue::String(value)) => device_product = value,
+                    ("device_version", SDValue::String(value)) => device_version = value,
+                    (name, SDValue::String(value)) => attributes.insert(name, value),
+                    (name, SDValue::Bool(value)) => attributes.insert(name, &value.to_string()),
+                    (name, SDValue::F64(value)) => attributes.insert(name, &value.to_string()),
+                    (name, SDValue::I64(value)) => attributes.insert(name, &value.to_string()),
+                    (name, SDValue::U64(value)) => attributes.insert(name, &value.to_string()),
+                    (name, SDValue::Null) => attributes.insert(name, ""),
+                }
+            }
+        }
+        for (name, value) in &self.extra {
+            attributes.insert(name, value);
+        }
+        let event_id = record.msgid.as_deref().unwrap_or(DEFAULT_EVENT_ID);
+        let mut res = format!(
+            "LEEF:{}|{}|{}|{}|{}|",
+            self.version,
+            escape_header(device_vendor),
+            escape_header(device_product),
+            escape_header(device_version),
+            escape_header(event_id)
+        );
+        if self.version == "2.0" {
+            if self.delimiter == DEFAULT_DELIMITER {
+                res.push_str("x09");
+            } else {
+                res.push(self.delimiter);
+            }
+            res.push('|');
+        }
+        res.push_str(&attributes.out);
+        Ok(res.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+
+    fn record() -> Record {
+        Record {
+            ts: 1_438_790_025.637,
+            hostname: "example.org".to_owned(),
+            facility: None,
+            severity: Some(3),
+            appname: None,
+            procid: None,
+            msgid: Some("100".to_owned()),
+            msg: Some("Blocked\tconnection".to_owned()),
+            full_msg: None,
+            sd: Some(StructuredData {
+                sd_id: None,
+                pairs: vec![
+                    (
+                        "_device_vendor".to_owned(),
+                        SDValue::String("Acme".to_owned()),
+                    ),
+                    ("_dst".to_owned(), SDValue::String("10.0.0.5".to_owned())),
+                    ("_dpt".to_owned(), SDValue::U64(443)),
+                ],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_leef_encode() {
+        let config = Config::from_string("[output]\nleef_version = \"1.0\"").unwrap();
+        let res = LeefEncoder::new(&config).encode(record()).unwrap();
+        let expected = format!(
+            "LEEF:1.0|Acme|flowgger|{}|100|devTime=Aug 05 2015 15:53:45.637\tdevTimeFormat=MMM \
+             dd yyyy HH:mm:ss.SSS\tsev=7\tsrc=example.org\tmsg=Blocked \
+             connection\tdst=10.0.0.5\tdpt=443",
+            DEFAULT_DEVICE_VERSION
+        );
+        assert_eq!(String::from_utf8(res).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_leef2_encode_delimiter() {
+        let config = Config::from_string(
+            "[output]\nleef_delimiter = \"^\"\n[output.leef_extra]\ncat = \"firewall\"",
+        )
+        .unwrap();
+        let res = LeefEncoder::new(&config).encode(record()).unwrap();
+        let res = String::from_utf8(res).unwrap();
+        assert!(res.starts_with("LEEF:2.0|Acme|flowgger|"));
+        assert!(res.contains("|100|^|devTime=Aug 05 2015 15:53:45.637^"));
+        assert!(res.ends_with("^msg=Blocked\tconnection^dst=10.0.0.5^dpt=443^cat=firewall"));
+    }
+
+    #[test]
+    #[should_panic(expected = "output.leef_delimiter must be a single character")]
+    fn test_leef_encode_invalid_delimiter() {
+        let config = Config::from_string("[output]\nleef_delimiter = \"^^\"").unwrap();
+        LeefEncoder::new(&config);
+    }
+}
@@ -0,0 +1,214 @@
+use super::Encoder;
+use crate::flowgger::config::Config;
+use crate::flowgger::record::{Record, SDValue};
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+
+const SEVERITY_NAMES: [&str; 8] = [
+    "emerg", "alert", "crit", "error", "warn", "notice", "info", "debug",
+];
+
+#[derive(Clone)]
+pub struct LogfmtEncoder {
+    extra: Vec<(String, String)>,
+}
+
+impl LogfmtEncoder {
+    pub fn new(config: &Config) -> LogfmtEncoder {
+        let extra = match config.lookup("output.logfmt_extra") {
+            None => Vec::new(),
+            Some(extra) => extra
+                .as_table()
+                .expect("output.logfmt_extra must be a list of key/value pairs")
+                .iter()
+                .map(|(k, v)| {
+                    (
+                        k.to_owned(),
+                        v.as_str()
+                            .expect("output.logfmt_extra values must be strings")
+                            .to_owned(),
+                    )
+                })
+                .collect(),
+        };
+        LogfmtEncoder { extra }
+    }
+}
+
+struct LogfmtString {
+    out: String,
+}
+
+impl LogfmtString {
+    pub fn new() -> LogfmtString {
+        LogfmtString { out: String::new() }
+    }
+
+    fn insert_key(&mut self, key: &str) {
+        if !self.out.is_empty() {
+            self.out.push(' ');
+        }
+        if key
+            .chars()
+            .any(|c| c == '=' || c == '"' || c.is_whitespace() || c.is_control())
+        {
+            let key_esc: String = key
+                .chars()
+                .map(|c| match c {
+                    '=' | '"' => '_',
+                    c if c.is_whitespace() || c.is_control() => '_',
+                    c => c,
+                })
+                .collect();
+            self.out.push_str(&key_esc);
+        } else {
+            self.out.push_str(key);
+        }
+    }
+
+    /// Insert a pair, quoting the value if it is empty or contains spaces, quotes, `=` or
+    /// control characters
+    pub fn insert(&mut self, key: &str, value: &str) {
+        self.insert_key(key);
+        self.out.push('=');
+        if !value.is_empty()
+            && !value
+                .chars()
+                .any(|c| c == '=' || c == '"' || c == '\\' || c == ' ' || c.is_control())
+        {
+            self.out.push_str(value);
+            return;
+        }
+        self.out.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\t' => self.out.push_str("\\t"),
+                '\r' => self.out.push_str("\\r"),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    pub fn finalize(self) -> String {
+        self.out
+    }
+}
+
+impl Encoder for LogfmtEncoder {
+    fn encode(&self, record: Record) -> Result<Vec<u8>, &'static str> {
+        let mut res = LogfmtString::new();
+        let ts_s = record.ts.floor();
+        let ts_ns = ((record.ts - ts_s) * 1e9).round().min(999_999_999.0) as u32;
+        let dt = DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(ts_s as i64, ts_ns), Utc);
+        res.insert("time", &dt.to_rfc3339_opts(SecondsFormat::AutoSi, true));
+        if let Some(severity) = record.severity {
+            match SEVERITY_NAMES.get(severity as usize) {
+                Some(name) => res.insert("level", name),
+                None => res.insert("level", &severity.to_string()),
+            }
+        }
+        res.insert("host", &record.hostname);
+        if let Some(msg) = record.msg {
+            res.insert("msg", &msg);
+        }
+        if let Some(full_msg) = record.full_msg {
+            res.insert("full_message", &full_msg);
+        }
+        if let Some(facility) = record.facility {
+            res.insert("facility", &format!("{}", facility));
+        }
+        if let Some(appname) = record.appname {
+            res.insert("appname", &appname);
+        }
+        if let Some(procid) = record.procid {
+            res.insert("procid", &procid);
+        }
+        if let Some(msgid) = record.msgid {
+            res.insert("msgid", &msgid);
+        }
+        if let Some(sd) = record.sd {
+            for (name, value) in &sd.pairs {
+                let name = name.strip_prefix('_').unwrap_or(name);
+                match *value {
+                    SDValue::String(ref value) => res.insert(name, value),
+                    SDValue::Bool(ref value) => res.insert(name, &value.to_string()),
+                    SDValue::F64(ref value) => res.insert(name, &value.to_string()),
+                    SDValue::I64(ref value) => res.insert(name, &value.to_string()),
+                    SDValue::U64(ref value) => res.insert(name, &value.to_string()),
+                    SDValue::Null => res.insert(name, ""),
+                }
+            }
+        }
+        for (name, value) in &self.extra {
+            let name = name.strip_prefix('_').unwrap_or(name);
+            res.insert(name, value);
+        }
+        Ok(res.finalize().into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flowgger::record::StructuredData;
+
+    #[test]
+    fn test_logfmt_encode() {
+        let expected_msg = r#"time=2015-08-05T15:53:45.500Z level=warn host=example.org msg="A short \"message\"" appname=app path="/a b" empty="" count=42 ok=true x-header1=x"#;
+        let config = Config::from_string("[output.logfmt_extra]\nx-header1 = \"x\"").unwrap();
+        let record = Record {
+            ts: 1_438_790_025.5,
+            hostname: "example.org".to_string(),
+            facility: None,
+            severity: Some(4),
+            appname: Some("app".to_string()),
+            procid: None,
+            msgid: None,
+            msg: Some("A short \"message\"".to_string()),
+            full_msg: None,
+            sd: Some(StructuredData {
+                sd_id: None,
+                pairs: vec![
+                    ("_path".to_string(), SDValue::String("/a b".to_string())),
+                    ("_empty".to_string(), SDValue::String("".to_string())),
+                    ("_count".to_string(), SDValue::U64(42)),
+                    ("_ok".to_string(), SDValue::Bool(true)),
+                ],
+            }),
+        };
+        let encoder = LogfmtEncoder::new(&config);
+        assert_eq!(
+            String::from_utf8_lossy(&encoder.encode(record).unwrap()),
+            expected_msg
+        );
+    }
+
+    #[test]
+    fn test_logfmt_encode_escaping() {
+        let expected_msg = r#"time=2015-08-05T15:53:45Z host=h msg="line1\nline2\\" a_b_c=1"#;
+        let config = Config::from_string("").unwrap();
+        let record = Record {
+            ts: 1_438_790_025.0,
+            hostname: "h".to_string(),
+            facility: None,
+            severity: None,
+            appname: None,
+            procid: None,
+            msgid: None,
+            msg: Some("line1\nline2\\".to_string()),
+            full_msg: None,
+            sd: Some(StructuredData {
+                sd_id: None,
+                pairs: vec![("a b=c".to_string(), SDValue::I64(1))],
+            }),
+        };
+        let encoder = LogfmtEncoder::new(&config);
+        assert_eq!(
+            String::from_utf8_lossy(&encoder.encode(record).unwrap()),
+            expected_msg
+        );
+    }
+}
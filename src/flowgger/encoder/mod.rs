@@ -1,9 +1,15 @@
 #[cfg(feature = "capnp-recompile")]
 mod capnp_encoder;
+#[cfg(feature = "cef")]
+mod cef_encoder;
 #[cfg(feature = "gelf")]
 mod gelf_encoder;
 #[cfg(feature = "json")]
 mod json_encoder;
+#[cfg(feature = "leef")]
+mod leef_encoder;
+#[cfg(feature = "logfmt")]
+mod logfmt_encoder;
 #[cfg(feature = "ltsv")]
 mod ltsv_encoder;
 #[cfg(feature = "rfc3164")]
@@ -13,10 +19,16 @@ mod rfc5424_encoder;
 
 #[cfg(feature = "capnp-recompile")]
 pub use self::capnp_encoder::CapnpEncoder;
+#[cfg(feature = "cef")]
+pub use self::cef_encoder::CefEncoder;
 #[cfg(feature = "gelf")]
 pub use self::gelf_encoder::GelfEncoder;
 #[cfg(feature = "json")]
 pub use self::json_encoder::JsonEncoder;
+#[cfg(feature = "leef")]
+pub use self::leef_encoder::LeefEncoder;
+#[cfg(feature = "logfmt")]
+pub use self::logfmt_encoder::LogfmtEncoder;
 #[cfg(feature = "ltsv")]
 pub use self::ltsv_encoder::LTSVEncoder;
 #[cfg(feature = "rfc3164")]
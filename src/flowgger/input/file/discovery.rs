@@ -1,20 +1,28 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, Receiver, SyncSender};
+use std::sync::mpsc::{channel, sync_channel, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
 
 use glob::{glob, Pattern};
+use log::{debug, error, info};
 
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::file::registry::Registry;
 use crate::flowgger::input::file::worker::FileWorker;
+use crate::flowgger::input::file::{FileConfig, StartPosition};
 
 pub struct FileDiscovery {
     watcher: RecommendedWatcher,
     event_rx: Receiver<DebouncedEvent>,
-    path_match: Pattern,
+    path_matches: Vec<Pattern>,
+    config: FileConfig,
+    registry: Registry,
+    followed: Arc<Mutex<HashMap<PathBuf, SyncSender<()>>>>,
     log_tx: SyncSender<Vec<u8>>,
     decoder: Box<dyn Decoder + Send>,
     encoder: Box<dyn Encoder + Send>,
@@ -22,7 +30,8 @@ pub struct FileDiscovery {
 
 impl FileDiscovery {
     pub fn new(
-        path_match: &str,
+        config: FileConfig,
+        registry: Registry,
         log_tx: SyncSender<Vec<u8>>,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
@@ -34,38 +43,69 @@ impl FileDiscovery {
         FileDiscovery {
             watcher,
             event_rx: rx,
-            path_match: Pattern::new(path_match).expect("Wrong input.src"),
+            path_matches: config
+                .src
+                .iter()
+                .map(|src| Pattern::new(src).expect("Wrong input.src"))
+                .collect(),
+            config,
+            registry,
+            followed: Arc::new(Mutex::new(HashMap::new())),
             log_tx,
             decoder,
             encoder,
         }
     }
 
+    /// A single watcher reports the changes in the directories of the files to follow.
+    /// Workers are woken up when the file they follow changes, and started for new
+    /// files.
     pub fn run(&mut self) {
-        let path = self.path_match.clone();
-        self.add_initial_watches(PathBuf::from(path.as_str()));
+        for path_match in self.path_matches.clone() {
+            self.add_initial_watches(PathBuf::from(path_match.as_str()));
+        }
         self.start_initial_workers();
 
         loop {
             match self.event_rx.recv() {
                 Ok(event) => match event {
                     DebouncedEvent::Create(event_path) => {
-                        if event_path.metadata().unwrap().is_dir() {
-                            if should_be_watched(&self.path_match, &event_path) {
+                        if event_path.is_dir() {
+                            if self
+                                .path_matches
+                                .iter()
+                                .any(|path_match| should_be_watched(path_match, &event_path))
+                            {
                                 self.add_directory_watch(&event_path)
                             }
-                        } else if self.path_match.matches_path(&event_path) {
-                            self.start_worker(&event_path, false);
+                        } else {
+                            self.file_changed(&event_path);
                         }
                     }
-                    DebouncedEvent::NoticeWrite(event_path) => {
-                        if self.path_match.matches_path(&event_path) {
-                            self.start_worker(&event_path, false);
+                    DebouncedEvent::NoticeWrite(event_path)
+                    | DebouncedEvent::Write(event_path)
+                    | DebouncedEvent::Chmod(event_path) => self.file_changed(&event_path),
+                    DebouncedEvent::NoticeRemove(event_path)
+                    | DebouncedEvent::Remove(event_path) => {
+                        self.wake_up(&event_path);
+                    }
+                    DebouncedEvent::Rename(from, to) => {
+                        self.wake_up(&from);
+                        self.file_changed(&to);
+                    }
+                    DebouncedEvent::Rescan => {
+                        for wakeup in self.followed.lock().unwrap().values() {
+                            let _ = wakeup.try_send(());
                         }
                     }
-                    _ => println!("Unknown DebouncedEvent {:?}", event),
+                    DebouncedEvent::Error(e, path) => {
+                        error!("File system watcher error on {:?}: {}", path, e)
+                    }
                 },
-                Err(e) => println!("Error receiving event: {}", e),
+                Err(e) => {
+                    error!("Error receiving event: {}", e);
+                    return;
+                }
             }
         }
     }
@@ -87,35 +127,128 @@ impl FileDiscovery {
     }
 
     fn start_initial_workers(&self) {
-        for entry in glob(self.path_match.as_str()).expect("Failed to read glob pattern") {
-            match entry {
-                Ok(path) => {
-                    println!("Adding {} to watch list.", path.clone().into_os_string().into_string().unwrap());
-                    self.start_worker(&path, false);
-                }
-                Err(e) => panic!("Failed to read glob entry: {}", e),
-            };
+        let from_tail = self.config.start_position == StartPosition::End;
+        for path_match in &self.path_matches {
+            for entry in glob(path_match.as_str()).expect("Failed to read glob pattern") {
+                match entry {
+                    Ok(path) => {
+                        if !self.should_follow(&path) {
+                            continue;
+                        }
+                        info!("Adding {} to watch list", path.display());
+                        self.start_worker(&path, from_tail);
+                    }
+                    Err(e) => panic!("Failed to read glob entry: {}", e),
+                };
+            }
         }
     }
 
+    fn should_follow(&self, path: &Path) -> bool {
+        self.path_matches
+            .iter()
+            .any(|path_match| path_match.matches_path(path))
+            && should_follow(&self.config, path)
+    }
+
     fn add_directory_watch(&mut self, path: &Path) {
         self.watcher
             .watch(path, RecursiveMode::NonRecursive)
             .unwrap();
     }
 
+    /// Wake up the worker following a file that has changed, or start one
+    fn file_changed(&self, path: &Path) {
+        if !self.wake_up(path) && self.should_follow(path) {
+            self.start_worker(path, false);
+        }
+    }
+
+    /// Wake up the worker following `path`, if there is one
+    fn wake_up(&self, path: &Path) -> bool {
+        match self.followed.lock().unwrap().get(path) {
+            Some(wakeup) => {
+                debug!("{} changed", path.display());
+                // A pending wake up already makes the worker read everything
+                let _ = wakeup.try_send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Start following a file, unless a worker is already following it. A worker keeps
+    /// following a path after the file has been rotated.
     fn start_worker(&self, path: &Path, from_tail: bool) {
+        let (wakeup_tx, wakeup_rx) = sync_channel(1);
+        {
+            let mut followed = self.followed.lock().unwrap();
+            if followed.contains_key(path) {
+                return;
+            }
+            followed.insert(path.to_owned(), wakeup_tx);
+        }
+        let followed = self.followed.clone();
         let p = path.to_owned().clone();
+        let c = self.config.clone();
+        let r = self.registry.clone();
         let t = self.log_tx.clone();
         let d: Box<dyn Decoder + Send> = self.decoder.clone_boxed();
         let e: Box<dyn Encoder + Send> = self.encoder.clone_boxed();
         thread::spawn(move || {
-            let mut worker = FileWorker::new(&p, t, d, e);
-            worker.run(from_tail);
+            let (mut from_tail, mut wakeup_rx) = (from_tail, wakeup_rx);
+            loop {
+                let (d, e) = (d.clone_boxed(), e.clone_boxed());
+                let mut worker = FileWorker::new(&p, c.clone(), r.clone(), t.clone(), d, e);
+                let state = worker.run(from_tail, &wakeup_rx);
+                let mut followed = followed.lock().unwrap();
+                followed.remove(&p);
+                // Changes made while an inactive file was being closed were reported to
+                // its worker, and aren't reported again. They are read by a new worker.
+                let changed = match (state, p.metadata()) {
+                    (Some(state), Ok(metadata)) => {
+                        !state.is_same_file(&metadata) || metadata.len() > state.offset
+                    }
+                    _ => false,
+                };
+                if !changed || !should_follow(&c, &p) {
+                    break;
+                }
+                let (wakeup_tx, rx) = sync_channel(1);
+                followed.insert(p.clone(), wakeup_tx);
+                from_tail = false;
+                wakeup_rx = rx;
+            }
         });
     }
 }
 
+/// Whether a file matching `input.src` is neither excluded nor older than
+/// `input.ignore_older_than`
+fn should_follow(config: &FileConfig, path: &Path) -> bool {
+    let file_name = path.file_name().and_then(|x| x.to_str()).unwrap_or("");
+    if config
+        .exclude
+        .iter()
+        .any(|pattern| pattern.matches(file_name) || pattern.matches_path(path))
+    {
+        return false;
+    }
+    let metadata = match path.metadata() {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    if !metadata.is_file() {
+        return false;
+    }
+    match (config.ignore_older_than, metadata.modified()) {
+        (Some(max_age), Ok(modified)) => SystemTime::now()
+            .duration_since(modified)
+            .map_or(true, |age| age <= max_age),
+        _ => true,
+    }
+}
+
 fn should_be_watched(match_path: &Pattern, path: &Path) -> bool {
     if match_path.matches_path(path) {
         true
@@ -183,3 +316,45 @@ fn test_should_be_watched() {
         assert_eq!(data.result, should_be_watched(&data.match_path, &data.path));
     }
 }
+
+#[test]
+fn test_should_follow() {
+    use crate::flowgger::config::Config;
+    use crate::flowgger::input::file::FileInput;
+    use std::fs::{self, File};
+    use tempdir::TempDir;
+
+    let dir = TempDir::new("flowgger").unwrap();
+    let config = Config::from_string(&format!(
+        "[input]\nsrc = [\"{0}/*.log\", \"{0}/*.log.*\"]\nexclude = [\"*.1\", \"*.gz\"]\n\
+         ignore_older_than = 3600\nstart_position = \"end\"",
+        dir.path().display()
+    ))
+    .unwrap();
+    let config = FileInput::new(&config).file_config;
+    assert_eq!(config.src.len(), 2);
+    assert_eq!(config.start_position, StartPosition::End);
+
+    for name in &[
+        "app.log",
+        "app.log.1",
+        "app.log.2.gz",
+        "app.log.2",
+        "old.log",
+    ] {
+        fs::write(dir.path().join(name), "line\n").unwrap();
+    }
+    File::options()
+        .write(true)
+        .open(dir.path().join("old.log"))
+        .unwrap()
+        .set_modified(SystemTime::now() - Duration::from_secs(7200))
+        .unwrap();
+    assert!(should_follow(&config, &dir.path().join("app.log")));
+    assert!(should_follow(&config, &dir.path().join("app.log.2")));
+    assert!(!should_follow(&config, &dir.path().join("app.log.1")));
+    assert!(!should_follow(&config, &dir.path().join("app.log.2.gz")));
+    assert!(!should_follow(&config, &dir.path().join("old.log")));
+    assert!(!should_follow(&config, &dir.path().join("missing.log")));
+    assert!(!should_follow(&config, dir.path()));
+}
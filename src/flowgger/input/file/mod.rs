@@ -1,17 +1,43 @@
 mod discovery;
+mod registry;
 mod worker;
 use self::discovery::FileDiscovery;
+use self::registry::Registry;
 
+use std::path::PathBuf;
 use std::sync::mpsc::SyncSender;
+use std::time::Duration;
+
+use glob::Pattern;
 
 use super::Input;
 use crate::flowgger::config::Config;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::splitter::MultilineConfig;
+
+const DEFAULT_MAX_LINE_LENGTH: usize = 1_048_576;
+const DEFAULT_REGISTRY_INTERVAL: u64 = 5;
+const DEFAULT_ROTATE_WAIT: u64 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StartPosition {
+    Beginning,
+    End,
+}
 
 #[derive(Clone)]
 pub struct FileConfig {
-    src: String,
+    src: Vec<String>,
+    exclude: Vec<Pattern>,
+    ignore_older_than: Option<Duration>,
+    start_position: StartPosition,
+    close_inactive: Option<Duration>,
+    max_line_length: usize,
+    multiline: Option<MultilineConfig>,
+    registry: Option<PathBuf>,
+    registry_interval: Duration,
+    rotate_wait: Duration,
 }
 
 pub struct FileInput {
@@ -20,23 +46,116 @@ pub struct FileInput {
 
 impl FileInput {
     pub fn new(config: &Config) -> FileInput {
-        let src_path = match config.lookup("input.src") {
-            None => panic!("Missing file path"),
-            Some(src) => src.as_str().expect("OK").to_owned(),
+        let src = string_list(config, "input.src").expect("Missing file path");
+        let exclude = string_list(config, "input.exclude")
+            .unwrap_or_default()
+            .iter()
+            .map(|x| Pattern::new(x).expect("Invalid pattern in input.exclude"))
+            .collect();
+        let ignore_older_than = config.lookup("input.ignore_older_than").map(|x| {
+            Duration::from_secs(
+                x.as_integer()
+                    .expect("input.ignore_older_than must be a number of seconds")
+                    as u64,
+            )
+        });
+        let start_position = match config
+            .lookup("input.start_position")
+            .map_or("beginning", |x| {
+                x.as_str().expect("input.start_position must be a string")
+            }) {
+            "beginning" => StartPosition::Beginning,
+            "end" => StartPosition::End,
+            _ => panic!("input.start_position must be beginning or end"),
+        };
+        let close_inactive = config.lookup("input.close_inactive").map(|x| {
+            Duration::from_secs(
+                x.as_integer()
+                    .expect("input.close_inactive must be a number of seconds")
+                    as u64,
+            )
+        });
+        let max_line_length =
+            config
+                .lookup("input.max_line_length")
+                .map_or(DEFAULT_MAX_LINE_LENGTH, |x| {
+                    x.as_integer()
+                        .expect("input.max_line_length must be a size integer")
+                        as usize
+                });
+        let multiline = MultilineConfig::new(config);
+        let registry = config.lookup("input.file_registry").map(|x| {
+            PathBuf::from(
+                x.as_str()
+                    .expect("input.file_registry must be a path to a file"),
+            )
+        });
+        let registry_interval =
+            config
+                .lookup("input.file_registry_interval")
+                .map_or(DEFAULT_REGISTRY_INTERVAL, |x| {
+                    x.as_integer()
+                        .expect("input.file_registry_interval must be a number of seconds")
+                        as u64
+                });
+        let rotate_wait =
+            config
+                .lookup("input.file_rotate_wait")
+                .map_or(DEFAULT_ROTATE_WAIT, |x| {
+                    x.as_integer()
+                        .expect("input.file_rotate_wait must be a number of seconds")
+                        as u64
+                });
+        let file_config = FileConfig {
+            src,
+            exclude,
+            ignore_older_than,
+            start_position,
+            close_inactive,
+            max_line_length,
+            multiline,
+            registry,
+            registry_interval: Duration::from_secs(registry_interval),
+            rotate_wait: Duration::from_secs(rotate_wait),
         };
-        let file_config = FileConfig { src: src_path };
         FileInput { file_config }
     }
 }
 
+/// A list of strings, that can also be given as a single string
+fn string_list(config: &Config, key: &str) -> Option<Vec<String>> {
+    let value = config.lookup(key)?;
+    if let Some(value) = value.as_str() {
+        return Some(vec![value.to_owned()]);
+    }
+    let values = value
+        .as_array()
+        .unwrap_or_else(|| panic!("{} must be a string or a list of strings", key));
+    Some(
+        values
+            .iter()
+            .map(|x| {
+                x.as_str()
+                    .unwrap_or_else(|| panic!("{} must be a list of strings", key))
+                    .to_owned()
+            })
+            .collect(),
+    )
+}
+
 impl Input for FileInput {
+    /// With `input.file_registry`, SIGINT and SIGTERM are handled by saving the registry
+    /// before exiting, instead of terminating the process right away
     fn accept(
         &self,
         tx: SyncSender<Vec<u8>>,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
-        let mut discovery = FileDiscovery::new(&self.file_config.src, tx, decoder, encoder);
+        let registry = Registry::new(self.file_config.registry.clone());
+        registry.start_checkpoints(self.file_config.registry_interval);
+        let mut discovery =
+            FileDiscovery::new(self.file_config.clone(), registry, tx, decoder, encoder);
         discovery.run();
     }
 }
@@ -0,0 +1,531 @@
+use crate::flowgger::utils::delivery;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::{self, File, Metadata};
+use std::io::{self, stderr, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileExt, MetadataExt};
+
+/// Number of bytes at the beginning of a file used to recognize it
+pub const FINGERPRINT_SIZE: u64 = 1024;
+
+/// Offsets waiting for their records to be delivered, per file. Intermediate offsets
+/// are dropped past this, so that an output that never acknowledges a record doesn't
+/// make them pile up.
+const MAX_PENDING_CHECKPOINTS: usize = 1024;
+
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// What is known about a followed file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileState {
+    pub dev: u64,
+    pub ino: u64,
+    pub offset: u64,
+    pub fingerprint_len: u64,
+    pub fingerprint: u64,
+}
+
+impl FileState {
+    /// The identity of an open file, with an offset of 0
+    pub fn of(file: &File) -> io::Result<FileState> {
+        let (dev, ino) = file_id(&file.metadata()?);
+        let mut state = FileState {
+            dev,
+            ino,
+            ..FileState::default()
+        };
+        state.update_fingerprint(file)?;
+        Ok(state)
+    }
+
+    /// Whether `metadata` describes the file this state is about
+    pub fn is_same_file(&self, metadata: &Metadata) -> bool {
+        file_id(metadata) == (self.dev, self.ino)
+    }
+
+    /// Extend the fingerprint while the file is shorter than `FINGERPRINT_SIZE`
+    pub fn update_fingerprint(&mut self, file: &File) -> io::Result<()> {
+        if self.fingerprint_len < FINGERPRINT_SIZE {
+            let (len, fingerprint) = fingerprint(file, FINGERPRINT_SIZE)?;
+            self.fingerprint_len = len;
+            self.fingerprint = fingerprint;
+        }
+        Ok(())
+    }
+
+    /// Whether `file` is the file this state was saved for
+    pub fn matches(&self, file: &File) -> bool {
+        match file.metadata() {
+            Ok(ref metadata) if self.is_same_file(metadata) => self.fingerprint_matches(file),
+            _ => false,
+        }
+    }
+
+    /// Whether the first bytes of `file` are still the ones it was recognized by
+    pub fn fingerprint_matches(&self, file: &File) -> bool {
+        match fingerprint(file, self.fingerprint_len) {
+            Ok((len, fingerprint)) => {
+                len == self.fingerprint_len && fingerprint == self.fingerprint
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> (u64, u64) {
+    (metadata.dev(), metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn file_id(_metadata: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+/// FNV-1a hash of the first `max_len` bytes of a file, that has to be stable across
+/// versions
+fn fingerprint(file: &File, max_len: u64) -> io::Result<(u64, u64)> {
+    let mut buf = vec![0; max_len as usize];
+    let mut len = 0;
+    while len < buf.len() {
+        match read_at(file, &mut buf[len..], len as u64)? {
+            0 => break,
+            read => len += read,
+        }
+    }
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &byte in &buf[..len] {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    Ok((len as u64, hash))
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.read_at(buf, offset)
+}
+
+#[cfg(not(unix))]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::io::{Read, Seek, SeekFrom};
+    let mut file = file.try_clone()?;
+    let position = file.seek(SeekFrom::Current(0))?;
+    file.seek(SeekFrom::Start(offset))?;
+    let res = file.read(buf);
+    file.seek(SeekFrom::Start(position))?;
+    res
+}
+
+/// Read offsets of the followed files, so that files closed after `input.close_inactive`
+/// are read again from where they were left. The offsets up to which records have been
+/// delivered by the output are persisted in the `input.file_registry` file, so that a
+/// restart resumes where the previous instance stopped, without losing the records
+/// that were still queued.
+#[derive(Clone)]
+pub struct Registry {
+    path: Option<PathBuf>,
+    states: Arc<Mutex<HashMap<PathBuf, FileState>>>,
+    delivered: Arc<Mutex<HashMap<PathBuf, FileState>>>,
+}
+
+impl Registry {
+    pub fn new(path: Option<PathBuf>) -> Registry {
+        let states = match path {
+            Some(ref path) => match load(path) {
+                Ok(states) => states,
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+                Err(e) => panic!(
+                    "Unable to read the file registry [{}]: {}",
+                    path.display(),
+                    e
+                ),
+            },
+            None => HashMap::new(),
+        };
+        Registry {
+            path,
+            delivered: Arc::new(Mutex::new(states.clone())),
+            states: Arc::new(Mutex::new(states)),
+        }
+    }
+
+    /// Whether the offsets are saved to a file
+    pub fn is_persistent(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// The offset to resume reading `file` from, if it was already being followed at
+    /// `path`, or at another path it has been renamed from
+    pub fn resume_offset(&self, path: &Path, file: &File) -> Option<u64> {
+        let metadata = file.metadata().ok()?;
+        let states = self.states.lock().unwrap();
+        let is_resumable =
+            |state: &FileState| metadata.len() >= state.offset && state.matches(file);
+        match states.get(path) {
+            Some(state) if is_resumable(state) => Some(state.offset),
+            _ => states
+                .values()
+                .filter(|state| is_resumable(state))
+                .map(|state| state.offset)
+                .max(),
+        }
+    }
+
+    /// Whether `file` has already been read entirely, whatever its path was then. This
+    /// recognizes compressed files renamed by the following rotations.
+    pub fn is_read(&self, file: &File) -> bool {
+        let metadata = match file.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => return false,
+        };
+        self.states.lock().unwrap().values().any(|state| {
+            state.is_same_file(&metadata)
+                && state.offset >= metadata.len()
+                && state.fingerprint_matches(file)
+        })
+    }
+
+    /// Record the offset the file has been read up to
+    pub fn update(&self, path: &Path, state: &FileState) {
+        self.states
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), state.clone());
+    }
+
+    /// Record the offset up to which the records of the file have been delivered. This
+    /// is the offset that gets saved.
+    pub fn checkpoint(&self, path: &Path, state: &FileState) {
+        self.delivered
+            .lock()
+            .unwrap()
+            .insert(path.to_owned(), state.clone());
+    }
+
+    /// Atomically replace the registry file with the delivered offsets. Files that
+    /// don't exist any more, such as old rotated logs, are forgotten.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        self.states
+            .lock()
+            .unwrap()
+            .retain(|file_path, _| file_path.exists());
+        let mut delivered = self.delivered.lock().unwrap();
+        delivered.retain(|file_path, _| file_path.exists());
+        let mut content = String::new();
+        for (file_path, state) in delivered.iter() {
+            let file_path = match file_path.to_str() {
+                Some(file_path) if !file_path.contains('\n') => file_path,
+                _ => continue,
+            };
+            content.push_str(&format!(
+                "{} {} {} {} {:016x} {}\n",
+                state.dev,
+                state.ino,
+                state.offset,
+                state.fingerprint_len,
+                state.fingerprint,
+                file_path
+            ));
+        }
+        drop(delivered);
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        {
+            let mut tmp_file = File::create(&tmp_path)?;
+            tmp_file.write_all(content.as_bytes())?;
+            tmp_file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Save the registry every `interval`, as well as when the process is asked to
+    /// terminate with SIGINT or SIGTERM. This replaces the default handlers of these
+    /// signals for the whole process: the registry is saved, and the process exits.
+    /// Records that were queued, but not delivered yet, are read again after a restart.
+    pub fn start_checkpoints(&self, interval: Duration) {
+        if self.path.is_none() {
+            return;
+        }
+        handle_shutdown_signals();
+        let registry = self.clone();
+        thread::spawn(move || {
+            let mut last_save = Instant::now();
+            loop {
+                thread::sleep(Duration::from_millis(100));
+                let shutdown = SHUTDOWN.load(Ordering::SeqCst);
+                if !shutdown && last_save.elapsed() < interval {
+                    continue;
+                }
+                if let Err(e) = registry.save() {
+                    let _ = writeln!(stderr(), "Unable to save the file registry: {}", e);
+                }
+                if shutdown {
+                    std::process::exit(0);
+                }
+                last_save = Instant::now();
+            }
+        });
+    }
+}
+
+/// Save the offsets of a file in the registry once the records read before them have
+/// been delivered. Records are acknowledged through `utils::delivery` when the registry
+/// is persistent, and right away otherwise.
+#[derive(Clone)]
+pub struct Checkpoints(Arc<CheckpointsInner>);
+
+struct CheckpointsInner {
+    registry: Registry,
+    path: PathBuf,
+    state: Mutex<CheckpointsState>,
+}
+
+#[derive(Default)]
+struct CheckpointsState {
+    /// Sequence number of the next queued record
+    next_seq: u64,
+    /// Every record before this one has been delivered
+    delivered_until: u64,
+    /// Records delivered before some of the records that precede them
+    delivered_ahead: BTreeSet<u64>,
+    /// Offsets to save once the records before the sequence number have been delivered
+    pending: VecDeque<(u64, FileState)>,
+}
+
+impl Checkpoints {
+    pub fn new(registry: &Registry, path: &Path) -> Checkpoints {
+        Checkpoints(Arc::new(CheckpointsInner {
+            registry: registry.clone(),
+            path: path.to_owned(),
+            state: Mutex::new(CheckpointsState::default()),
+        }))
+    }
+
+    /// Called before a record is queued
+    pub fn expect(&self, record: &[u8]) {
+        if !self.0.registry.is_persistent() {
+            return;
+        }
+        let seq = {
+            let mut state = self.0.state.lock().unwrap();
+            state.next_seq += 1;
+            state.next_seq - 1
+        };
+        let checkpoints = self.clone();
+        delivery::expect(record, move || checkpoints.delivered(seq));
+    }
+
+    /// Save `file_state` once the records queued so far have been delivered
+    pub fn add(&self, file_state: FileState) {
+        let mut state = self.0.state.lock().unwrap();
+        let seq = state.next_seq;
+        let full = state.pending.len() >= MAX_PENDING_CHECKPOINTS;
+        match state.pending.back_mut() {
+            Some(last) if last.0 == seq || full => *last = (seq, file_state),
+            _ => state.pending.push_back((seq, file_state)),
+        }
+        self.commit(&mut state);
+    }
+
+    fn delivered(&self, seq: u64) {
+        let mut state = self.0.state.lock().unwrap();
+        if seq != state.delivered_until {
+            state.delivered_ahead.insert(seq);
+            return;
+        }
+        state.delivered_until += 1;
+        loop {
+            let next = state.delivered_until;
+            if !state.delivered_ahead.remove(&next) {
+                break;
+            }
+            state.delivered_until += 1;
+        }
+        self.commit(&mut state);
+    }
+
+    fn commit(&self, state: &mut CheckpointsState) {
+        let mut last = None;
+        while let Some(&(seq, _)) = state.pending.front() {
+            if seq > state.delivered_until {
+                break;
+            }
+            last = state.pending.pop_front();
+        }
+        if let Some((_, file_state)) = last {
+            self.0.registry.checkpoint(&self.0.path, &file_state);
+        }
+    }
+}
+
+fn load(path: &Path) -> io::Result<HashMap<PathBuf, FileState>> {
+    let mut states = HashMap::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let parts: Vec<&str> = line.splitn(6, ' ').collect();
+        if parts.len() != 6 {
+            continue;
+        }
+        let state = FileState {
+            dev: parts[0].parse().unwrap_or(0),
+            ino: parts[1].parse().unwrap_or(0),
+            offset: parts[2].parse().unwrap_or(0),
+            fingerprint_len: parts[3].parse().unwrap_or(0),
+            fingerprint: u64::from_str_radix(parts[4], 16).unwrap_or(0),
+        };
+        states.insert(PathBuf::from(parts[5]), state);
+    }
+    Ok(states)
+}
+
+#[cfg(unix)]
+extern "C" fn on_shutdown_signal(_: libc::c_int) {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+fn handle_shutdown_signals() {
+    let handler = on_shutdown_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(not(unix))]
+fn handle_shutdown_signals() {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_registry_resume() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let log_path = dir.path().join("test.log");
+        let registry_path = dir.path().join("registry");
+        fs::write(&log_path, "first line\nsecond line\n").unwrap();
+
+        let registry = Registry::new(Some(registry_path.clone()));
+        let file = File::open(&log_path).unwrap();
+        assert_eq!(registry.resume_offset(&log_path, &file), None);
+        let mut state = FileState::of(&file).unwrap();
+        assert_eq!(state.fingerprint_len, 23);
+        // Only the offset of the delivered records is saved
+        state.offset = 23;
+        registry.update(&log_path, &state);
+        state.offset = 11;
+        registry.checkpoint(&log_path, &state);
+        registry.save().unwrap();
+
+        let registry = Registry::new(Some(registry_path.clone()));
+        assert_eq!(registry.resume_offset(&log_path, &file), Some(11));
+
+        // Appending to the file keeps it recognizable
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&log_path)
+            .unwrap()
+            .write_all(b"third line\n")
+            .unwrap();
+        assert_eq!(registry.resume_offset(&log_path, &file), Some(11));
+
+        // A file renamed while it wasn't followed is resumed at its new path
+        let rotated_path = dir.path().join("test.log.1");
+        fs::rename(&log_path, &rotated_path).unwrap();
+        assert_eq!(registry.resume_offset(&rotated_path, &file), Some(11));
+
+        // A different file at the same path starts from the beginning
+        fs::write(&log_path, "other content\n").unwrap();
+        let file = File::open(&log_path).unwrap();
+        assert_eq!(registry.resume_offset(&log_path, &file), None);
+    }
+
+    #[test]
+    fn test_registry_prune() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let registry_path = dir.path().join("registry");
+        let registry = Registry::new(Some(registry_path.clone()));
+        for name in &["test.log", "test.log.1"] {
+            let log_path = dir.path().join(name);
+            fs::write(&log_path, "line\n").unwrap();
+            let state = FileState::of(&File::open(&log_path).unwrap()).unwrap();
+            registry.update(&log_path, &state);
+            registry.checkpoint(&log_path, &state);
+        }
+        fs::remove_file(dir.path().join("test.log.1")).unwrap();
+        registry.save().unwrap();
+        let saved = fs::read_to_string(&registry_path).unwrap();
+        assert_eq!(saved.lines().count(), 1);
+        assert!(saved.ends_with("test.log\n"));
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let log_path = dir.path().join("test.log");
+        fs::write(&log_path, "first\nsecond\nthird\n").unwrap();
+        let file = File::open(&log_path).unwrap();
+        let registry = Registry::new(Some(dir.path().join("registry")));
+        let checkpoints = Checkpoints::new(&registry, &log_path);
+        let delivered = |registry: &Registry| {
+            registry
+                .delivered
+                .lock()
+                .unwrap()
+                .get(&log_path)
+                .map(|state| state.offset)
+        };
+        let mut state = FileState::of(&file).unwrap();
+        let mut receipts = Vec::new();
+        for (record, offset) in &[
+            ("test_checkpoints first", 6),
+            ("test_checkpoints second", 13),
+        ] {
+            checkpoints.expect(record.as_bytes());
+            state.offset = *offset;
+            checkpoints.add(state.clone());
+            receipts.push(delivery::receipt(record.as_bytes()).unwrap());
+        }
+        checkpoints.expect(b"test_checkpoints third");
+        state.offset = 19;
+        checkpoints.add(state.clone());
+        let third = delivery::receipt(b"test_checkpoints third").unwrap();
+        assert_eq!(delivered(&registry), None);
+
+        // Out of order deliveries are only saved once the previous records are delivered
+        receipts.pop().unwrap().delivered();
+        assert_eq!(delivered(&registry), None);
+        receipts.pop().unwrap().delivered();
+        assert_eq!(delivered(&registry), Some(13));
+        third.delivered();
+        assert_eq!(delivered(&registry), Some(19));
+    }
+
+    #[test]
+    fn test_registry_in_memory() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let log_path = dir.path().join("test.log");
+        fs::write(&log_path, "line\n").unwrap();
+        let file = File::open(&log_path).unwrap();
+        let registry = Registry::new(None);
+        let mut state = FileState::of(&file).unwrap();
+        state.offset = 5;
+        registry.update(&log_path, &state);
+        assert_eq!(registry.resume_offset(&log_path, &file), Some(5));
+        assert!(registry.save().is_ok());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
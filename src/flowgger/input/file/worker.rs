@@ -1,21 +1,53 @@
 use std;
-use std::fs::File;
-use std::io::{stderr, stdout};
-use std::io::{BufReader, SeekFrom};
+use std::fs::{self, File};
 use std::io::prelude::*;
+use std::io::stderr;
+use std::io::{BufReader, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{channel, SyncSender};
-use std::time::Duration;
+use std::str;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender};
+use std::time::{Duration, Instant};
 
-use notify::{RecursiveMode, Watcher};
+use flate2::read::MultiGzDecoder;
+use log::{debug, info, warn};
 
-use crate::flowgger::decoder::Decoder;
+use crate::flowgger::decoder::{Decoder, FrameSize};
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::input::file::registry::{Checkpoints, FileState, Registry};
+use crate::flowgger::input::file::FileConfig;
+use crate::flowgger::splitter::{Multiline, MultilineConfig};
+
+/// How often to look for writes to a file, including the ones the file system watcher
+/// doesn't report, such as writes to a rotated file
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const READ_BUFFER_SIZE: usize = 65_536;
 
-use super::super::super::notify::RecommendedWatcher;
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+/// Recognize compressed files by their magic bytes
+fn compression(path: &Path) -> Option<Compression> {
+    let mut magic = Vec::with_capacity(4);
+    File::open(path)
+        .ok()?
+        .take(4)
+        .read_to_end(&mut magic)
+        .ok()?;
+    match magic.as_slice() {
+        [0x1f, 0x8b, ..] => Some(Compression::Gzip),
+        [0x28, 0xb5, 0x2f, 0xfd] => Some(Compression::Zstd),
+        _ => None,
+    }
+}
 
 pub struct FileWorker {
     path: PathBuf,
+    config: FileConfig,
+    registry: Registry,
+    checkpoints: Checkpoints,
     tx: SyncSender<Vec<u8>>,
     decoder: Box<dyn Decoder + Send>,
     encoder: Box<dyn Encoder + Send>,
@@ -24,114 +56,998 @@ pub struct FileWorker {
 impl FileWorker {
     pub fn new(
         path: &Path,
+        config: FileConfig,
+        registry: Registry,
         tx: SyncSender<Vec<u8>>,
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) -> FileWorker {
         FileWorker {
             path: PathBuf::from(path),
+            config,
+            checkpoints: Checkpoints::new(&registry, path),
+            registry,
             tx,
             decoder,
             encoder,
         }
     }
 
-    pub fn run(&mut self, from_tail: bool) {
-        let (tx, rx) = channel();
-        let mut watcher: RecommendedWatcher = Watcher::new(tx, Duration::from_secs(2))
-            .expect("Cannot create file watcher");
-        watcher
-            .watch(&self.path, RecursiveMode::NonRecursive)
-            .unwrap();
+    /// Follow the file until it is removed or closed after `input.close_inactive`.
+    /// Records are read as soon as `wakeup` reports a change to the file, and at least
+    /// every `POLL_INTERVAL` for the changes the file system watcher can't report.
+    /// Returns the state of the file as it was last read if it has been closed for being
+    /// inactive.
+    pub fn run(&mut self, from_tail: bool, wakeup: &Receiver<()>) -> Option<FileState> {
+        if let Some(compression) = compression(&self.path) {
+            self.read_compressed(compression);
+            return None;
+        }
+        let fr = match FollowReader::new(
+            &self.path,
+            from_tail,
+            &self.registry,
+            self.config.rotate_wait,
+        ) {
+            Ok(fr) => fr,
+            Err(e) => {
+                warn!("Unable to open {}: {}", self.path.display(), e);
+                return None;
+            }
+        };
+        info!("Starting reader for {}", self.path.display());
+        let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, fr);
+        let mut buffer = ReadBuffer::default();
 
-        println!("Starting reader for {}", &self.path.to_str().unwrap());
-        stdout().flush().expect("Failed to flush stdout");
-        let fr = FollowReader::new(&self.path, from_tail);
-        let mut reader = BufReader::new(fr);
-        let mut buffer = Vec::new();
+        let (mut decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) =
+            (self.decoder.clone_boxed(), self.encoder.clone_boxed());
+        decoder.set_source(&self.path);
+        let mut multiline = self.config.multiline.as_ref().map(PendingMultiline::new);
+        let mut last_read = Instant::now();
+        let mut inactive = false;
+        loop {
+            let (file_id, offset) = (reader.get_ref().file_id(), reader.get_ref().state.offset);
+            if self.read_records(
+                &mut reader,
+                &mut buffer,
+                &*decoder,
+                &*encoder,
+                &mut multiline,
+            ) {
+                break;
+            }
+            if reader.get_ref().file_id() != file_id || reader.get_ref().state.offset != offset {
+                last_read = Instant::now();
+            }
+            if let Some(close_inactive) = self.config.close_inactive {
+                if last_read.elapsed() >= close_inactive {
+                    // The discovery starts a new worker, resuming from the saved offset,
+                    // once the file is written to again
+                    info!("Closing inactive file {}", self.path.display());
+                    inactive = true;
+                    break;
+                }
+            }
+
+            // Wake up in time to flush a pending multiline record
+            let timeout = match multiline.as_ref().and_then(PendingMultiline::time_left) {
+                Some(timeout) if timeout < POLL_INTERVAL => timeout,
+                _ => POLL_INTERVAL,
+            };
+            if let Err(RecvTimeoutError::Disconnected) = wakeup.recv_timeout(timeout) {
+                break;
+            }
+            let time_left = multiline.as_ref().and_then(PendingMultiline::time_left);
+            if time_left == Some(Duration::new(0, 0)) {
+                self.flush_multiline(&mut multiline, &*decoder, &*encoder);
+                self.save_offset(&reader, &buffer, &multiline);
+            }
+        }
+        self.flush_multiline(&mut multiline, &*decoder, &*encoder);
+        self.save_offset(&reader, &buffer, &multiline);
+        debug!("Stopped reading {}", self.path.display());
+        if inactive {
+            Some(reader.into_inner().state)
+        } else {
+            None
+        }
+    }
+
+    fn flush_multiline(
+        &self,
+        multiline: &mut Option<PendingMultiline>,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+    ) {
+        if let Some(record) = multiline.as_mut().and_then(PendingMultiline::flush) {
+            if let Err(e) = self.handle_record(&record, decoder, encoder) {
+                let _ = writeln!(stderr(), "{}: [{}]", e, record.trim());
+            }
+        }
+    }
 
-        let (decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) =
+    /// Read and handle the records written since the last call, and record the offset
+    /// of the last complete record in the registry. Returns `true` once the file can't
+    /// be read any more.
+    fn read_records(
+        &self,
+        reader: &mut BufReader<FollowReader>,
+        buffer: &mut ReadBuffer,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+        multiline: &mut Option<PendingMultiline>,
+    ) -> bool {
+        let finish = self.read_available(reader, buffer, decoder, encoder, multiline);
+        self.save_offset(reader, buffer, multiline);
+        finish
+    }
+
+    /// Record the offset of the first line that isn't part of a queued record in the
+    /// registry. This offset is saved once the records have been delivered.
+    fn save_offset(
+        &self,
+        reader: &BufReader<FollowReader>,
+        buffer: &ReadBuffer,
+        multiline: &Option<PendingMultiline>,
+    ) {
+        let multiline_start = multiline.as_ref().and_then(|multiline| multiline.start);
+        let pending = reader.buffer().len() as u64 + buffer.pending_len(multiline_start);
+        let mut state = reader.get_ref().state.clone();
+        state.offset = state.offset.saturating_sub(pending);
+        self.registry.update(&self.path, &state);
+        self.checkpoints.add(state);
+    }
+
+    /// Compressed files, such as rotated logs, are read once instead of being followed.
+    /// Their whole size is saved in the registry once they have been entirely read, so
+    /// that they aren't read again, even after having been renamed.
+    fn read_compressed(&self, compression: Compression) {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) => {
+                warn!("Unable to open {}: {}", self.path.display(), e);
+                return;
+            }
+        };
+        let mut state = match FileState::of(&file) {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        if self.registry.is_read(&file) {
+            return;
+        }
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+        info!("Reading compressed file {}", self.path.display());
+        let decompressed: Box<dyn Read> = match compression {
+            Compression::Gzip => Box::new(MultiGzDecoder::new(file)),
+            Compression::Zstd => match zstd::Decoder::new(file) {
+                Ok(decompressed) => Box::new(decompressed),
+                Err(e) => {
+                    warn!("Unable to read {}: {}", self.path.display(), e);
+                    return;
+                }
+            },
+        };
+        let mut reader = BufReader::with_capacity(READ_BUFFER_SIZE, decompressed);
+        let mut buffer = ReadBuffer::default();
+        let (mut decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) =
             (self.decoder.clone_boxed(), self.encoder.clone_boxed());
-        let mut finish = false;
-        while !finish {
-            match rx.recv() {
-                Ok(evt) => loop {
-                    println!("Watcher received event:{:?}", evt);
-                    stdout().flush().expect("Failed to flush stdout");
-                    let r = reader.read_until(10, &mut buffer);
-                    match r {
-                        Ok(bytes_read) => {
-                            println!("Read {} bytes from {}", bytes_read, &self.path.to_str().unwrap());
-                            stdout().flush().expect("Failed to flush stdout");
-                            if bytes_read == 0 {
-                                break;
-                            }
-                        }
-                        Err(_) => {
-                            finish = true;
-                            break;
-                        }
+        decoder.set_source(&self.path);
+        let mut multiline = self.config.multiline.as_ref().map(PendingMultiline::new);
+        if self.read_available(
+            &mut reader,
+            &mut buffer,
+            &*decoder,
+            &*encoder,
+            &mut multiline,
+        ) {
+            warn!("Corrupted compressed file {}", self.path.display());
+            return;
+        }
+        // The last line doesn't have to be terminated
+        if decoder.binary_framing().is_none() && !buffer.data.is_empty() {
+            let mut last_line = buffer.data.split_off(0);
+            last_line.push(b'\n');
+            self.read_available(
+                &mut &last_line[..],
+                &mut buffer,
+                &*decoder,
+                &*encoder,
+                &mut multiline,
+            );
+        }
+        for partial in buffer.partials.drain() {
+            let record = partial.record.as_bytes();
+            self.handle_line(record, partial.start, &*decoder, &*encoder, &mut multiline);
+        }
+        self.flush_multiline(&mut multiline, &*decoder, &*encoder);
+        state.offset = size;
+        self.registry.update(&self.path, &state);
+        self.checkpoints.add(state);
+    }
+
+    /// Handle the complete records that can be read. Lines longer than
+    /// `input.max_line_length` are truncated. Returns `true` on read errors.
+    fn read_available(
+        &self,
+        reader: &mut dyn BufRead,
+        buffer: &mut ReadBuffer,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+        multiline: &mut Option<PendingMultiline>,
+    ) -> bool {
+        if let Some(message_size) = decoder.binary_framing() {
+            return !self.read_messages(reader, &mut buffer.data, message_size, decoder, encoder);
+        }
+        let max_line_length = self.config.max_line_length;
+        loop {
+            let limit = (max_line_length + 1).saturating_sub(buffer.data.len()) as u64;
+            match (&mut *reader)
+                .take(limit)
+                .read_until(b'\n', &mut buffer.data)
+            {
+                Ok(0) => return false,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Unable to read {}: {}", self.path.display(), e);
+                    return true;
+                }
+            }
+            if buffer.data.last() != Some(&b'\n') {
+                if buffer.data.len() <= max_line_length {
+                    // Incomplete line, the rest hasn't been written yet
+                    continue;
+                }
+                if !buffer.discarding {
+                    warn!(
+                        "Line longer than {} bytes truncated in {}",
+                        max_line_length,
+                        self.path.display()
+                    );
+                    let line = &buffer.data[..max_line_length];
+                    self.handle_line(line, buffer.consumed, decoder, encoder, multiline);
+                }
+                buffer.consume();
+                buffer.discarding = true;
+                continue;
+            }
+            if buffer.discarding {
+                // End of a truncated line
+                buffer.consume();
+                buffer.discarding = false;
+                continue;
+            }
+            let line_len = buffer.data.len() - 1;
+            let start = buffer.consumed;
+            match str::from_utf8(&buffer.data[..line_len]) {
+                Ok(line) => {
+                    if let Some(partial) = buffer.partials.push(line, start, decoder) {
+                        let record = partial.record.as_bytes();
+                        self.handle_line(record, partial.start, decoder, encoder, multiline);
+                    } else if let Some(partial) = buffer.partials.take_longer(max_line_length) {
+                        warn!(
+                            "Record longer than {} bytes split in {}",
+                            max_line_length,
+                            self.path.display()
+                        );
+                        let record = partial.record.as_bytes();
+                        self.handle_line(record, partial.start, decoder, encoder, multiline);
                     }
-                    if buffer[buffer.len() - 1] == 10 {
-                        buffer.pop();
-                        let line = String::from_utf8(buffer.clone()).unwrap();
-                        buffer.truncate(0);
-                        if let Err(e) = handle_record(&line, &self.tx, &decoder, &encoder) {
-                            let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
-                        }
+                }
+                Err(_) => {
+                    let line = &buffer.data[..line_len];
+                    self.handle_line(line, start, decoder, encoder, multiline)
+                }
+            }
+            buffer.consume();
+        }
+    }
+
+    /// Handle a line, or a record made of several lines, that starts at `start` in the
+    /// bytes read by the `ReadBuffer`
+    fn handle_line(
+        &self,
+        line: &[u8],
+        start: u64,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+        multiline: &mut Option<PendingMultiline>,
+    ) {
+        let line = match str::from_utf8(line) {
+            Ok(line) => line,
+            Err(_) => {
+                let _ = writeln!(stderr(), "Invalid UTF-8 input in {}", self.path.display());
+                return;
+            }
+        };
+        let record = match multiline {
+            Some(ref mut multiline) => multiline.push(line, start),
+            None => Some(line.to_owned()),
+        };
+        if let Some(record) = record {
+            if let Err(e) = self.handle_record(&record, decoder, encoder) {
+                let _ = writeln!(stderr(), "{}: [{}]", e, record.trim());
+            }
+        }
+    }
+
+    /// Read the records of a binary format, keeping an incomplete record in `buffer` until
+    /// the rest of it has been written. Records larger than `input.max_line_length` are
+    /// skipped. Returns `false` once the file can't be read any more.
+    fn read_messages(
+        &self,
+        reader: &mut dyn BufRead,
+        buffer: &mut Vec<u8>,
+        message_size: FrameSize,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+    ) -> bool {
+        let max_message_size = self.config.max_line_length;
+        let mut start = 0;
+        let readable = loop {
+            match message_size(&buffer[start..]) {
+                Ok(Some(size)) => {
+                    if size > max_message_size {
+                        warn!(
+                            "Record longer than {} bytes skipped in {}",
+                            max_message_size,
+                            self.path.display()
+                        );
                     } else {
-                        println!("Buffer not full, waiting for it to fill...");
-                        stdout().flush().expect("Failed to flush stdout");
+                        let reencoded = decoder
+                            .decode_bytes(&buffer[start..start + size])
+                            .and_then(|decoded| encoder.encode(decoded));
+                        match reencoded {
+                            Ok(reencoded) => self.send(reencoded),
+                            Err(e) => {
+                                let _ = writeln!(stderr(), "{}", e);
+                            }
+                        }
                     }
-                },
-                Err(err) => {
-                    println!("RecvError in watcher: {}", err.to_string());
-                    stdout().flush().expect("Failed to flush stdout");
+                    start += size;
+                    continue;
+                }
+                Ok(None) if buffer.len() - start <= max_message_size => {}
+                Ok(None) => {
+                    // The framing is lost, skip what has been read so far
+                    warn!(
+                        "Record longer than {} bytes skipped in {}",
+                        max_message_size,
+                        self.path.display()
+                    );
+                    start = buffer.len();
+                    break true;
                 }
+                Err(e) => {
+                    // The framing is lost, skip what has been read so far
+                    let _ = writeln!(stderr(), "{}", e);
+                    start = buffer.len();
+                    break true;
+                }
+            }
+            // Only move the incomplete record to the front once most of the buffer has
+            // been decoded
+            if start > buffer.len() / 2 {
+                buffer.drain(..start);
+                start = 0;
+            }
+            let read = match reader.fill_buf() {
+                Ok([]) => break true,
+                Ok(chunk) => {
+                    buffer.extend_from_slice(chunk);
+                    chunk.len()
+                }
+                Err(_) => break false,
+            };
+            reader.consume(read);
+        };
+        buffer.drain(..start);
+        readable
+    }
+
+    fn handle_record(
+        &self,
+        line: &str,
+        decoder: &dyn Decoder,
+        encoder: &dyn Encoder,
+    ) -> Result<(), &'static str> {
+        let decoded = decoder.decode(line)?;
+        let reencoded = encoder.encode(decoded)?;
+        self.send(reencoded);
+        Ok(())
+    }
+
+    fn send(&self, reencoded: Vec<u8>) {
+        self.checkpoints.expect(&reencoded);
+        self.tx.send(reencoded).unwrap();
+    }
+}
+
+/// Data read from a file that doesn't make a complete record yet
+#[derive(Default)]
+struct ReadBuffer {
+    /// The line being read
+    data: Vec<u8>,
+    /// The rest of a truncated line is being skipped
+    discarding: bool,
+    /// Number of bytes of the lines read so far
+    consumed: u64,
+    partials: Partials,
+}
+
+impl ReadBuffer {
+    /// Done with the line in `data`
+    fn consume(&mut self) {
+        self.consumed += self.data.len() as u64;
+        self.data.clear();
+    }
+
+    /// Number of bytes read since the beginning of the oldest incomplete record, to read
+    /// again if the file is reopened. `multiline_start` is the position of the first line
+    /// of a pending multiline record.
+    fn pending_len(&self, multiline_start: Option<u64>) -> u64 {
+        let start = self
+            .partials
+            .oldest_start()
+            .into_iter()
+            .chain(multiline_start)
+            .min()
+            .unwrap_or(self.consumed);
+        self.consumed - start + self.data.len() as u64
+    }
+}
+
+/// Lines joined into a multiline record that hasn't been sent yet
+struct PendingMultiline {
+    multiline: Multiline,
+    /// Position of the first line of the pending record, in bytes read by the `ReadBuffer`
+    start: Option<u64>,
+}
+
+impl PendingMultiline {
+    fn new(config: &MultilineConfig) -> PendingMultiline {
+        PendingMultiline {
+            multiline: Multiline::new(config),
+            start: None,
+        }
+    }
+
+    /// Add a line that starts at `start`, and return the previous record if this line
+    /// starts a new one
+    fn push(&mut self, line: &str, start: u64) -> Option<String> {
+        let previous = self.multiline.push(line);
+        if previous.is_some() || self.start.is_none() {
+            self.start = Some(start);
+        }
+        previous
+    }
+
+    fn flush(&mut self) -> Option<String> {
+        self.start = None;
+        self.multiline.flush()
+    }
+
+    fn time_left(&self) -> Option<Duration> {
+        self.multiline.time_left()
+    }
+}
+
+/// Records spanning several lines that aren't complete yet, one per stream
+#[derive(Default)]
+struct Partials(Vec<Partial>);
+
+struct Partial {
+    stream: Option<String>,
+    record: String,
+    /// Position of the first line of the record, in bytes read by the `ReadBuffer`
+    start: u64,
+}
+
+impl Partials {
+    /// Append `line`, that starts at `start`, to the incomplete record of its stream.
+    /// Returns the record once it is complete.
+    fn push(&mut self, line: &str, start: u64, decoder: &dyn Decoder) -> Option<Partial> {
+        let stream = decoder.record_stream(line);
+        let partial = match self.0.iter().position(|x| x.stream == stream) {
+            Some(i) => {
+                let mut partial = self.0.remove(i);
+                partial.record.push('\n');
+                partial.record.push_str(line);
+                partial
             }
+            None => Partial {
+                stream,
+                record: line.to_owned(),
+                start,
+            },
+        };
+        if decoder.is_complete(&partial.record) {
+            return Some(partial);
         }
+        self.0.push(partial);
+        None
+    }
+
+    /// Remove an incomplete record that grew larger than `max_length`
+    fn take_longer(&mut self, max_length: usize) -> Option<Partial> {
+        let i = self.0.iter().position(|x| x.record.len() > max_length)?;
+        Some(self.0.remove(i))
+    }
+
+    /// Remove the incomplete records, in the order they were started
+    fn drain(&mut self) -> Vec<Partial> {
+        self.0.sort_by_key(|x| x.start);
+        self.0.drain(..).collect()
+    }
+
+    fn oldest_start(&self) -> Option<u64> {
+        self.0.iter().map(|x| x.start).min()
     }
 }
 
+/// Read a file as it grows. Once the file has been renamed or removed, it is read
+/// until the end and for `rotate_wait`, before the new file at the same path is
+/// opened. A file truncated in place is read again from the beginning.
 pub struct FollowReader {
     file: File,
     path: PathBuf,
+    state: FileState,
+    rotate_wait: Duration,
+    rotated_at: Option<Instant>,
+    at_eof: bool,
 }
 
 impl FollowReader {
-    pub fn new(filename: &Path, from_tail: bool) -> FollowReader {
-        let mut f = File::open(filename).expect("Failed to open file");
-        if from_tail {
-            f.seek(SeekFrom::End(0)).unwrap();
-        }
-        FollowReader {
+    /// Open a file, resuming from the offset saved in the registry if it was already
+    /// being followed
+    pub fn new(
+        filename: &Path,
+        from_tail: bool,
+        registry: &Registry,
+        rotate_wait: Duration,
+    ) -> std::io::Result<FollowReader> {
+        let mut f = File::open(filename)?;
+        let mut state = FileState::of(&f)?;
+        state.offset = match registry.resume_offset(filename, &f) {
+            Some(offset) => f.seek(SeekFrom::Start(offset))?,
+            None if from_tail => f.seek(SeekFrom::End(0))?,
+            None => 0,
+        };
+        Ok(FollowReader {
             file: f,
             path: PathBuf::from(filename),
+            state,
+            rotate_wait,
+            rotated_at: None,
+            at_eof: false,
+        })
+    }
+
+    fn file_id(&self) -> (u64, u64) {
+        (self.state.dev, self.state.ino)
+    }
+
+    /// Read the file again from the beginning if it has been truncated, even if more
+    /// data than what had been read has been written since then
+    fn check_truncation(&mut self) -> std::io::Result<bool> {
+        let size = self.file.metadata()?.len();
+        if size >= self.state.offset && self.state.fingerprint_matches(&self.file) {
+            return Ok(false);
         }
+        info!("{} has been truncated", self.path.display());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.state.offset = 0;
+        self.state.fingerprint_len = 0;
+        self.state.update_fingerprint(&self.file)?;
+        Ok(true)
+    }
+
+    /// Called at the end of the file, to detect truncation and rotation
+    fn check_rotation(&mut self) -> std::io::Result<()> {
+        if self.check_truncation()? {
+            return Ok(());
+        }
+        match fs::metadata(&self.path) {
+            Ok(ref metadata) if self.state.is_same_file(metadata) => {
+                self.rotated_at = None;
+                return Ok(());
+            }
+            _ => {}
+        }
+        // Data can still be written to the old file for a while
+        let rotated_at = *self.rotated_at.get_or_insert_with(Instant::now);
+        if rotated_at.elapsed() < self.rotate_wait {
+            return Ok(());
+        }
+        let file = File::open(&self.path)?;
+        info!("{} has been rotated, reopening it", self.path.display());
+        self.state = FileState::of(&file)?;
+        self.file = file;
+        self.rotated_at = None;
+        Ok(())
     }
 }
 
 impl Read for FollowReader {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        if self.path.exists() {
-            self.file.sync_data().unwrap();
-            self.file.read(buf)
-        } else {
-            Err(std::io::Error::new(std::io::ErrorKind::Other, ""))
+        if self.at_eof {
+            self.at_eof = false;
+            self.check_truncation()?;
+        }
+        let mut read = self.file.read(buf)?;
+        if read == 0 {
+            self.check_rotation()?;
+            read = self.file.read(buf)?;
+            self.at_eof = read == 0;
         }
+        if read > 0 {
+            self.state.offset += read as u64;
+            self.state.update_fingerprint(&self.file)?;
+        }
+        Ok(read)
     }
 }
 
-fn handle_record(
-    line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
-) -> Result<(), &'static str> {
-    println!("reading log line: {}", line);
-    stdout().flush().expect("Failed to flush stdout");
-    let decoded = decoder.decode(line)?;
-    let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+    #[cfg(any(feature = "cri", feature = "gelf"))]
+    use crate::flowgger::config::Config;
+    #[cfg(feature = "cri")]
+    use crate::flowgger::decoder::CriDecoder;
+    #[cfg(feature = "gelf")]
+    use crate::flowgger::decoder::GelfDecoder;
+    #[cfg(feature = "gelf")]
+    use crate::flowgger::encoder::GelfEncoder;
+    #[cfg(feature = "gelf")]
+    use crate::flowgger::input::file::FileInput;
+    #[cfg(feature = "gelf")]
+    use crate::flowgger::record::Record;
+    #[cfg(feature = "gelf")]
+    use flate2::write::GzEncoder;
+    #[cfg(feature = "gelf")]
+    use std::sync::mpsc::sync_channel;
+    use tempdir::TempDir;
+
+    fn read_all(reader: &mut FollowReader) -> String {
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn test_follow_rename() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("test.log");
+        fs::write(&path, "first\n").unwrap();
+        let registry = Registry::new(None);
+        let mut reader =
+            FollowReader::new(&path, false, &registry, Duration::from_secs(0)).unwrap();
+        assert_eq!(read_all(&mut reader), "first\n");
+
+        let mut rotated = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        fs::rename(&path, dir.path().join("test.log.1")).unwrap();
+        rotated.write_all(b"second\n").unwrap();
+        fs::write(&path, "third\n").unwrap();
+        assert_eq!(read_all(&mut reader), "second\nthird\n");
+        assert_eq!(reader.state.offset, 6);
+    }
+
+    #[test]
+    fn test_follow_rename_wait() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("test.log");
+        fs::write(&path, "first\n").unwrap();
+        let registry = Registry::new(None);
+        let mut reader =
+            FollowReader::new(&path, false, &registry, Duration::from_secs(60)).unwrap();
+        fs::rename(&path, dir.path().join("test.log.1")).unwrap();
+        fs::write(&path, "second\n").unwrap();
+        assert_eq!(read_all(&mut reader), "first\n");
+        assert_eq!(read_all(&mut reader), "");
+        assert!(reader.rotated_at.is_some());
+    }
+
+    #[test]
+    fn test_follow_truncate() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("test.log");
+        fs::write(&path, "first line\n").unwrap();
+        let registry = Registry::new(None);
+        let mut reader =
+            FollowReader::new(&path, false, &registry, Duration::from_secs(60)).unwrap();
+        assert_eq!(read_all(&mut reader), "first line\n");
+
+        // copytruncate
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"second\n")
+            .unwrap();
+        assert_eq!(read_all(&mut reader), "second\n");
+        assert_eq!(reader.state.offset, 7);
+
+        // Truncated and written again past the previous offset
+        fs::write(&path, "another first line\n").unwrap();
+        assert_eq!(read_all(&mut reader), "another first line\n");
+    }
+
+    /// Read `path` until it is inactive, with `settings` added to the `[input]` section,
+    /// and return the records as GELF
+    #[cfg(feature = "gelf")]
+    fn run_worker(
+        path: &Path,
+        settings: &str,
+        registry: &Registry,
+        decoder: fn(&Config) -> Box<dyn Decoder + Send>,
+    ) -> Vec<String> {
+        let config =
+            Config::from_string(&format!("[input]\nsrc = \"unused\"\n{}", settings)).unwrap();
+        let (tx, rx) = sync_channel(10);
+        let (_wakeup_tx, wakeup_rx) = sync_channel(1);
+        let mut worker = FileWorker::new(
+            path,
+            FileInput::new(&config).file_config,
+            registry.clone(),
+            tx,
+            decoder(&config),
+            Box::new(GelfEncoder::new(&config)),
+        );
+        worker.run(false, &wakeup_rx);
+        rx.try_iter()
+            .map(|record| String::from_utf8(record).unwrap())
+            .collect()
+    }
+
+    #[cfg(feature = "gelf")]
+    fn gelf_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+        Box::new(GelfDecoder::new(config))
+    }
+
+    #[cfg(feature = "gelf")]
+    #[test]
+    fn test_compressed_files() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let content = "{\"host\":\"h\",\"short_message\":\"first\"}\n\
+                       {\"host\":\"h\",\"short_message\":\"second\"}";
+        let gz_path = dir.path().join("app.log.1.gz");
+        let mut gz = GzEncoder::new(File::create(&gz_path).unwrap(), Default::default());
+        gz.write_all(content.as_bytes()).unwrap();
+        gz.finish().unwrap();
+        let zst_path = dir.path().join("app.log.2.zst");
+        fs::write(&zst_path, zstd::encode_all(content.as_bytes(), 0).unwrap()).unwrap();
+        assert_eq!(compression(&gz_path), Some(Compression::Gzip));
+        assert_eq!(compression(&zst_path), Some(Compression::Zstd));
+
+        let registry = Registry::new(None);
+        let mut records = Vec::new();
+        for path in &[&gz_path, &zst_path, &gz_path] {
+            records.extend(run_worker(path, "", &registry, gelf_decoder));
+        }
+        assert_eq!(records.len(), 4);
+        assert!(records[0].contains("first") && records[1].contains("second"));
+        assert!(records[2].contains("first") && records[3].contains("second"));
+    }
+
+    #[cfg(feature = "gelf")]
+    #[test]
+    fn test_compressed_file_renamed() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let gz_path = dir.path().join("app.log.2.gz");
+        let mut gz = GzEncoder::new(File::create(&gz_path).unwrap(), Default::default());
+        gz.write_all(b"{\"host\":\"h\",\"short_message\":\"first\"}\n")
+            .unwrap();
+        gz.finish().unwrap();
+
+        let registry = Registry::new(None);
+        assert_eq!(run_worker(&gz_path, "", &registry, gelf_decoder).len(), 1);
+        // logrotate renames the compressed files at every rotation
+        let rotated_path = dir.path().join("app.log.3.gz");
+        fs::rename(&gz_path, &rotated_path).unwrap();
+        assert!(run_worker(&rotated_path, "", &registry, gelf_decoder).is_empty());
+    }
+
+    #[cfg(feature = "gelf")]
+    #[test]
+    fn test_worker_long_lines() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("app.log");
+        let long_message = "x".repeat(100);
+        fs::write(
+            &path,
+            format!(
+                "{{\"host\":\"h\",\"short_message\":\"first\"}}\n\
+                 {{\"host\":\"h\",\"short_message\":\"{}\"}}\n\
+                 {{\"host\":\"h\",\"short_message\":\"last\"}}\n\
+                 {{\"host\":\"h\",",
+                long_message
+            ),
+        )
+        .unwrap();
+        let registry = Registry::new(None);
+        // Returns once the file is inactive
+        let settings = "max_line_length = 64\nclose_inactive = 0";
+        let records = run_worker(&path, settings, &registry, gelf_decoder);
+        assert_eq!(records.len(), 2);
+        assert!(records[0].contains("first") && records[1].contains("last"));
+
+        // The incomplete last line is read again when the file is reopened
+        let file = File::open(&path).unwrap();
+        let size = file.metadata().unwrap().len();
+        assert_eq!(registry.resume_offset(&path, &file), Some(size - 12));
+    }
+
+    #[cfg(all(feature = "gelf", feature = "multiline"))]
+    #[test]
+    fn test_worker_pending_multiline() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("app.log");
+        let first = "{\"host\":\"h\",\"short_message\":\"first\"}\n";
+        fs::write(
+            &path,
+            format!("{}{{\"host\":\"h\",\n \"short_message\":", first),
+        )
+        .unwrap();
+        let config = Config::from_string(
+            "[input]\nsrc = \"unused\"\nmultiline_continuation = '^\\s'\nclose_inactive = 0",
+        )
+        .unwrap();
+        let registry = Registry::new(None);
+        let (tx, rx) = sync_channel(10);
+        let worker = FileWorker::new(
+            &path,
+            FileInput::new(&config).file_config,
+            registry.clone(),
+            tx,
+            gelf_decoder(&config),
+            Box::new(GelfEncoder::new(&config)),
+        );
+        let fr = FollowReader::new(&path, false, &registry, Duration::from_secs(0)).unwrap();
+        let mut reader = BufReader::new(fr);
+        let mut buffer = ReadBuffer::default();
+        let mut multiline = worker.config.multiline.as_ref().map(PendingMultiline::new);
+        let (decoder, encoder) = (&*worker.decoder, &*worker.encoder);
+        worker.read_records(&mut reader, &mut buffer, decoder, encoder, &mut multiline);
+        assert_eq!(rx.try_iter().count(), 1);
+        // The lines of the pending record are read again if the file is reopened
+        // before it is complete
+        let file = File::open(&path).unwrap();
+        assert_eq!(
+            registry.resume_offset(&path, &file),
+            Some(first.len() as u64)
+        );
+
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"\"second\"}\n{\"host\":\"h\",\n")
+            .unwrap();
+        worker.read_records(&mut reader, &mut buffer, decoder, encoder, &mut multiline);
+        assert_eq!(rx.try_iter().count(), 1);
+        let file = File::open(&path).unwrap();
+        let size = file.metadata().unwrap().len();
+        assert_eq!(registry.resume_offset(&path, &file), Some(size - 13));
+    }
+
+    /// GELF records prefixed with their length, as a single byte
+    #[cfg(feature = "gelf")]
+    #[derive(Clone)]
+    struct PrefixedDecoder(GelfDecoder);
+
+    #[cfg(feature = "gelf")]
+    impl Decoder for PrefixedDecoder {
+        fn decode(&self, line: &str) -> Result<Record, &'static str> {
+            self.0.decode(line)
+        }
+
+        fn decode_bytes(&self, bytes: &[u8]) -> Result<Record, &'static str> {
+            self.0.decode_bytes(&bytes[1..])
+        }
+
+        fn binary_framing(&self) -> Option<FrameSize> {
+            Some(|buffer| {
+                Ok(buffer
+                    .first()
+                    .map(|&len| 1 + len as usize)
+                    .filter(|&size| size <= buffer.len()))
+            })
+        }
+    }
+
+    #[cfg(feature = "gelf")]
+    #[test]
+    fn test_worker_binary_records() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("app.capnp");
+        let mut content = Vec::new();
+        for message in &["first", &"x".repeat(100), "last"] {
+            let record = format!("{{\"host\":\"h\",\"short_message\":\"{}\"}}", message);
+            content.push(record.len() as u8);
+            content.extend_from_slice(record.as_bytes());
+        }
+        content.extend_from_slice(&[100, b'{']);
+        fs::write(&path, &content).unwrap();
+        let registry = Registry::new(None);
+        let settings = "max_line_length = 64\nclose_inactive = 0";
+        let records = run_worker(&path, settings, &registry, |config| {
+            Box::new(PrefixedDecoder(GelfDecoder::new(config)))
+        });
+        assert_eq!(records.len(), 2);
+        assert!(records[0].contains("first") && records[1].contains("last"));
+
+        // The incomplete last record is read again when the file is reopened
+        let file = File::open(&path).unwrap();
+        let size = file.metadata().unwrap().len();
+        assert_eq!(registry.resume_offset(&path, &file), Some(size - 2));
+    }
+
+    #[cfg(feature = "cri")]
+    fn complete(partial: Option<Partial>) -> Option<(String, u64)> {
+        partial.map(|partial| (partial.record, partial.start))
+    }
+
+    #[cfg(feature = "cri")]
+    #[test]
+    fn test_partials_interleaved_streams() {
+        let decoder = CriDecoder::new(&Config::from_string("").unwrap());
+        let mut partials = Partials::default();
+        assert!(partials
+            .push("2015-08-05T15:53:45Z stdout P a", 0, &decoder)
+            .is_none());
+        assert_eq!(
+            complete(partials.push("2015-08-05T15:53:46Z stderr F x", 32, &decoder)),
+            Some(("2015-08-05T15:53:46Z stderr F x".to_owned(), 32))
+        );
+        assert_eq!(partials.oldest_start(), Some(0));
+        assert_eq!(
+            complete(partials.push("2015-08-05T15:53:47Z stdout F b", 64, &decoder)),
+            Some((
+                "2015-08-05T15:53:45Z stdout P a\n2015-08-05T15:53:47Z stdout F b".to_owned(),
+                0
+            ))
+        );
+        assert_eq!(partials.oldest_start(), None);
+    }
+
+    #[cfg(all(feature = "cri", feature = "gelf"))]
+    #[test]
+    fn test_worker_interleaved_streams() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("0.log");
+        let last_line = "2015-08-05T15:53:48Z stdout P c\n";
+        fs::write(
+            &path,
+            format!(
+                "2015-08-05T15:53:45Z stdout P a\n\
+                 2015-08-05T15:53:46Z stderr F x\n\
+                 2015-08-05T15:53:47Z stdout F b\n\
+                 {}",
+                last_line
+            ),
+        )
+        .unwrap();
+        let registry = Registry::new(None);
+        let records = run_worker(&path, "close_inactive = 0", &registry, |config| {
+            Box::new(CriDecoder::new(config))
+        });
+        assert_eq!(records.len(), 2);
+        assert!(records[0].contains("\"short_message\":\"x\""));
+        assert!(records[1].contains("\"short_message\":\"ab\""));
+
+        // The incomplete stdout record is read again when the file is reopened
+        let file = File::open(&path).unwrap();
+        let size = file.metadata().unwrap().len();
+        assert_eq!(
+            registry.resume_offset(&path, &file),
+            Some(size - last_line.len() as u64)
+        );
+    }
 }
@@ -0,0 +1,219 @@
+use crate::flowgger::config::Config;
+use crate::flowgger::utils::metrics::{self, Counter};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const GELF_CHUNK_MAGIC: [u8; 2] = [0x1e, 0x0f];
+const GELF_CHUNK_HEADER_SIZE: usize = 12;
+const MAX_CHUNKS: u8 = 128;
+const DEFAULT_TIMEOUT: u64 = 5;
+const DEFAULT_MAX_BYTES: usize = 33_554_432;
+
+/// Whether a datagram is a chunk of a larger GELF message
+pub fn is_chunk(datagram: &[u8]) -> bool {
+    datagram.len() >= GELF_CHUNK_HEADER_SIZE && datagram[..2] == GELF_CHUNK_MAGIC
+}
+
+#[derive(Clone)]
+struct PendingMessage {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    size: usize,
+    first_seen: Instant,
+}
+
+/// Reassemble chunked GELF messages. Messages that are still incomplete after a timeout
+/// are dropped, as well as the oldest messages when the buffered chunks exceed a
+/// maximum size.
+#[derive(Clone)]
+pub struct ChunkAssembler {
+    messages: HashMap<[u8; 8], PendingMessage>,
+    size: usize,
+    max_bytes: usize,
+    timeout: Duration,
+    expired: Counter,
+    incomplete: Counter,
+}
+
+impl ChunkAssembler {
+    /// `input.gelf_chunk_timeout` is the number of seconds to wait for the missing
+    /// chunks of a message (5 by default), and `input.gelf_chunks_max_bytes` the maximum
+    /// size of the chunks waiting to be reassembled
+    pub fn new(config: &Config) -> ChunkAssembler {
+        let timeout = config
+            .lookup("input.gelf_chunk_timeout")
+            .map_or(DEFAULT_TIMEOUT, |x| {
+                x.as_integer()
+                    .expect("input.gelf_chunk_timeout must be a number of seconds")
+                    as u64
+            });
+        let max_bytes =
+            config
+                .lookup("input.gelf_chunks_max_bytes")
+                .map_or(DEFAULT_MAX_BYTES, |x| {
+                    x.as_integer()
+                        .expect("input.gelf_chunks_max_bytes must be a size integer")
+                        as usize
+                });
+        ChunkAssembler {
+            messages: HashMap::new(),
+            size: 0,
+            max_bytes,
+            timeout: Duration::from_secs(timeout),
+            expired: metrics::counter("input.udp.gelf_chunks_expired"),
+            incomplete: metrics::counter("input.udp.gelf_chunks_incomplete"),
+        }
+    }
+
+    /// An empty assembler for one of `threads` threads, that share the maximum size
+    pub fn for_thread(&self, threads: usize) -> ChunkAssembler {
+        ChunkAssembler {
+            messages: HashMap::new(),
+            size: 0,
+            max_bytes: self.max_bytes / threads.max(1),
+            ..self.clone()
+        }
+    }
+
+    /// Add a chunk, and return the reassembled message once all its chunks have been
+    /// received
+    pub fn push(&mut self, datagram: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+        if !is_chunk(datagram) {
+            return Err("Invalid GELF chunk");
+        }
+        let mut id = [0; 8];
+        id.copy_from_slice(&datagram[2..10]);
+        let (seq, count) = (datagram[10], datagram[11]);
+        if count == 0 || count > MAX_CHUNKS {
+            return Err("Invalid number of chunks in a GELF message");
+        }
+        if seq >= count {
+            return Err("Invalid GELF chunk sequence number");
+        }
+        let payload = &datagram[GELF_CHUNK_HEADER_SIZE..];
+
+        self.expire();
+        let message = self.messages.entry(id).or_insert_with(|| PendingMessage {
+            chunks: vec![None; count as usize],
+            received: 0,
+            size: 0,
+            first_seen: Instant::now(),
+        });
+        if message.chunks.len() != count as usize {
+            return Err("Inconsistent number of chunks in a GELF message");
+        }
+        let chunk = &mut message.chunks[seq as usize];
+        if chunk.is_some() {
+            return Ok(None);
+        }
+        *chunk = Some(payload.to_vec());
+        message.received += 1;
+        message.size += payload.len();
+        self.size += payload.len();
+
+        if message.received == message.chunks.len() {
+            let message = self.messages.remove(&id).unwrap();
+            self.size -= message.size;
+            let mut res = Vec::with_capacity(message.size);
+            for chunk in message.chunks.into_iter().flatten() {
+                res.extend_from_slice(&chunk);
+            }
+            return Ok(Some(res));
+        }
+        while self.size > self.max_bytes {
+            let oldest = self
+                .messages
+                .iter()
+                .min_by_key(|(_, message)| message.first_seen)
+                .map(|(id, _)| *id);
+            match oldest {
+                Some(oldest) => {
+                    let message = self.messages.remove(&oldest).unwrap();
+                    self.size -= message.size;
+                    self.incomplete.inc();
+                }
+                None => break,
+            }
+        }
+        Ok(None)
+    }
+
+    /// Drop the messages whose chunks haven't all been received in time
+    pub fn expire(&mut self) {
+        let timeout = self.timeout;
+        let mut expired_size = 0;
+        let mut expired = 0;
+        self.messages.retain(|_, message| {
+            if message.first_seen.elapsed() < timeout {
+                return true;
+            }
+            expired_size += message.size;
+            expired += 1;
+            false
+        });
+        self.size -= expired_size;
+        self.expired.add(expired);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn chunk(id: u8, seq: u8, count: u8, payload: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![0x1e, 0x0f, id, 0, 0, 0, 0, 0, 0, 0, seq, count];
+        chunk.extend_from_slice(payload);
+        chunk
+    }
+
+    #[test]
+    fn test_gelf_chunks_reassembly() {
+        let config = Config::from_string("").unwrap();
+        let mut assembler = ChunkAssembler::new(&config);
+        assert!(is_chunk(&chunk(1, 0, 3, b"{\"a\"")));
+        assert!(!is_chunk(b"{\"short_message\":\"x\"}"));
+        assert_eq!(assembler.push(&chunk(1, 2, 3, b"}")), Ok(None));
+        assert_eq!(assembler.push(&chunk(2, 0, 2, b"other")), Ok(None));
+        assert_eq!(assembler.push(&chunk(1, 0, 3, b"{\"a\"")), Ok(None));
+        assert_eq!(assembler.push(&chunk(1, 0, 3, b"{\"a\"")), Ok(None));
+        assert_eq!(
+            assembler.push(&chunk(1, 1, 3, b":1")),
+            Ok(Some(b"{\"a\":1}".to_vec()))
+        );
+        assert_eq!(assembler.messages.len(), 1);
+        assert_eq!(assembler.size, 5);
+    }
+
+    #[test]
+    fn test_gelf_chunks_invalid() {
+        let config = Config::from_string("").unwrap();
+        let mut assembler = ChunkAssembler::new(&config);
+        assert!(assembler.push(&chunk(1, 0, 129, b"x")).is_err());
+        assert!(assembler.push(&chunk(1, 0, 0, b"x")).is_err());
+        assert!(assembler.push(&chunk(1, 3, 3, b"x")).is_err());
+        assert_eq!(assembler.push(&chunk(1, 0, 3, b"x")), Ok(None));
+        assert!(assembler.push(&chunk(1, 1, 2, b"x")).is_err());
+    }
+
+    #[test]
+    fn test_gelf_chunks_limits() {
+        let config =
+            Config::from_string("[input]\ngelf_chunk_timeout = 0\ngelf_chunks_max_bytes = 4")
+                .unwrap();
+        let mut assembler = ChunkAssembler::new(&config);
+        let before = assembler.incomplete.get();
+        assert_eq!(assembler.push(&chunk(1, 0, 2, b"12345")), Ok(None));
+        assert!(assembler.messages.is_empty());
+        assert!(assembler.incomplete.get() > before);
+        assert_eq!(assembler.for_thread(4).max_bytes, 1);
+
+        let config = Config::from_string("[input]\ngelf_chunk_timeout = 0").unwrap();
+        let mut assembler = ChunkAssembler::new(&config);
+        let before = assembler.expired.get();
+        assert_eq!(assembler.push(&chunk(1, 0, 2, b"1")), Ok(None));
+        assembler.expire();
+        assert!(assembler.messages.is_empty());
+        assert_eq!(assembler.size, 0);
+        assert!(assembler.expired.get() > before);
+    }
+}
@@ -0,0 +1,625 @@
+use super::Input;
+use crate::flowgger::config::Config;
+use crate::flowgger::decoder::Decoder;
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::utils::metrics::{self, Counter};
+use flate2::read::GzDecoder;
+#[cfg(feature = "tls")]
+use openssl::ssl::SslAcceptor;
+use serde_json::de;
+use serde_json::value::Value;
+use std::io::{self, stderr, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_LISTEN: &str = "0.0.0.0:8080";
+const DEFAULT_MAX_BODY_SIZE: usize = 10_485_760;
+const DEFAULT_MAX_CONNECTIONS: usize = 256;
+const DEFAULT_TIMEOUT: u64 = 60;
+const MAX_HEADER_LINE_SIZE: usize = 8192;
+const MAX_HEADERS: usize = 100;
+
+type Status = (u16, &'static str);
+
+const NO_CONTENT: Status = (204, "No Content");
+const BAD_REQUEST: Status = (400, "Bad Request");
+const UNAUTHORIZED: Status = (401, "Unauthorized");
+const METHOD_NOT_ALLOWED: Status = (405, "Method Not Allowed");
+const LENGTH_REQUIRED: Status = (411, "Length Required");
+const PAYLOAD_TOO_LARGE: Status = (413, "Payload Too Large");
+const UNSUPPORTED_MEDIA_TYPE: Status = (415, "Unsupported Media Type");
+const TOO_MANY_REQUESTS: Status = (429, "Too Many Requests");
+const SERVICE_UNAVAILABLE: Status = (503, "Service Unavailable");
+
+#[derive(Clone)]
+pub struct HttpConfig {
+    token: Option<String>,
+    max_body_size: usize,
+    #[cfg(feature = "tls")]
+    acceptor: Option<SslAcceptor>,
+    throttled: Counter,
+}
+
+/// HTTP input: records are sent as the body of `POST` requests, either one record per
+/// line, as a JSON array of records, or gzip-compressed.
+pub struct HttpInput {
+    listen: String,
+    timeout: Option<Duration>,
+    max_connections: usize,
+    http_config: HttpConfig,
+}
+
+impl HttpInput {
+    /// Requests must carry an `Authorization: Bearer <input.http_token>` header if
+    /// `input.http_token` is set. Bodies are limited to `input.http_max_body_size` bytes
+    /// once decompressed, and at most `input.http_max_connections` connections are
+    /// served at the same time. With `input.http_tls = true`, connections use TLS,
+    /// configured like the `tls` input.
+    pub fn new(config: &Config) -> HttpInput {
+        let listen = config
+            .lookup("input.listen")
+            .map_or(DEFAULT_LISTEN, |x| {
+                x.as_str().expect("input.listen must be an ip:port string")
+            })
+            .to_owned();
+        let timeout = config.lookup("input.timeout").map_or(DEFAULT_TIMEOUT, |x| {
+            x.as_integer().expect("input.timeout must be an integer") as u64
+        });
+        let token = config.lookup("input.http_token").map(|x| {
+            x.as_str()
+                .expect("input.http_token must be a string")
+                .to_owned()
+        });
+        let max_body_size =
+            config
+                .lookup("input.http_max_body_size")
+                .map_or(DEFAULT_MAX_BODY_SIZE, |x| {
+                    x.as_integer()
+                        .expect("input.http_max_body_size must be a size integer")
+                        as usize
+                });
+        let max_connections =
+            config
+                .lookup("input.http_max_connections")
+                .map_or(DEFAULT_MAX_CONNECTIONS, |x| {
+                    x.as_integer()
+                        .filter(|&x| x > 0)
+                        .expect("input.http_max_connections must be a positive integer")
+                        as usize
+                });
+        let tls = config
+            .lookup("input.http_tls")
+            .map(|x| x.as_bool().expect("input.http_tls must be a boolean"))
+            .unwrap_or(false);
+        let http_config = HttpConfig {
+            token,
+            max_body_size,
+            #[cfg(feature = "tls")]
+            acceptor: get_acceptor(config, tls),
+            throttled: metrics::counter("input.http.throttled"),
+        };
+        #[cfg(not(feature = "tls"))]
+        {
+            if tls {
+                panic!("Support for tls is not compiled in");
+            }
+        }
+        HttpInput {
+            listen,
+            timeout: Some(Duration::from_secs(timeout)),
+            max_connections,
+            http_config,
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn get_acceptor(config: &Config, tls: bool) -> Option<SslAcceptor> {
+    if !tls {
+        return None;
+    }
+    let (tls_config, _, _) = super::tls::config_parse(config);
+    Some(tls_config.acceptor)
+}
+
+impl Input for HttpInput {
+    fn accept(
+        &self,
+        tx: SyncSender<Vec<u8>>,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        let listener = TcpListener::bind(&self.listen as &str).unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        for mut client in listener.incoming().flatten() {
+            if connections.load(Ordering::SeqCst) >= self.max_connections {
+                #[cfg(feature = "tls")]
+                {
+                    if self.http_config.acceptor.is_some() {
+                        continue;
+                    }
+                }
+                let _ = write_response(&mut client, SERVICE_UNAVAILABLE, false);
+                continue;
+            }
+            let _ = client.set_read_timeout(self.timeout);
+            let tx = tx.clone();
+            let http_config = self.http_config.clone();
+            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            let slot = ConnectionSlot::new(&connections);
+            thread::spawn(move || {
+                handle_client(client, tx, decoder, encoder, http_config);
+                drop(slot);
+            });
+        }
+    }
+}
+
+/// A connection being served, counted until it is dropped
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn new(connections: &Arc<AtomicUsize>) -> ConnectionSlot {
+        connections.fetch_add(1, Ordering::SeqCst);
+        ConnectionSlot(connections.clone())
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle_client(
+    client: TcpStream,
+    tx: SyncSender<Vec<u8>>,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+    http_config: HttpConfig,
+) {
+    #[cfg(feature = "tls")]
+    {
+        if let Some(ref acceptor) = http_config.acceptor {
+            match acceptor.accept(client) {
+                Ok(sslclient) => {
+                    handle_connection(sslclient, &tx, &*decoder, &*encoder, &http_config)
+                }
+                Err(_) => {
+                    let _ = writeln!(stderr(), "SSL handshake aborted by the client");
+                }
+            }
+            return;
+        }
+    }
+    handle_connection(client, &tx, &*decoder, &*encoder, &http_config);
+}
+
+struct RequestHead {
+    method: String,
+    keep_alive: bool,
+    headers: Vec<(String, String)>,
+}
+
+impl RequestHead {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Serve requests until the client closes the connection, or asks for it to be closed
+fn handle_connection<T: Read + Write>(
+    stream: T,
+    tx: &SyncSender<Vec<u8>>,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+    http_config: &HttpConfig,
+) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let head = match read_head(&mut reader) {
+            Ok(Some(head)) => head,
+            Ok(None) => return,
+            Err(e) => {
+                if e.kind() == ErrorKind::InvalidData {
+                    let _ = write_response(reader.get_mut(), BAD_REQUEST, false);
+                }
+                return;
+            }
+        };
+        let (status, keep_alive) = match handle_request(&mut reader, &head, http_config) {
+            Ok(body) => (
+                handle_body(&body, &head, tx, decoder, encoder, http_config),
+                head.keep_alive,
+            ),
+            // The body may not have been read, the connection can't be reused
+            Err(status) => (status, false),
+        };
+        if write_response(reader.get_mut(), status, keep_alive).is_err() || !keep_alive {
+            return;
+        }
+    }
+}
+
+/// Check the request and read its body
+fn handle_request<T: Read + Write>(
+    reader: &mut BufReader<T>,
+    head: &RequestHead,
+    http_config: &HttpConfig,
+) -> Result<Vec<u8>, Status> {
+    if head.method != "POST" {
+        return Err(METHOD_NOT_ALLOWED);
+    }
+    if let Some(ref token) = http_config.token {
+        let authorized = head
+            .header("authorization")
+            .and_then(|x| x.strip_prefix("Bearer "))
+            .map(|x| constant_time_eq(x.trim().as_bytes(), token.as_bytes()))
+            .unwrap_or(false);
+        if !authorized {
+            return Err(UNAUTHORIZED);
+        }
+    }
+    if head.header("transfer-encoding").is_some() {
+        return Err(LENGTH_REQUIRED);
+    }
+    let length: usize = head
+        .header("content-length")
+        .ok_or(LENGTH_REQUIRED)?
+        .parse()
+        .or(Err(BAD_REQUEST))?;
+    if length > http_config.max_body_size {
+        return Err(PAYLOAD_TOO_LARGE);
+    }
+    let expects_continue = head
+        .header("expect")
+        .map(|x| x.eq_ignore_ascii_case("100-continue"))
+        .unwrap_or(false);
+    if expects_continue {
+        let stream = reader.get_mut();
+        stream
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .and_then(|_| stream.flush())
+            .or(Err(BAD_REQUEST))?;
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).or(Err(BAD_REQUEST))?;
+    Ok(body)
+}
+
+/// Decode the records of a body and queue them. Records that can't be decoded are
+/// skipped. If the queue is full, none of the records are queued, so that the client can
+/// retry the request later without duplicating them.
+fn handle_body(
+    body: &[u8],
+    head: &RequestHead,
+    tx: &SyncSender<Vec<u8>>,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+    http_config: &HttpConfig,
+) -> Status {
+    let records = match get_records(body, head, http_config.max_body_size) {
+        Ok(records) => records,
+        Err(status) => return status,
+    };
+    let mut reencoded_records = Vec::with_capacity(records.len());
+    for record in records {
+        match decoder
+            .decode(&record)
+            .and_then(|decoded| encoder.encode(decoded))
+        {
+            Ok(reencoded) => reencoded_records.push(reencoded),
+            Err(e) => {
+                let _ = writeln!(stderr(), "{}: [{}]", e, record.trim());
+            }
+        }
+    }
+    let mut reencoded_records = reencoded_records.into_iter();
+    if let Some(first) = reencoded_records.next() {
+        match tx.try_send(first) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                http_config.throttled.inc();
+                return TOO_MANY_REQUESTS;
+            }
+            Err(TrySendError::Disconnected(_)) => return SERVICE_UNAVAILABLE,
+        }
+    }
+    // Once the first record is queued, the request is accepted as a whole
+    for reencoded in reencoded_records {
+        if tx.send(reencoded).is_err() {
+            return SERVICE_UNAVAILABLE;
+        }
+    }
+    NO_CONTENT
+}
+
+/// Split a body into records. Bodies starting with `[` that can be parsed as a JSON array
+/// hold one record per element, others one record per line.
+fn get_records(body: &[u8], head: &RequestHead, max_size: usize) -> Result<Vec<String>, Status> {
+    let gzipped = match head.header("content-encoding") {
+        None | Some("identity") => body.starts_with(&[0x1f, 0x8b]),
+        Some(encoding) if encoding.eq_ignore_ascii_case("gzip") => true,
+        _ => return Err(UNSUPPORTED_MEDIA_TYPE),
+    };
+    let decompressed;
+    let body = if gzipped {
+        let mut res = Vec::new();
+        GzDecoder::new(body)
+            .take(max_size as u64 + 1)
+            .read_to_end(&mut res)
+            .or(Err(BAD_REQUEST))?;
+        if res.len() > max_size {
+            return Err(PAYLOAD_TOO_LARGE);
+        }
+        decompressed = res;
+        &decompressed
+    } else {
+        body
+    };
+    let body = str::from_utf8(body).or(Err(BAD_REQUEST))?;
+    if body.trim_start().starts_with('[') {
+        if let Ok(Value::Array(values)) = de::from_str::<Value>(body) {
+            return Ok(values
+                .into_iter()
+                .map(|value| match value {
+                    Value::String(record) => record,
+                    value => value.to_string(),
+                })
+                .collect());
+        }
+    }
+    Ok(body
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.is_empty())
+        .map(|line| line.to_owned())
+        .collect())
+}
+
+/// Read a header line, without its line terminator
+fn read_header_line(reader: &mut dyn BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    let read = reader
+        .take(MAX_HEADER_LINE_SIZE as u64)
+        .read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            "Header line too long",
+        ));
+    }
+    line.pop();
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| io::Error::new(ErrorKind::InvalidData, "Invalid header line"))
+}
+
+/// Read the request line and the headers, or return `None` if the connection has been
+/// closed. Header names are lowercased.
+fn read_head(reader: &mut dyn BufRead) -> io::Result<Option<RequestHead>> {
+    let invalid = || io::Error::new(ErrorKind::InvalidData, "Invalid HTTP request");
+    let request_line = loop {
+        match read_header_line(reader)? {
+            None => return Ok(None),
+            Some(ref line) if line.is_empty() => continue,
+            Some(line) => break line,
+        }
+    };
+    let mut parts = request_line.split(' ');
+    let (method, version) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(_target), Some(version)) => (method.to_owned(), version),
+        _ => return Err(invalid()),
+    };
+    let mut keep_alive = match version {
+        "HTTP/1.1" => true,
+        "HTTP/1.0" => false,
+        _ => return Err(invalid()),
+    };
+    let mut headers = Vec::new();
+    loop {
+        let line = read_header_line(reader)?.ok_or_else(invalid)?;
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err(invalid());
+        }
+        let sep = line.find(':').ok_or_else(invalid)?;
+        let name = line[..sep].trim().to_lowercase();
+        let value = line[sep + 1..].trim().to_owned();
+        if name == "connection" {
+            if value.eq_ignore_ascii_case("close") {
+                keep_alive = false;
+            } else if value.eq_ignore_ascii_case("keep-alive") {
+                keep_alive = true;
+            }
+        }
+        headers.push((name, value));
+    }
+    Ok(Some(RequestHead {
+        method,
+        keep_alive,
+        headers,
+    }))
+}
+
+fn write_response(writer: &mut dyn Write, status: Status, keep_alive: bool) -> io::Result<()> {
+    let extra = match status.0 {
+        401 => "WWW-Authenticate: Bearer\r\n",
+        405 => "Allow: POST\r\n",
+        429 | 503 => "Retry-After: 1\r\n",
+        _ => "",
+    };
+    let connection = if keep_alive { "keep-alive" } else { "close" };
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\n{}Content-Length: 0\r\nConnection: {}\r\n\r\n",
+        status.0, status.1, extra, connection
+    )?;
+    writer.flush()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::decoder::RFC5424Decoder;
+    use crate::flowgger::encoder::RFC5424Encoder;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Cursor;
+    use std::sync::mpsc::sync_channel;
+
+    /// A connection reading a request, and recording the response
+    struct Connection {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const LINE: &str = "<23>1 2015-08-05T15:53:45.637Z testhostname appname 69 42 - test";
+
+    fn request(config: &str, request: Vec<u8>, queue_size: usize) -> (String, Vec<Vec<u8>>) {
+        let config = Config::from_string(config).unwrap();
+        let http_config = HttpInput::new(&config).http_config;
+        let (tx, rx) = sync_channel(queue_size);
+        let decoder = RFC5424Decoder::new(&config);
+        let encoder = RFC5424Encoder::new(&config);
+        let mut connection = Connection {
+            request: Cursor::new(request),
+            response: Vec::new(),
+        };
+        handle_connection(&mut connection, &tx, &decoder, &encoder, &http_config);
+        (
+            String::from_utf8(connection.response).unwrap(),
+            rx.try_iter().collect(),
+        )
+    }
+
+    fn post(headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut request = format!(
+            "POST /logs HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n",
+            headers,
+            body.len()
+        )
+        .into_bytes();
+        request.extend_from_slice(body);
+        request
+    }
+
+    #[test]
+    fn test_http_lines() {
+        let body = format!("{}\r\n\n{}\n", LINE, LINE);
+        let mut requests = post("", body.as_bytes());
+        requests.extend(post("Connection: close\r\n", LINE.as_bytes()));
+        let (response, records) = request("", requests, 10);
+        assert_eq!(
+            response,
+            "HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: keep-alive\r\n\r\n\
+             HTTP/1.1 204 No Content\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0], LINE.as_bytes());
+    }
+
+    #[test]
+    fn test_http_json_array_gzip() {
+        let body = format!("[{:?}, {:?}]", LINE, LINE);
+        let mut compressor = GzEncoder::new(Vec::new(), Compression::default());
+        compressor.write_all(body.as_bytes()).unwrap();
+        let body = compressor.finish().unwrap();
+        let (response, records) = request(
+            "",
+            post("Content-Encoding: gzip\r\nExpect: 100-continue\r\n", &body),
+            10,
+        );
+        assert!(response.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 204 "));
+        assert_eq!(records.len(), 2);
+
+        let head = RequestHead {
+            method: "POST".to_owned(),
+            keep_alive: true,
+            headers: Vec::new(),
+        };
+        assert_eq!(
+            get_records(b"[{\"a\":1},\"b\"]", &head, 1024),
+            Ok(vec!["{\"a\":1}".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(
+            get_records(b"[not json]\nx", &head, 1024),
+            Ok(vec!["[not json]".to_owned(), "x".to_owned()])
+        );
+    }
+
+    #[test]
+    fn test_http_errors() {
+        let (response, records) = request("", post("", LINE.as_bytes()), 0);
+        assert!(response.starts_with("HTTP/1.1 429 Too Many Requests\r\nRetry-After: 1\r\n"));
+        assert!(records.is_empty());
+
+        let (response, _) = request("", b"GET / HTTP/1.1\r\n\r\n".to_vec(), 10);
+        assert!(response.starts_with("HTTP/1.1 405 "));
+
+        let (response, _) = request("", b"POST / HTTP/1.1\r\n\r\n".to_vec(), 10);
+        assert!(response.starts_with("HTTP/1.1 411 "));
+
+        let (response, _) = request(
+            "[input]\nhttp_max_body_size = 8",
+            post("", b"123456789"),
+            10,
+        );
+        assert!(response.starts_with("HTTP/1.1 413 "));
+
+        let (response, _) = request("", b"garbage\r\n\r\n".to_vec(), 10);
+        assert!(response.starts_with("HTTP/1.1 400 "));
+    }
+
+    #[test]
+    fn test_http_token() {
+        let config = "[input]\nhttp_token = \"secret\"";
+        let (response, records) = request(config, post("", LINE.as_bytes()), 10);
+        assert!(response.starts_with("HTTP/1.1 401 "));
+        assert!(records.is_empty());
+
+        let (response, records) = request(
+            config,
+            post("Authorization: Bearer secret\r\n", LINE.as_bytes()),
+            10,
+        );
+        assert!(response.starts_with("HTTP/1.1 204 "));
+        assert_eq!(records.len(), 1);
+    }
+}
@@ -1,5 +1,9 @@
 #[cfg(feature = "file")]
 mod file;
+#[cfg(feature = "syslog")]
+mod gelf_chunks;
+#[cfg(feature = "http")]
+mod http_input;
 #[cfg(feature = "redis-input")]
 mod redis_input;
 mod stdin_input;
@@ -8,9 +12,13 @@ mod tcp;
 mod tls;
 #[cfg(feature = "syslog")]
 mod udp_input;
+#[cfg(feature = "unix")]
+mod unix_input;
 
 #[cfg(feature = "file")]
 pub use self::file::FileInput;
+#[cfg(feature = "http")]
+pub use self::http_input::HttpInput;
 #[cfg(feature = "redis-input")]
 pub use self::redis_input::RedisInput;
 pub use self::stdin_input::StdinInput;
@@ -23,6 +31,8 @@ pub use self::tls::tls_input::TlsInput;
 pub use self::tls::tlsco_input::TlsCoInput;
 #[cfg(feature = "syslog")]
 pub use self::udp_input::UdpInput;
+#[cfg(feature = "unix")]
+pub use self::unix_input::UnixInput;
 
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
@@ -2,15 +2,26 @@ use super::Input;
 use crate::flowgger::config::Config;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+use crate::flowgger::utils::delivery;
+use rand::Rng;
 use redis;
-use redis::{Commands, Connection, RedisResult};
+use redis::{Commands, Connection, RedisResult, Value};
 use std::io::{stderr, Write};
-use std::process::exit;
-use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::{channel, Receiver, Sender, SyncSender};
 use std::thread;
+use std::time::{Duration, Instant};
 
 const DEFAULT_CONNECT: &str = "127.0.0.1";
+const DEFAULT_MODE: &str = "list";
 const DEFAULT_QUEUE_KEY: &str = "logs";
+const DEFAULT_RECOVERY_DELAY_INIT: u32 = 100;
+const DEFAULT_RECOVERY_DELAY_MAX: u32 = 30_000;
+const DEFAULT_RECOVERY_PROBE_TIME: u32 = 30_000;
+const DEFAULT_STREAM_CLAIM_IDLE: u32 = 60_000;
+const DEFAULT_STREAM_CONSUMER: &str = "flowgger";
+const DEFAULT_STREAM_FIELD: &str = "message";
+const DEFAULT_STREAM_GROUP: &str = "flowgger";
+const STREAM_BATCH_SIZE: u32 = 100;
 const DEFAULT_THREADS: u32 = 1;
 
 pub struct RedisInput {
@@ -21,19 +32,44 @@ pub struct RedisInput {
 struct RedisWorker {
     tid: u32,
     config: RedisConfig,
-    redis_cnx: Connection,
     tx: SyncSender<Vec<u8>>,
     decoder: Box<dyn Decoder + Send>,
     encoder: Box<dyn Encoder + Send>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RedisMode {
+    List,
+    Stream,
+    Pubsub,
+}
+
 #[derive(Clone)]
 struct RedisConfig {
     connect: String,
+    mode: RedisMode,
     queue_key: String,
+    channels: Vec<String>,
+    stream_group: String,
+    stream_consumer: String,
+    stream_field: String,
+    stream_claim_idle: u32,
+    recovery_delay_init: u32,
+    recovery_delay_max: u32,
+    recovery_probe_time: u32,
 }
 
 impl RedisInput {
+    /// With `input.redis_mode = "list"`, records are pulled from the
+    /// `input.redis_queue_key` list, and kept in a temporary list until an output has
+    /// delivered them. With `"stream"`, they are read from the `input.redis_queue_key`
+    /// stream by the `input.redis_stream_group` consumer group, and acknowledged once
+    /// delivered. With `"pubsub"`, they are received on the `input.redis_channels`
+    /// channels, that can be patterns. When the connection to the
+    /// Redis server is lost, reconnections are attempted after a random delay, starting
+    /// at `input.redis_recovery_delay_init` milliseconds and growing up to
+    /// `input.redis_recovery_delay_max`. The delay is reset once a connection has
+    /// lasted `input.redis_recovery_probe_time` milliseconds.
     pub fn new(config: &Config) -> RedisInput {
         let connect = config
             .lookup("input.redis_connect")
@@ -42,19 +78,114 @@ impl RedisInput {
                     .expect("input.redis_connect must be an ip:port string")
             })
             .to_owned();
+        let mode = match config.lookup("input.redis_mode").map_or(DEFAULT_MODE, |x| {
+            x.as_str().expect("input.redis_mode must be a string")
+        }) {
+            "list" => RedisMode::List,
+            "stream" => RedisMode::Stream,
+            "pubsub" => RedisMode::Pubsub,
+            _ => panic!("input.redis_mode must be list, stream or pubsub"),
+        };
         let queue_key = config
             .lookup("input.redis_queue_key")
             .map_or(DEFAULT_QUEUE_KEY, |x| {
                 x.as_str().expect("input.redis_queue_key must be a string")
             })
             .to_owned();
+        let channels = match config.lookup("input.redis_channels") {
+            None => vec![queue_key.clone()],
+            Some(channels) => channels
+                .as_array()
+                .expect("input.redis_channels must be a list of channels")
+                .iter()
+                .map(|x| {
+                    x.as_str()
+                        .expect("input.redis_channels must be a list of strings")
+                        .to_owned()
+                })
+                .collect(),
+        };
+        let stream_group = config
+            .lookup("input.redis_stream_group")
+            .map_or(DEFAULT_STREAM_GROUP, |x| {
+                x.as_str()
+                    .expect("input.redis_stream_group must be a string")
+            })
+            .to_owned();
+        let stream_consumer = config
+            .lookup("input.redis_stream_consumer")
+            .map_or(DEFAULT_STREAM_CONSUMER, |x| {
+                x.as_str()
+                    .expect("input.redis_stream_consumer must be a string")
+            })
+            .to_owned();
+        let stream_field = config
+            .lookup("input.redis_stream_field")
+            .map_or(DEFAULT_STREAM_FIELD, |x| {
+                x.as_str()
+                    .expect("input.redis_stream_field must be a string")
+            })
+            .to_owned();
+        let stream_claim_idle =
+            config
+                .lookup("input.redis_stream_claim_idle")
+                .map_or(DEFAULT_STREAM_CLAIM_IDLE, |x| {
+                    x.as_integer()
+                        .expect("input.redis_stream_claim_idle must be an integer")
+                        as u32
+                });
         let threads = config
             .lookup("input.redis_threads")
             .map_or(DEFAULT_THREADS, |x| {
                 x.as_integer()
                     .expect("input.redis_threads must be a 32-bit integer") as u32
             });
-        let redis_config = RedisConfig { connect, queue_key };
+        let recovery_delay_init = config.lookup("input.redis_recovery_delay_init").map_or(
+            DEFAULT_RECOVERY_DELAY_INIT,
+            |x| {
+                x.as_integer()
+                    .filter(|&x| x > 0)
+                    .expect("input.redis_recovery_delay_init must be a positive integer")
+                    as u32
+            },
+        );
+        let recovery_delay_max = config.lookup("input.redis_recovery_delay_max").map_or(
+            DEFAULT_RECOVERY_DELAY_MAX,
+            |x| {
+                x.as_integer()
+                    .filter(|&x| x > 0)
+                    .expect("input.redis_recovery_delay_max must be a positive integer")
+                    as u32
+            },
+        );
+        let recovery_probe_time = config.lookup("input.redis_recovery_probe_time").map_or(
+            DEFAULT_RECOVERY_PROBE_TIME,
+            |x| {
+                x.as_integer()
+                    .filter(|&x| x > 0)
+                    .expect("input.redis_recovery_probe_time must be a positive integer")
+                    as u32
+            },
+        );
+        let redis_config = RedisConfig {
+            connect,
+            mode,
+            queue_key,
+            channels,
+            stream_group,
+            stream_consumer,
+            stream_field,
+            stream_claim_idle,
+            recovery_delay_init,
+            recovery_delay_max,
+            recovery_probe_time,
+        };
+        // Every subscriber receives all the messages published on a channel
+        let threads = if mode == RedisMode::Pubsub {
+            1
+        } else {
+            threads
+        };
         RedisInput {
             config: redis_config,
             threads,
@@ -62,6 +193,57 @@ impl RedisInput {
     }
 }
 
+impl RedisConfig {
+    fn tmp_key(&self, tid: u32) -> String {
+        format!("{}.tmp.{}", self.queue_key, tid)
+    }
+
+    fn consumer_name(&self, tid: u32) -> String {
+        format!("{}-{}", self.stream_consumer, tid)
+    }
+
+    fn connect(&self) -> RedisResult<Connection> {
+        let client = match redis::Client::open(format!("redis://{}/", self.connect).as_ref()) {
+            Err(e) => panic!(
+                "Invalid connection string for the Redis server: [{}], error: {}",
+                self.connect, e
+            ),
+            Ok(client) => client,
+        };
+        client.get_connection()
+    }
+}
+
+/// Randomized exponential backoff between reconnections
+struct Recovery<'a> {
+    config: &'a RedisConfig,
+    delay: f64,
+    last_attempt: Instant,
+}
+
+impl<'a> Recovery<'a> {
+    fn new(config: &'a RedisConfig) -> Recovery<'a> {
+        Recovery {
+            config,
+            delay: f64::from(config.recovery_delay_init),
+            last_attempt: Instant::now(),
+        }
+    }
+
+    /// Wait before the next connection attempt
+    fn wait(&mut self) {
+        if self.last_attempt.elapsed()
+            > Duration::from_millis(u64::from(self.config.recovery_probe_time))
+        {
+            self.delay = f64::from(self.config.recovery_delay_init);
+        } else if self.delay < f64::from(self.config.recovery_delay_max) {
+            self.delay += rand::thread_rng().gen_range(0.0, self.delay);
+        }
+        thread::sleep(Duration::from_millis(self.delay.round() as u64));
+        self.last_attempt = Instant::now();
+    }
+}
+
 impl RedisWorker {
     fn new(
         tid: u32,
@@ -70,55 +252,332 @@ impl RedisWorker {
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) -> RedisWorker {
-        let redis_cnx = match redis::Client::open(format!("redis://{}/", config.connect).as_ref()) {
-            Err(e) => panic!(
-                "Invalid connection string for the Redis server: [{}], error: {}",
-                config.connect, e
-            ),
-            Ok(client) => match client.get_connection() {
-                Err(e) => panic!(
-                    "Unable to connect to the Redis server: [{}], error: {}",
-                    config.connect, e
-                ),
-                Ok(redis_cnx) => redis_cnx,
-            },
-        };
         RedisWorker {
             tid,
             config,
-            redis_cnx,
             tx,
             decoder,
             encoder,
         }
     }
 
-    fn run(self) -> Result<(), String> {
-        let queue_key: &str = &self.config.queue_key;
-        let queue_key_tmp: &str = &format!("{}.tmp.{}", queue_key, self.tid);
-        let redis_cnx = self.redis_cnx;
+    fn run(self) {
+        let ack_tx = if self.config.mode == RedisMode::Pubsub {
+            None
+        } else {
+            let (ack_tx, ack_rx) = channel();
+            let (config, tid) = (self.config.clone(), self.tid);
+            thread::spawn(move || run_acknowledgements(&config, tid, &ack_rx));
+            Some(ack_tx)
+        };
+        let mut recovery = Recovery::new(&self.config);
+        loop {
+            let res = match (self.config.mode, &ack_tx) {
+                (RedisMode::List, Some(ack_tx)) => self.pull_list(ack_tx),
+                (RedisMode::Stream, Some(ack_tx)) => self.pull_stream(ack_tx),
+                _ => self.pull_pubsub(),
+            };
+            if let Err(e) = res {
+                let _ = writeln!(stderr(), "Redis connection lost - {}", e);
+            }
+            recovery.wait();
+            let _ = writeln!(stderr(), "Attempting to reconnect to Redis");
+        }
+    }
+
+    fn connect(&self) -> Result<Connection, String> {
+        let redis_cnx = self.config.connect().map_err(|e| {
+            format!(
+                "Unable to connect to the Redis server: [{}], error: {}",
+                self.config.connect, e
+            )
+        })?;
         println!(
             "Connected to Redis [{}], pulling messages from key [{}]",
-            self.config.connect, queue_key
+            self.config.connect, self.config.queue_key
         );
+        Ok(redis_cnx)
+    }
+
+    /// Decode and queue a record. `ack` is the channel and token used to acknowledge
+    /// it once delivered, or right away if it cannot be decoded.
+    fn enqueue(&self, record: &str, ack: Option<(&Sender<String>, String)>) {
+        let reencoded = self
+            .decoder
+            .decode(record)
+            .and_then(|decoded| self.encoder.encode(decoded));
+        match reencoded {
+            Ok(reencoded) => {
+                if let Some((ack_tx, token)) = ack {
+                    let ack_tx = ack_tx.clone();
+                    delivery::expect(&reencoded, move || {
+                        let _ = ack_tx.send(token);
+                    });
+                }
+                self.tx.send(reencoded).unwrap();
+            }
+            Err(e) => {
+                let _ = writeln!(stderr(), "{}: [{}]", e, record.trim());
+                if let Some((ack_tx, token)) = ack {
+                    let _ = ack_tx.send(token);
+                }
+            }
+        }
+    }
+
+    fn pull_list(&self, ack_tx: &Sender<String>) -> Result<(), String> {
+        let queue_key: &str = &self.config.queue_key;
+        let queue_key_tmp: &str = &self.config.tmp_key(self.tid);
+        let redis_cnx = self.connect()?;
+        // Records left in the temporary list haven't been delivered yet
         while {
             let dummy: RedisResult<String> = redis_cnx.rpoplpush(queue_key_tmp, queue_key);
             dummy.is_ok()
         } {}
-        let (decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) = (self.decoder, self.encoder);
         loop {
             let line: String = match redis_cnx.brpoplpush(queue_key, queue_key_tmp, 0) {
                 Err(e) => return Err(format!("Redis protocol error in BRPOPLPUSH: [{}]", e)),
                 Ok(line) => line,
             };
-            if let Err(e) = handle_record(&line, &self.tx, &decoder, &encoder) {
-                let _ = writeln!(stderr(), "{}: [{}]", e, line.trim());
+            // The record is removed from the temporary list once delivered
+            self.enqueue(&line, Some((ack_tx, line.clone())));
+        }
+    }
+
+    fn pull_stream(&self, ack_tx: &Sender<String>) -> Result<(), String> {
+        let config = &self.config;
+        let consumer = config.consumer_name(self.tid);
+        let redis_cnx = self.connect()?;
+        let res: RedisResult<()> = redis::cmd("XGROUP")
+            .arg("CREATE")
+            .arg(&config.queue_key)
+            .arg(&config.stream_group)
+            .arg("0")
+            .arg("MKSTREAM")
+            .query(&redis_cnx);
+        match res {
+            Err(ref e) if e.extension_error_code() != Some("BUSYGROUP") => {
+                return Err(format!("Redis protocol error in XGROUP: [{}]", e))
             }
-            let res: RedisResult<u8> = redis_cnx.lrem(queue_key_tmp as &str, 1, line as String);
-            if let Err(e) = res {
-                return Err(format!("Redis protocol error in LREM: [{}]", e));
+            _ => {}
+        }
+
+        // Entries previously delivered to this consumer, but not acknowledged yet
+        let mut last_id = "0".to_owned();
+        loop {
+            let reply: Value = redis::cmd("XREADGROUP")
+                .arg("GROUP")
+                .arg(&config.stream_group)
+                .arg(&consumer)
+                .arg("COUNT")
+                .arg(STREAM_BATCH_SIZE)
+                .arg("STREAMS")
+                .arg(&config.queue_key)
+                .arg(&last_id)
+                .query(&redis_cnx)
+                .map_err(|e| format!("Redis protocol error in XREADGROUP: [{}]", e))?;
+            let entries = stream_entries(read_reply_entries(&reply), &config.stream_field);
+            match entries.last() {
+                None => break,
+                Some((id, _)) => last_id = id.clone(),
+            }
+            self.enqueue_entries(entries, ack_tx);
+        }
+
+        // Entries left pending by consumers that went away
+        let mut cursor = "0-0".to_owned();
+        loop {
+            let reply: Value = redis::cmd("XAUTOCLAIM")
+                .arg(&config.queue_key)
+                .arg(&config.stream_group)
+                .arg(&consumer)
+                .arg(config.stream_claim_idle)
+                .arg(&cursor)
+                .arg("COUNT")
+                .arg(STREAM_BATCH_SIZE)
+                .query(&redis_cnx)
+                .map_err(|e| format!("Redis protocol error in XAUTOCLAIM: [{}]", e))?;
+            let (next_cursor, entries) = match reply {
+                Value::Bulk(ref parts) if parts.len() >= 2 => (
+                    value_to_string(&parts[0]),
+                    stream_entries(Some(&parts[1]), &config.stream_field),
+                ),
+                _ => return Err("Unexpected reply to XAUTOCLAIM".to_owned()),
+            };
+            self.enqueue_entries(entries, ack_tx);
+            match next_cursor {
+                Some(ref next_cursor) if next_cursor != "0-0" => cursor = next_cursor.clone(),
+                _ => break,
+            }
+        }
+
+        loop {
+            let reply: Value = redis::cmd("XREADGROUP")
+                .arg("GROUP")
+                .arg(&config.stream_group)
+                .arg(&consumer)
+                .arg("COUNT")
+                .arg(STREAM_BATCH_SIZE)
+                .arg("BLOCK")
+                .arg(0)
+                .arg("STREAMS")
+                .arg(&config.queue_key)
+                .arg(">")
+                .query(&redis_cnx)
+                .map_err(|e| format!("Redis protocol error in XREADGROUP: [{}]", e))?;
+            let entries = stream_entries(read_reply_entries(&reply), &config.stream_field);
+            self.enqueue_entries(entries, ack_tx);
+        }
+    }
+
+    /// Entries are acknowledged with XACK once delivered. Entries without a record are
+    /// acknowledged right away.
+    fn enqueue_entries(&self, entries: Vec<(String, Option<String>)>, ack_tx: &Sender<String>) {
+        for (id, record) in entries {
+            match record {
+                Some(record) => self.enqueue(&record, Some((ack_tx, id))),
+                None => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Missing or invalid field [{}] in stream entry [{}]",
+                        self.config.stream_field,
+                        id
+                    );
+                    let _ = ack_tx.send(id);
+                }
+            }
+        }
+    }
+
+    fn pull_pubsub(&self) -> Result<(), String> {
+        let mut redis_cnx = self.connect()?;
+        let mut pubsub = redis_cnx.as_pubsub();
+        for channel in &self.config.channels {
+            let res = if is_pattern(channel) {
+                pubsub.psubscribe(channel)
+            } else {
+                pubsub.subscribe(channel)
+            };
+            res.map_err(|e| format!("Unable to subscribe to [{}]: [{}]", channel, e))?;
+        }
+        loop {
+            let msg = pubsub
+                .get_message()
+                .map_err(|e| format!("Redis protocol error in pub/sub: [{}]", e))?;
+            match msg.get_payload::<String>() {
+                Ok(record) => self.enqueue(&record, None),
+                Err(_) => {
+                    let _ = writeln!(
+                        stderr(),
+                        "Invalid UTF-8 message on channel [{}]",
+                        msg.get_channel_name()
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Channel names with glob-style characters are subscribed to as patterns
+fn is_pattern(channel: &str) -> bool {
+    channel.contains(['*', '?', '['])
+}
+
+fn value_to_string(value: &Value) -> Option<String> {
+    match *value {
+        Value::Data(ref data) => String::from_utf8(data.clone()).ok(),
+        Value::Status(ref status) => Some(status.clone()),
+        _ => None,
+    }
+}
+
+/// The entries of the only stream read by XREADGROUP, or `None` on timeout
+fn read_reply_entries(reply: &Value) -> Option<&Value> {
+    match *reply {
+        Value::Bulk(ref streams) => match streams.first() {
+            Some(Value::Bulk(ref stream)) => stream.get(1),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Extract the identifier and the `field` value of stream entries. Deleted entries
+/// have no value.
+fn stream_entries(entries: Option<&Value>, field: &str) -> Vec<(String, Option<String>)> {
+    let entries = match entries {
+        Some(Value::Bulk(ref entries)) => entries,
+        _ => return Vec::new(),
+    };
+    let mut res = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let entry = match *entry {
+            Value::Bulk(ref entry) if !entry.is_empty() => entry,
+            _ => continue,
+        };
+        let id = match value_to_string(&entry[0]) {
+            Some(id) => id,
+            None => continue,
+        };
+        let record = match entry.get(1) {
+            Some(Value::Bulk(ref pairs)) => pairs
+                .chunks(2)
+                .find(|pair| pair.len() == 2 && value_to_string(&pair[0]).as_deref() == Some(field))
+                .and_then(|pair| value_to_string(&pair[1])),
+            _ => None,
+        };
+        res.push((id, record));
+    }
+    res
+}
+
+/// Acknowledge the delivered records of worker `tid`, using a dedicated connection.
+/// Records are removed from the temporary list in list mode, and acknowledged with
+/// XACK in stream mode.
+fn run_acknowledgements(config: &RedisConfig, tid: u32, ack_rx: &Receiver<String>) {
+    let queue_key_tmp = config.tmp_key(tid);
+    let mut recovery = Recovery::new(config);
+    let mut pending = None;
+    loop {
+        let redis_cnx = match config.connect() {
+            Ok(redis_cnx) => redis_cnx,
+            Err(e) => {
+                let _ = writeln!(
+                    stderr(),
+                    "Unable to connect to Redis to acknowledge records - {}",
+                    e
+                );
+                recovery.wait();
+                continue;
+            }
+        };
+        loop {
+            let token = match pending.take() {
+                Some(token) => token,
+                None => match ack_rx.recv() {
+                    Ok(token) => token,
+                    Err(_) => return,
+                },
+            };
+            let res: RedisResult<u8> = match config.mode {
+                RedisMode::Stream => redis::cmd("XACK")
+                    .arg(&config.queue_key)
+                    .arg(&config.stream_group)
+                    .arg(&token)
+                    .query(&redis_cnx),
+                _ => redis_cnx.lrem(&queue_key_tmp, 1, &token),
             };
+            if let Err(e) = res {
+                let _ = writeln!(
+                    stderr(),
+                    "Redis protocol error while acknowledging: [{}]",
+                    e
+                );
+                pending = Some(token);
+                break;
+            }
         }
+        recovery.wait();
     }
 }
 
@@ -135,29 +594,90 @@ impl Input for RedisInput {
             let (encoder, decoder) = (encoder.clone_boxed(), decoder.clone_boxed());
             let tx = tx.clone();
             jids.push(thread::spawn(move || {
-                let worker = RedisWorker::new(tid, config, tx, decoder, encoder);
-                if let Err(e) = worker.run() {
-                    let _ = writeln!(stderr(), "Redis connection lost, aborting - {}", e);
-                }
-                exit(1);
+                RedisWorker::new(tid, config, tx, decoder, encoder).run();
             }));
         }
         for jid in jids {
             if jid.join().is_err() {
-                panic!("Redis connection lost");
+                panic!("Redis worker terminated");
             }
         }
     }
 }
 
-fn handle_record(
-    line: &str,
-    tx: &SyncSender<Vec<u8>>,
-    decoder: &Box<dyn Decoder>,
-    encoder: &Box<dyn Encoder>,
-) -> Result<(), &'static str> {
-    let decoded = decoder.decode(line)?;
-    let reencoded = encoder.encode(decoded)?;
-    tx.send(reencoded).unwrap();
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_redis_recovery() {
+        let config = Config::from_string(
+            "[input]\nredis_recovery_delay_init = 1\nredis_recovery_delay_max = 4\n\
+             redis_recovery_probe_time = 60000",
+        )
+        .unwrap();
+        let input = RedisInput::new(&config);
+        assert_eq!(input.config.tmp_key(3), "logs.tmp.3");
+        let mut recovery = Recovery::new(&input.config);
+        for _ in 0..30 {
+            recovery.wait();
+            assert!(recovery.delay < 8.0);
+        }
+        assert!(recovery.delay >= 4.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "input.redis_recovery_delay_init must be a positive integer")]
+    fn test_redis_recovery_zero_delay() {
+        let config = Config::from_string("[input]\nredis_recovery_delay_init = 0").unwrap();
+        RedisInput::new(&config);
+    }
+
+    fn data(s: &str) -> Value {
+        Value::Data(s.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_redis_stream_entries() {
+        let reply = Value::Bulk(vec![Value::Bulk(vec![
+            data("logs"),
+            Value::Bulk(vec![
+                Value::Bulk(vec![
+                    data("1-0"),
+                    Value::Bulk(vec![data("host"), data("a"), data("message"), data("x")]),
+                ]),
+                Value::Bulk(vec![
+                    data("2-0"),
+                    Value::Bulk(vec![data("host"), data("b")]),
+                ]),
+                Value::Bulk(vec![data("3-0"), Value::Nil]),
+                Value::Nil,
+            ]),
+        ])]);
+        let entries = stream_entries(read_reply_entries(&reply), "message");
+        assert_eq!(
+            entries,
+            vec![
+                ("1-0".to_owned(), Some("x".to_owned())),
+                ("2-0".to_owned(), None),
+                ("3-0".to_owned(), None),
+            ]
+        );
+        assert!(stream_entries(read_reply_entries(&Value::Nil), "message").is_empty());
+        assert!(is_pattern("logs.*"));
+        assert!(!is_pattern("logs"));
+    }
+
+    #[test]
+    fn test_redis_mode() {
+        let config = Config::from_string(
+            "[input]\nredis_mode = \"pubsub\"\nredis_threads = 4\nredis_channels = [\"a\", \"b.*\"]",
+        )
+        .unwrap();
+        let input = RedisInput::new(&config);
+        assert_eq!(input.config.mode, RedisMode::Pubsub);
+        assert_eq!(input.config.channels, vec!["a", "b.*"]);
+        assert_eq!(input.threads, 1);
+        assert_eq!(input.config.consumer_name(2), "flowgger-2");
+    }
 }
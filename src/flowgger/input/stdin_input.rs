@@ -4,7 +4,9 @@ use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
 #[cfg(feature = "capnp-recompile")]
 use crate::flowgger::splitter::CapnpSplitter;
-use crate::flowgger::splitter::{LineSplitter, NulSplitter, Splitter, SyslenSplitter};
+use crate::flowgger::splitter::{
+    get_line_splitter, MultilineConfig, NulSplitter, Splitter, SyslenSplitter,
+};
 use std::io::{stdin, BufReader};
 use std::sync::mpsc::SyncSender;
 
@@ -13,6 +15,7 @@ const DEFAULT_FRAMING: &str = "line";
 #[derive(Clone)]
 pub struct StdinConfig {
     framing: String,
+    multiline: Option<MultilineConfig>,
 }
 
 pub struct StdinInput {
@@ -20,15 +23,22 @@ pub struct StdinInput {
 }
 
 impl StdinInput {
+    /// Cap'n Proto records are framed on their own, so `input.framing` defaults to
+    /// `capnp` when `input.format` is `capnp`
     pub fn new(config: &Config) -> StdinInput {
+        let default_framing = match config.lookup("input.format").and_then(|x| x.as_str()) {
+            Some("capnp") => "capnp",
+            _ => DEFAULT_FRAMING,
+        };
         let framing = config
             .lookup("input.framing")
-            .map_or(DEFAULT_FRAMING, |x| {
+            .map_or(default_framing, |x| {
                 x.as_str()
                     .expect(r#"input.framing must be a string set to "line", "nul" or "syslen""#)
             })
             .to_owned();
-        let stdin_config = StdinConfig { framing };
+        let multiline = MultilineConfig::new(config);
+        let stdin_config = StdinConfig { framing, multiline };
         StdinInput { stdin_config }
     }
 }
@@ -56,7 +66,7 @@ impl Input for StdinInput {
         let reader = BufReader::new(stdin());
         let splitter = match &self.stdin_config.framing as &str {
             "capnp" => get_capnp_splitter(),
-            "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
+            "line" => get_line_splitter(&self.stdin_config.multiline),
             "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
             "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
             _ => panic!("Unsupported framing scheme"),
@@ -64,3 +74,18 @@ impl Input for StdinInput {
         splitter.run(reader, tx, decoder, encoder);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stdin_framing() {
+        let config = Config::from_string("[input]\nformat = \"capnp\"").unwrap();
+        assert_eq!(StdinInput::new(&config).stdin_config.framing, "capnp");
+        let config = Config::from_string("[input]\nformat = \"gelf\"").unwrap();
+        assert_eq!(StdinInput::new(&config).stdin_config.framing, "line");
+        let config = Config::from_string("[input]\nformat = \"capnp\"\nframing = \"nul\"").unwrap();
+        assert_eq!(StdinInput::new(&config).stdin_config.framing, "nul");
+    }
+}
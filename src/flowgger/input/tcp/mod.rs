@@ -1,4 +1,5 @@
 use crate::flowgger::config::Config;
+use crate::flowgger::splitter::MultilineConfig;
 
 pub mod tcp_input;
 #[cfg(feature = "coroutines")]
@@ -7,6 +8,7 @@ pub mod tcpco_input;
 pub use super::Input;
 
 const DEFAULT_FRAMING: &str = "line";
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1_048_576;
 const DEFAULT_LISTEN: &str = "0.0.0.0:514";
 #[cfg(feature = "coroutines")]
 const DEFAULT_THREADS: usize = 1;
@@ -15,7 +17,9 @@ const DEFAULT_TIMEOUT: u64 = 3600;
 #[derive(Clone)]
 pub struct TcpConfig {
     framing: String,
+    max_message_size: usize,
     threads: usize,
+    multiline: Option<MultilineConfig>,
 }
 
 #[cfg(feature = "coroutines")]
@@ -55,10 +59,25 @@ pub fn config_parse(config: &Config) -> (TcpConfig, String, u64) {
     let framing = config
         .lookup("input.framing")
         .map_or(framing, |x| {
-            x.as_str()
-                .expect(r#"input.framing must be a string set to "line", "nul" or "syslen""#)
+            x.as_str().expect(
+                r#"input.framing must be a string set to "line", "nul", "syslen" or "auto""#,
+            )
         })
         .to_owned();
-    let tcp_config = TcpConfig { framing, threads };
+    let max_message_size =
+        config
+            .lookup("input.max_message_size")
+            .map_or(DEFAULT_MAX_MESSAGE_SIZE, |x| {
+                x.as_integer()
+                    .expect("input.max_message_size must be a size integer")
+                    as usize
+            });
+    let multiline = MultilineConfig::new(config);
+    let tcp_config = TcpConfig {
+        framing,
+        max_message_size,
+        threads,
+        multiline,
+    };
     (tcp_config, listen, timeout)
 }
@@ -1,4 +1,5 @@
 use crate::flowgger::config::Config;
+use crate::flowgger::splitter::MultilineConfig;
 use openssl::bn::BigNum;
 use openssl::dh::Dh;
 use openssl::ssl::*;
@@ -22,6 +23,7 @@ const DEFAULT_CIPHERS: &str =
      !EDH-DSS-DES-CBC3-SHA:!EDH-RSA-DES-CBC3-SHA:!KRB5-DES-CBC3-SHA";
 const DEFAULT_COMPRESSION: bool = false;
 const DEFAULT_FRAMING: &str = "line";
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1_048_576;
 const DEFAULT_KEY: &str = "flowgger.pem";
 const DEFAULT_LISTEN: &str = "0.0.0.0:6514";
 #[cfg(feature = "coroutines")]
@@ -34,8 +36,10 @@ const TLS_VERIFY_DEPTH: u32 = 6;
 #[derive(Clone)]
 pub struct TlsConfig {
     framing: String,
+    max_message_size: usize,
     threads: usize,
-    acceptor: SslAcceptor,
+    pub(super) acceptor: SslAcceptor,
+    multiline: Option<MultilineConfig>,
 }
 
 fn set_fs(ctx: &mut SslContextBuilder) {
@@ -138,8 +142,9 @@ pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
     let framing = config
         .lookup("input.framing")
         .map_or(framing, |x| {
-            x.as_str()
-                .expect(r#"input.framing must be a string set to "line", "nul" or "syslen""#)
+            x.as_str().expect(
+                r#"input.framing must be a string set to "line", "nul", "syslen" or "auto""#,
+            )
         })
         .to_owned();
     let mut acceptor_builder = (if tls_modern {
@@ -175,10 +180,21 @@ pub fn config_parse(config: &Config) -> (TlsConfig, String, u64) {
             .expect("Unsupported cipher suite");
     }
     let acceptor = acceptor_builder.build();
+    let max_message_size =
+        config
+            .lookup("input.max_message_size")
+            .map_or(DEFAULT_MAX_MESSAGE_SIZE, |x| {
+                x.as_integer()
+                    .expect("input.max_message_size must be a size integer")
+                    as usize
+            });
+    let multiline = MultilineConfig::new(config);
     let tls_config = TlsConfig {
         framing,
+        max_message_size,
         threads,
         acceptor,
+        multiline,
     };
     (tls_config, listen, timeout)
 }
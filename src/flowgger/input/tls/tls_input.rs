@@ -4,7 +4,9 @@ use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
 #[cfg(feature = "capnp-recompile")]
 use crate::flowgger::splitter::CapnpSplitter;
-use crate::flowgger::splitter::{LineSplitter, NulSplitter, Splitter, SyslenSplitter};
+use crate::flowgger::splitter::{
+    get_line_splitter, AutoSplitter, NulSplitter, Splitter, SyslenSplitter,
+};
 use std::io::{stderr, BufReader, Write};
 use std::net::{TcpListener, TcpStream};
 use std::sync::mpsc::SyncSender;
@@ -83,9 +85,10 @@ fn handle_client(
     let reader = BufReader::new(sslclient);
     let splitter = match &tls_config.framing as &str {
         "capnp" => get_capnp_splitter(),
-        "line" => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
+        "line" => get_line_splitter(&tls_config.multiline),
         "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
         "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+        "auto" => Box::new(AutoSplitter::new(tls_config.max_message_size)) as Box<dyn Splitter<_>>,
         _ => panic!("Unsupported framing scheme"),
     };
     splitter.run(reader, tx, decoder, encoder);
@@ -3,7 +3,7 @@ use crate::flowgger::config::Config;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
 use crate::flowgger::splitter::{
-    CapnpSplitter, LineSplitter, NulSplitter, Splitter, SyslenSplitter,
+    AutoSplitter, CapnpSplitter, LineSplitter, NulSplitter, Splitter, SyslenSplitter,
 };
 use may::net::{TcpListener, TcpStream};
 use std::io::{stderr, BufReader, Write};
@@ -69,6 +69,7 @@ fn handle_client(
         "line" => Box::new(LineSplitter) as Box<Splitter<_>>,
         "syslen" => Box::new(SyslenSplitter) as Box<Splitter<_>>,
         "nul" => Box::new(NulSplitter) as Box<Splitter<_>>,
+        "auto" => Box::new(AutoSplitter::new(tls_config.max_message_size)) as Box<Splitter<_>>,
         _ => panic!("Unsupported framing scheme"),
     };
     splitter.run(reader, tx, decoder, encoder);
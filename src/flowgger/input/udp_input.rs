@@ -1,17 +1,29 @@
+use super::gelf_chunks::{self, ChunkAssembler};
 use super::Input;
 use crate::flowgger::config::Config;
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
+#[cfg(target_os = "linux")]
+use crate::flowgger::utils::metrics;
 use flate2::read::{GzDecoder, ZlibDecoder};
-use std::io::{stderr, Read, Write};
+use std::io::{self, stderr, Read, Write};
 use std::net::SocketAddr;
 use std::net::UdpSocket;
-use std::str;
 use std::sync::mpsc::SyncSender;
+use std::thread;
+use std::time::Duration;
+
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, FromRawFd};
 
 const DEFAULT_LISTEN: &str = "0.0.0.0:514";
+const DEFAULT_THREADS: u32 = 1;
+const DEFAULT_BATCH_SIZE: usize = 1;
 const MAX_UDP_PACKET_SIZE: usize = 65_527;
 const MAX_COMPRESSION_RATIO: usize = 5;
+const EXPIRATION_INTERVAL: Duration = Duration::from_secs(1);
+#[cfg(target_os = "linux")]
+const KERNEL_DROPS_INTERVAL: Duration = Duration::from_secs(5);
 
 /// UDP input structure for flowgger
 /// It will receive messages from the network, decode them and reencoded them as configured
@@ -20,6 +32,10 @@ const MAX_COMPRESSION_RATIO: usize = 5;
 /// [`Config`]: ../config/struct.Config.html
 pub struct UdpInput {
     listen: SocketAddr,
+    threads: u32,
+    batch_size: usize,
+    rcvbuf: Option<usize>,
+    gelf_chunks: ChunkAssembler,
 }
 
 impl UdpInput {
@@ -33,6 +49,15 @@ impl UdpInput {
     /// # Panic
     /// `input.listen must be an ip:port string`:  input.listen is not parsable as a string
     /// `Unable to parse ip:port string from input.listen` input.listen is not a valid ip:port
+    ///
+    /// Chunked GELF messages are reassembled according to `input.gelf_chunk_timeout` and
+    /// `input.gelf_chunks_max_bytes`, that is shared by the threads
+    ///
+    /// `input.udp_threads` sockets are bound to the same address with `SO_REUSEPORT`, each
+    /// of them being read by its own thread. A single thread is used on platforms without
+    /// `SO_REUSEPORT`. On Linux, up to `input.udp_batch_size`
+    /// datagrams are received at once with `recvmmsg`. `input.udp_rcvbuf` sets the size of
+    /// the kernel receive buffer of the sockets.
     pub fn new(config: &Config) -> UdpInput {
         let listen = config
             .lookup("input.listen")
@@ -43,15 +68,40 @@ impl UdpInput {
         let bind_address: SocketAddr = listen
             .parse()
             .expect("unable to parse ip:port string from input.listen");
+        let threads = config
+            .lookup("input.udp_threads")
+            .map_or(DEFAULT_THREADS, |x| {
+                x.as_integer()
+                    .filter(|&x| x > 0)
+                    .expect("input.udp_threads must be a positive integer") as u32
+            });
+        let batch_size = config
+            .lookup("input.udp_batch_size")
+            .map_or(DEFAULT_BATCH_SIZE, |x| {
+                x.as_integer()
+                    .filter(|&x| x > 0 && x <= 1024)
+                    .expect("input.udp_batch_size must be an integer between 1 and 1024")
+                    as usize
+            });
+        let rcvbuf = config.lookup("input.udp_rcvbuf").map(|x| {
+            x.as_integer()
+                .filter(|&x| x > 0 && x <= i64::from(i32::MAX))
+                .expect("input.udp_rcvbuf must be a positive number of bytes") as usize
+        });
         UdpInput {
             listen: bind_address,
+            threads,
+            batch_size,
+            rcvbuf,
+            gelf_chunks: ChunkAssembler::new(config),
         }
     }
 }
 
 impl Input for UdpInput {
-    /// Bind a [`UdpSocket`][] to the configured listen address and starts a loop for accepting
-    /// incoming upd packets
+    /// Bind [`UdpSocket`][]s to the configured listen address and starts a thread per socket
+    /// for accepting incoming upd packets. GELF chunks are buffered until the whole message
+    /// has been received, and decompressed afterwards.
     ///
     /// [`UdpSocket`]: https://doc.rust-lang.org/std/net/struct.UdpSocket.html
     ///
@@ -69,23 +119,341 @@ impl Input for UdpInput {
         decoder: Box<dyn Decoder + Send>,
         encoder: Box<dyn Encoder + Send>,
     ) {
-        let socket = UdpSocket::bind(&self.listen)
-            .unwrap_or_else(|_| panic!("Unable to listen to {}", self.listen));
-        let tx = tx.clone();
-        let (decoder, encoder): (Box<dyn Decoder>, Box<dyn Encoder>) =
-            (decoder.clone_boxed(), encoder.clone_boxed());
+        let sockets = bind_sockets(&self.listen, self.threads)
+            .unwrap_or_else(|e| panic!("Unable to listen to {}: {}", self.listen, e));
+        #[cfg(target_os = "linux")]
+        start_kernel_drops_monitor(&sockets);
+        let threads = sockets.len();
+        let mut jids = Vec::new();
+        for socket in sockets {
+            if let Some(rcvbuf) = self.rcvbuf {
+                set_rcvbuf(&socket, rcvbuf);
+            }
+            // Wake up regularly to drop the chunked messages that can't be completed any more
+            let _ = socket.set_read_timeout(Some(EXPIRATION_INTERVAL));
+            let tx = tx.clone();
+            let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+            let gelf_chunks = self.gelf_chunks.for_thread(threads);
+            let batch_size = self.batch_size;
+            jids.push(thread::spawn(move || {
+                let worker = UdpWorker {
+                    tx,
+                    decoder,
+                    encoder,
+                    gelf_chunks,
+                };
+                worker.run(&socket, batch_size);
+            }));
+        }
+        for jid in jids {
+            if jid.join().is_err() {
+                panic!("UDP worker terminated");
+            }
+        }
+    }
+}
+
+struct UdpWorker {
+    tx: SyncSender<Vec<u8>>,
+    decoder: Box<dyn Decoder>,
+    encoder: Box<dyn Encoder>,
+    gelf_chunks: ChunkAssembler,
+}
+
+impl UdpWorker {
+    #[cfg(target_os = "linux")]
+    fn run(mut self, socket: &UdpSocket, batch_size: usize) {
+        if batch_size <= 1 {
+            return self.run_single(socket);
+        }
+        let mut buf = vec![0; MAX_UDP_PACKET_SIZE * batch_size];
+        let mut lengths = vec![0; batch_size];
+        loop {
+            let count = match recv_batch(socket, &mut buf, &mut lengths) {
+                Ok(count) => count,
+                Err(_) => {
+                    self.gelf_chunks.expire();
+                    continue;
+                }
+            };
+            for (chunk, &length) in buf.chunks(MAX_UDP_PACKET_SIZE).zip(&lengths[..count]) {
+                self.handle_datagram(&chunk[..length]);
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn run(self, socket: &UdpSocket, _batch_size: usize) {
+        self.run_single(socket)
+    }
+
+    fn run_single(mut self, socket: &UdpSocket) {
         let mut buf = [0; MAX_UDP_PACKET_SIZE];
         loop {
             let (length, _src) = match socket.recv_from(&mut buf) {
                 Ok(res) => res,
-                Err(_) => continue,
+                Err(_) => {
+                    self.gelf_chunks.expire();
+                    continue;
+                }
             };
-            let line = &buf[..length];
-            if let Err(e) = handle_record_maybe_compressed(line, &tx, &decoder, &encoder) {
-                let _ = writeln!(stderr(), "{}", e);
+            self.handle_datagram(&buf[..length]);
+        }
+    }
+
+    fn handle_datagram(&mut self, datagram: &[u8]) {
+        let reassembled;
+        let mut line = datagram;
+        if gelf_chunks::is_chunk(line) {
+            match self.gelf_chunks.push(line) {
+                Ok(Some(message)) => {
+                    reassembled = message;
+                    line = &reassembled;
+                }
+                Ok(None) => return,
+                Err(e) => {
+                    let _ = writeln!(stderr(), "{}", e);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = handle_record_maybe_compressed(line, &self.tx, &self.decoder, &self.encoder)
+        {
+            let _ = writeln!(stderr(), "{}", e);
+        }
+    }
+}
+
+/// Bind `threads` sockets to `listen`. Additional sockets share the address of the first
+/// one with `SO_REUSEPORT`, and the kernel spreads the datagrams across them.
+#[cfg(unix)]
+fn bind_sockets(listen: &SocketAddr, threads: u32) -> io::Result<Vec<UdpSocket>> {
+    if threads <= 1 {
+        return Ok(vec![UdpSocket::bind(listen)?]);
+    }
+    let first = bind_reuseport(listen)?;
+    // With port 0, all the sockets have to use the port picked for the first one
+    let listen = first.local_addr()?;
+    let mut sockets = vec![first];
+    for _ in 1..threads {
+        sockets.push(bind_reuseport(&listen)?);
+    }
+    Ok(sockets)
+}
+
+/// Without `SO_REUSEPORT`, a single socket is read by a single thread, so that the chunks
+/// of a GELF message are all reassembled by the same thread
+#[cfg(not(unix))]
+fn bind_sockets(listen: &SocketAddr, threads: u32) -> io::Result<Vec<UdpSocket>> {
+    if threads > 1 {
+        let _ = writeln!(
+            stderr(),
+            "input.udp_threads is ignored without SO_REUSEPORT, using a single thread"
+        );
+    }
+    Ok(vec![UdpSocket::bind(listen)?])
+}
+
+#[cfg(unix)]
+fn bind_reuseport(listen: &SocketAddr) -> io::Result<UdpSocket> {
+    let domain = match listen {
+        SocketAddr::V4(_) => libc::AF_INET,
+        SocketAddr::V6(_) => libc::AF_INET6,
+    };
+    let fd = unsafe { libc::socket(domain, libc::SOCK_DGRAM, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // The socket is closed on error once owned by the UdpSocket
+    let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+    set_socket_option(&socket, libc::SO_REUSEPORT, 1)?;
+    let res = match listen {
+        SocketAddr::V4(addr) => {
+            let mut sin: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+            sin.sin_family = libc::AF_INET as libc::sa_family_t;
+            sin.sin_port = addr.port().to_be();
+            sin.sin_addr.s_addr = u32::from_ne_bytes(addr.ip().octets());
+            unsafe {
+                libc::bind(
+                    fd,
+                    &sin as *const _ as *const libc::sockaddr,
+                    std::mem::size_of_val(&sin) as libc::socklen_t,
+                )
+            }
+        }
+        SocketAddr::V6(addr) => {
+            let mut sin6: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+            sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sin6.sin6_port = addr.port().to_be();
+            sin6.sin6_flowinfo = addr.flowinfo();
+            sin6.sin6_addr.s6_addr = addr.ip().octets();
+            sin6.sin6_scope_id = addr.scope_id();
+            unsafe {
+                libc::bind(
+                    fd,
+                    &sin6 as *const _ as *const libc::sockaddr,
+                    std::mem::size_of_val(&sin6) as libc::socklen_t,
+                )
+            }
+        }
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+#[cfg(unix)]
+fn set_socket_option(
+    socket: &UdpSocket,
+    option: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &value as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&value) as libc::socklen_t,
+        )
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The kernel caps the receive buffer to `net.core.rmem_max`, which has to be raised for
+/// larger buffers
+#[cfg(unix)]
+fn set_rcvbuf(socket: &UdpSocket, rcvbuf: usize) {
+    if let Err(e) = set_socket_option(socket, libc::SO_RCVBUF, rcvbuf as libc::c_int) {
+        let _ = writeln!(stderr(), "Unable to set the UDP receive buffer size: {}", e);
+        return;
+    }
+    let mut actual: libc::c_int = 0;
+    let mut len = std::mem::size_of_val(&actual) as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_RCVBUF,
+            &mut actual as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    // Linux reports twice the requested size, to account for its bookkeeping overhead
+    if res == 0 && (actual as usize) < rcvbuf {
+        let _ = writeln!(
+            stderr(),
+            "The UDP receive buffer is {} bytes instead of {}, net.core.rmem_max may be too low",
+            actual,
+            rcvbuf
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn set_rcvbuf(_socket: &UdpSocket, _rcvbuf: usize) {
+    let _ = writeln!(
+        stderr(),
+        "input.udp_rcvbuf is not supported on this platform"
+    );
+}
+
+/// Receive up to `lengths.len()` datagrams at once. Datagram `i` is stored at offset
+/// `i * MAX_UDP_PACKET_SIZE` of `buf`, and its length in `lengths[i]`. The call blocks
+/// until at least one datagram has been received, or the read timeout expires.
+#[cfg(target_os = "linux")]
+fn recv_batch(socket: &UdpSocket, buf: &mut [u8], lengths: &mut [usize]) -> io::Result<usize> {
+    let mut iovecs: Vec<libc::iovec> = buf
+        .chunks_mut(MAX_UDP_PACKET_SIZE)
+        .take(lengths.len())
+        .map(|chunk| libc::iovec {
+            iov_base: chunk.as_mut_ptr() as *mut libc::c_void,
+            iov_len: chunk.len(),
+        })
+        .collect();
+    let mut msgs: Vec<libc::mmsghdr> = iovecs
+        .iter_mut()
+        .map(|iov| {
+            let mut msg: libc::mmsghdr = unsafe { std::mem::zeroed() };
+            msg.msg_hdr.msg_iov = iov;
+            msg.msg_hdr.msg_iovlen = 1;
+            msg
+        })
+        .collect();
+    let count = unsafe {
+        libc::recvmmsg(
+            socket.as_raw_fd(),
+            msgs.as_mut_ptr(),
+            msgs.len() as libc::c_uint,
+            libc::MSG_WAITFORONE as _,
+            std::ptr::null_mut(),
+        )
+    };
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let count = count as usize;
+    for (length, msg) in lengths.iter_mut().zip(&msgs[..count]) {
+        *length = msg.msg_len as usize;
+    }
+    Ok(count)
+}
+
+/// Report the datagrams dropped by the kernel on the input sockets, usually because the
+/// receive buffers were full, as the `input.udp.kernel_drops` counter
+#[cfg(target_os = "linux")]
+fn start_kernel_drops_monitor(sockets: &[UdpSocket]) {
+    let inodes: Vec<u64> = sockets.iter().filter_map(socket_inode).collect();
+    let counter = metrics::counter("input.udp.kernel_drops");
+    thread::spawn(move || {
+        let mut reported = 0;
+        loop {
+            thread::sleep(KERNEL_DROPS_INTERVAL);
+            let drops: u64 = ["/proc/net/udp", "/proc/net/udp6"]
+                .iter()
+                .filter_map(|path| std::fs::read_to_string(path).ok())
+                .map(|table| kernel_drops(&table, &inodes))
+                .sum();
+            if drops > reported {
+                counter.add(drops - reported);
+                reported = drops;
             }
         }
+    });
+}
+
+#[cfg(target_os = "linux")]
+fn socket_inode(socket: &UdpSocket) -> Option<u64> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(socket.as_raw_fd(), &mut stat) } != 0 {
+        return None;
     }
+    Some(stat.st_ino as u64)
+}
+
+/// Total of the `drops` column of a `/proc/net/udp` table, for the sockets with the
+/// given inodes
+#[cfg(target_os = "linux")]
+fn kernel_drops(table: &str, inodes: &[u64]) -> u64 {
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 13 {
+                return None;
+            }
+            let inode: u64 = fields[9].parse().ok()?;
+            if !inodes.contains(&inode) {
+                return None;
+            }
+            fields[12].parse::<u64>().ok()
+        })
+        .sum()
 }
 
 /// Handle a line that could be compressed in the Zlib or Gz format, uncompress it if compressed
@@ -122,7 +490,8 @@ fn handle_record_maybe_compressed(
     }
 }
 
-/// Decode a byte line in a valid utf-8 format, encodes it and sends it over throught a channel
+/// Decode a datagram, encodes it and sends it over throught a channel. Text formats require
+/// datagrams in a valid utf-8 format, binary formats such as Cap'n Proto get them as is.
 ///
 /// # Errors
 /// `Invalid UTF-8 input`: The record is not in a valid utf-8 format, it could be a non supported compression format
@@ -132,11 +501,7 @@ fn handle_record(
     decoder: &Box<dyn Decoder>,
     encoder: &Box<dyn Encoder>,
 ) -> Result<(), &'static str> {
-    let line = match str::from_utf8(line) {
-        Err(_) => return Err("Invalid UTF-8 input"),
-        Ok(line) => line,
-    };
-    let decoded = decoder.decode(line)?;
+    let decoded = decoder.decode_bytes(line)?;
     let reencoded = encoder.encode(decoded)?;
     tx.send(reencoded).unwrap();
     Ok(())
@@ -150,6 +515,7 @@ mod test {
     use crate::flowgger::get_encoder_rfc3164;
     use flate2::write::{GzEncoder, ZlibEncoder};
     use flate2::Compression;
+    use std::str;
     use std::sync::mpsc::{sync_channel, Receiver};
 
     const DEFAULT_QUEUE_SIZE: usize = 10_000_000;
@@ -179,6 +545,81 @@ mod test {
         assert_eq!(input.listen, default_addr);
     }
 
+    #[test]
+    fn test_udp_input_threads() {
+        let config = Config::from_string(
+            "[input]\nudp_threads = 4\nudp_batch_size = 32\nudp_rcvbuf = 8388608",
+        )
+        .unwrap();
+        let input = UdpInput::new(&config);
+        assert_eq!(input.threads, 4);
+        assert_eq!(input.batch_size, 32);
+        assert_eq!(input.rcvbuf, Some(8_388_608));
+
+        let input = UdpInput::new(&Config::from_string("").unwrap());
+        assert_eq!(input.threads, DEFAULT_THREADS);
+        assert_eq!(input.batch_size, DEFAULT_BATCH_SIZE);
+        assert_eq!(input.rcvbuf, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "input.udp_threads must be a positive integer")]
+    fn test_udp_input_no_threads() {
+        let config = Config::from_string("[input]\nudp_threads = 0").unwrap();
+        UdpInput::new(&config);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_udp_bind_sockets() {
+        let listen: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let sockets = bind_sockets(&listen, 3).unwrap();
+        assert_eq!(sockets.len(), 3);
+        let port = sockets[0].local_addr().unwrap().port();
+        assert_ne!(port, 0);
+        for socket in &sockets {
+            assert_eq!(socket.local_addr().unwrap().port(), port);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_udp_recv_batch() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        for datagram in &["first", "second", "third"] {
+            sender
+                .send_to(datagram.as_bytes(), socket.local_addr().unwrap())
+                .unwrap();
+        }
+        let mut buf = vec![0; MAX_UDP_PACKET_SIZE * 4];
+        let mut lengths = vec![0; 4];
+        let mut received = Vec::new();
+        while received.len() < 3 {
+            let count = recv_batch(&socket, &mut buf, &mut lengths).unwrap();
+            for (chunk, &length) in buf.chunks(MAX_UDP_PACKET_SIZE).zip(&lengths[..count]) {
+                received.push(str::from_utf8(&chunk[..length]).unwrap().to_owned());
+            }
+        }
+        assert_eq!(received, vec!["first", "second", "third"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_udp_kernel_drops() {
+        let table = "   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  297: 00000000:0202 00000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 41042 2 0000000000000000 12
+  297: 00000000:0202 00000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 41043 2 0000000000000000 30
+  312: 0100007F:0035 00000000:0000 07 00000000:00000000 00:00000000 00000000   101        0 20511 2 0000000000000000 7
+";
+        assert_eq!(kernel_drops(table, &[41042, 41043]), 42);
+        assert_eq!(kernel_drops(table, &[20511]), 7);
+        assert_eq!(kernel_drops(table, &[1]), 0);
+    }
+
     fn handle_record_set_up() -> (
         &'static str,
         SyncSender<Vec<u8>>,
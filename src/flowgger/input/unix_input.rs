@@ -0,0 +1,535 @@
+use super::tcp::tcp_input::get_capnp_splitter;
+use super::Input;
+use crate::flowgger::config::Config;
+use crate::flowgger::decoder::{Decoder, FrameSize};
+use crate::flowgger::encoder::Encoder;
+use crate::flowgger::record::{Record, SDValue, StructuredData};
+use crate::flowgger::splitter::{
+    get_line_splitter, AutoSplitter, MultilineConfig, NulSplitter, Splitter, SyslenSplitter,
+};
+use std::ffi::CString;
+use std::fs::{self, Permissions};
+use std::io::{self, stderr, BufReader, Write};
+use std::os::unix::fs::{chown, FileTypeExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::SyncSender;
+use std::thread;
+use toml::Value;
+
+const DEFAULT_FRAMING: &str = "line";
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 1_048_576;
+const DEFAULT_PATH: &str = "/dev/log";
+const DEFAULT_PERMISSIONS: u32 = 0o666;
+const DEFAULT_SOCKET_TYPE: &str = "dgram";
+const MAX_DATAGRAM_SIZE: usize = 65_536;
+
+#[derive(Clone)]
+pub struct UnixConfig {
+    stream: bool,
+    framing: String,
+    max_message_size: usize,
+    multiline: Option<MultilineConfig>,
+}
+
+/// Unix domain socket input, such as `/dev/log` for local `syslog(3)` clients
+pub struct UnixInput {
+    path: PathBuf,
+    permissions: u32,
+    owner: Option<u32>,
+    group: Option<u32>,
+    unix_config: UnixConfig,
+}
+
+impl UnixInput {
+    /// The socket is created at `input.unix_path` (`/dev/log` by default), with the
+    /// `input.unix_permissions` mode, and optionally owned by `input.unix_owner` and
+    /// `input.unix_group`, given as names or numeric ids. `input.unix_socket_type` is
+    /// either `dgram` (one record per datagram) or `stream`, split according to
+    /// `input.framing`.
+    pub fn new(config: &Config) -> UnixInput {
+        let path = PathBuf::from(config.lookup("input.unix_path").map_or(DEFAULT_PATH, |x| {
+            x.as_str().expect("input.unix_path must be a path")
+        }));
+        let permissions =
+            config
+                .lookup("input.unix_permissions")
+                .map_or(DEFAULT_PERMISSIONS, |x| {
+                    x.as_integer()
+                        .expect("input.unix_permissions must be an integer such as 0o666")
+                        as u32
+                });
+        let owner = config
+            .lookup("input.unix_owner")
+            .map(|x| lookup_id(x, "input.unix_owner", get_uid));
+        let group = config
+            .lookup("input.unix_group")
+            .map(|x| lookup_id(x, "input.unix_group", get_gid));
+        let stream =
+            match config
+                .lookup("input.unix_socket_type")
+                .map_or(DEFAULT_SOCKET_TYPE, |x| {
+                    x.as_str()
+                        .expect(r#"input.unix_socket_type must be "dgram" or "stream""#)
+                }) {
+                "dgram" => false,
+                "stream" => true,
+                _ => panic!(r#"input.unix_socket_type must be "dgram" or "stream""#),
+            };
+        let framing = config
+            .lookup("input.framing")
+            .map_or(DEFAULT_FRAMING, |x| {
+                x.as_str().expect(
+                    r#"input.framing must be a string set to "line", "nul", "syslen" or "auto""#,
+                )
+            })
+            .to_owned();
+        let max_message_size =
+            config
+                .lookup("input.max_message_size")
+                .map_or(DEFAULT_MAX_MESSAGE_SIZE, |x| {
+                    x.as_integer()
+                        .expect("input.max_message_size must be a size integer")
+                        as usize
+                });
+        let multiline = MultilineConfig::new(config);
+        UnixInput {
+            path,
+            permissions,
+            owner,
+            group,
+            unix_config: UnixConfig {
+                stream,
+                framing,
+                max_message_size,
+                multiline,
+            },
+        }
+    }
+
+    /// Remove the socket left by a previous instance. A socket another process, such as
+    /// journald or rsyslog, is still listening to is left untouched.
+    ///
+    /// # Panics
+    ///
+    /// - `Another process is listening to <path>`
+    fn prepare(&self) {
+        if let Ok(metadata) = fs::symlink_metadata(&self.path) {
+            if metadata.file_type().is_socket() {
+                if is_listening(&self.path) {
+                    panic!("Another process is listening to {}", self.path.display());
+                }
+                let _ = fs::remove_file(&self.path);
+            }
+        }
+    }
+
+    /// Apply the permissions and owner to the socket once it has been bound
+    fn set_permissions(&self) {
+        fs::set_permissions(&self.path, Permissions::from_mode(self.permissions))
+            .expect("Unable to set the permissions of the unix socket");
+        if self.owner.is_some() || self.group.is_some() {
+            chown(&self.path, self.owner, self.group)
+                .expect("Unable to set the owner of the unix socket");
+        }
+    }
+}
+
+/// Whether a stream or datagram socket is bound to `path`. Connecting to a socket left
+/// by a process that exited is refused.
+fn is_listening(path: &Path) -> bool {
+    UnixStream::connect(path).is_ok()
+        || UnixDatagram::unbound().is_ok_and(|socket| socket.connect(path).is_ok())
+}
+
+/// Resolve a user or group given as a numeric id or as a name
+fn lookup_id(value: &Value, key: &str, by_name: fn(&str) -> Option<u32>) -> u32 {
+    match *value {
+        Value::Integer(id) => id as u32,
+        Value::String(ref name) => name
+            .parse()
+            .ok()
+            .or_else(|| by_name(name))
+            .unwrap_or_else(|| panic!("{}: unknown name [{}]", key, name)),
+        _ => panic!("{} must be a name or a numeric id", key),
+    }
+}
+
+fn get_uid(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let passwd = unsafe { libc::getpwnam(name.as_ptr()) };
+    if passwd.is_null() {
+        return None;
+    }
+    Some(unsafe { (*passwd).pw_uid })
+}
+
+fn get_gid(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    let group = unsafe { libc::getgrnam(name.as_ptr()) };
+    if group.is_null() {
+        return None;
+    }
+    Some(unsafe { (*group).gr_gid })
+}
+
+impl Input for UnixInput {
+    fn accept(
+        &self,
+        tx: SyncSender<Vec<u8>>,
+        decoder: Box<dyn Decoder + Send>,
+        encoder: Box<dyn Encoder + Send>,
+    ) {
+        self.prepare();
+        if self.unix_config.stream {
+            let listener = UnixListener::bind(&self.path)
+                .unwrap_or_else(|_| panic!("Unable to listen to {}", self.path.display()));
+            self.set_permissions();
+            for client in listener.incoming().flatten() {
+                let tx = tx.clone();
+                let unix_config = self.unix_config.clone();
+                let (decoder, encoder) = (decoder.clone_boxed(), encoder.clone_boxed());
+                thread::spawn(move || {
+                    handle_client(client, tx, decoder, encoder, unix_config);
+                });
+            }
+        } else {
+            let socket = UnixDatagram::bind(&self.path)
+                .unwrap_or_else(|_| panic!("Unable to listen to {}", self.path.display()));
+            self.set_permissions();
+            pass_credentials(&socket);
+            let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+            loop {
+                let (length, credentials) = match recv_with_credentials(&socket, &mut buf) {
+                    Ok(res) => res,
+                    Err(e) => {
+                        let _ = writeln!(stderr(), "{}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) =
+                    handle_datagram(&buf[..length], credentials, &tx, &*decoder, &*encoder)
+                {
+                    let _ = writeln!(stderr(), "{}", e);
+                }
+            }
+        }
+    }
+}
+
+fn handle_client(
+    client: UnixStream,
+    tx: SyncSender<Vec<u8>>,
+    decoder: Box<dyn Decoder + Send>,
+    encoder: Box<dyn Encoder>,
+    unix_config: UnixConfig,
+) {
+    let decoder = match peer_credentials(&client) {
+        Some(credentials) => Box::new(CredentialsDecoder {
+            decoder,
+            credentials,
+        }) as Box<dyn Decoder>,
+        None => decoder as Box<dyn Decoder>,
+    };
+    let reader = BufReader::new(client);
+    let splitter = match &unix_config.framing as &str {
+        "capnp" => get_capnp_splitter(),
+        "line" => get_line_splitter(&unix_config.multiline),
+        "syslen" => Box::new(SyslenSplitter) as Box<dyn Splitter<_>>,
+        "nul" => Box::new(NulSplitter) as Box<dyn Splitter<_>>,
+        "auto" => Box::new(AutoSplitter::new(unix_config.max_message_size)) as Box<dyn Splitter<_>>,
+        _ => panic!("Unsupported framing scheme"),
+    };
+    splitter.run(reader, tx, decoder, encoder);
+}
+
+/// Decode a datagram, ignoring the line feed or NUL character `syslog(3)` implementations
+/// may terminate it with
+fn handle_datagram(
+    datagram: &[u8],
+    credentials: Option<Credentials>,
+    tx: &SyncSender<Vec<u8>>,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) -> Result<(), &'static str> {
+    let mut datagram = datagram;
+    while let Some((&last, rest)) = datagram.split_last() {
+        if last != b'\n' && last != 0 {
+            break;
+        }
+        datagram = rest;
+    }
+    let mut record = decoder.decode_bytes(datagram)?;
+    if let Some(credentials) = credentials {
+        credentials.add_to(&mut record);
+    }
+    let reencoded = encoder.encode(record)?;
+    tx.send(reencoded).unwrap();
+    Ok(())
+}
+
+/// Process credentials of the sender of a record
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Credentials {
+    pid: u32,
+    uid: u32,
+    gid: u32,
+}
+
+impl Credentials {
+    /// Store the credentials as `_pid`, `_uid` and `_gid` structured data pairs
+    fn add_to(&self, record: &mut Record) {
+        let sd = record.sd.get_or_insert_with(|| StructuredData::new(None));
+        sd.pairs
+            .push(("_pid".to_owned(), SDValue::U64(u64::from(self.pid))));
+        sd.pairs
+            .push(("_uid".to_owned(), SDValue::U64(u64::from(self.uid))));
+        sd.pairs
+            .push(("_gid".to_owned(), SDValue::U64(u64::from(self.gid))));
+    }
+}
+
+/// Decoder adding the credentials of the peer of a stream to every record
+#[derive(Clone)]
+struct CredentialsDecoder {
+    decoder: Box<dyn Decoder + Send>,
+    credentials: Credentials,
+}
+
+impl Decoder for CredentialsDecoder {
+    fn decode(&self, line: &str) -> Result<Record, &'static str> {
+        let mut record = self.decoder.decode(line)?;
+        self.credentials.add_to(&mut record);
+        Ok(record)
+    }
+
+    fn decode_bytes(&self, bytes: &[u8]) -> Result<Record, &'static str> {
+        let mut record = self.decoder.decode_bytes(bytes)?;
+        self.credentials.add_to(&mut record);
+        Ok(record)
+    }
+
+    fn is_complete(&self, line: &str) -> bool {
+        self.decoder.is_complete(line)
+    }
+
+    fn record_stream(&self, line: &str) -> Option<String> {
+        self.decoder.record_stream(line)
+    }
+
+    fn set_source(&mut self, path: &Path) {
+        self.decoder.set_source(path)
+    }
+
+    fn binary_framing(&self) -> Option<FrameSize> {
+        self.decoder.binary_framing()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pass_credentials(socket: &UnixDatagram) {
+    let enable: libc::c_int = 1;
+    let res = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PASSCRED,
+            &enable as *const _ as *const libc::c_void,
+            std::mem::size_of_val(&enable) as libc::socklen_t,
+        )
+    };
+    if res != 0 {
+        let _ = writeln!(
+            stderr(),
+            "Unable to receive the credentials of local clients: {}",
+            io::Error::last_os_error()
+        );
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pass_credentials(_socket: &UnixDatagram) {}
+
+/// Receive a datagram, along with the SCM_CREDENTIALS of the sender
+#[cfg(target_os = "linux")]
+fn recv_with_credentials(
+    socket: &UnixDatagram,
+    buf: &mut [u8],
+) -> io::Result<(usize, Option<Credentials>)> {
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    // u64 words keep the control buffer aligned for cmsghdr
+    let mut control = [0u64; 8];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+    let length = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut msg, 0) };
+    if length < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut credentials = None;
+    unsafe {
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_CREDENTIALS
+            {
+                let ucred = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::ucred);
+                credentials = Some(Credentials {
+                    pid: ucred.pid as u32,
+                    uid: ucred.uid,
+                    gid: ucred.gid,
+                });
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+    }
+    Ok((length as usize, credentials))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn recv_with_credentials(
+    socket: &UnixDatagram,
+    buf: &mut [u8],
+) -> io::Result<(usize, Option<Credentials>)> {
+    socket.recv(buf).map(|length| (length, None))
+}
+
+/// Credentials of the process connected to a stream socket
+#[cfg(target_os = "linux")]
+fn peer_credentials(stream: &UnixStream) -> Option<Credentials> {
+    let mut ucred: libc::ucred = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut ucred as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if res != 0 {
+        return None;
+    }
+    Some(Credentials {
+        pid: ucred.pid as u32,
+        uid: ucred.uid,
+        gid: ucred.gid,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peer_credentials(_stream: &UnixStream) -> Option<Credentials> {
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::flowgger::decoder::RFC3164Decoder;
+    use crate::flowgger::encoder::GelfEncoder;
+    use std::sync::mpsc::sync_channel;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_unix_input_config() {
+        let config = Config::from_string("").unwrap();
+        let input = UnixInput::new(&config);
+        assert_eq!(input.path, Path::new("/dev/log"));
+        assert_eq!(input.permissions, 0o666);
+        assert!(!input.unix_config.stream);
+
+        let config = Config::from_string(
+            "[input]\nunix_path = \"/run/flowgger.sock\"\nunix_socket_type = \"stream\"\n\
+             unix_permissions = 0o660\nunix_owner = 0\nunix_group = \"0\"",
+        )
+        .unwrap();
+        let input = UnixInput::new(&config);
+        assert!(input.unix_config.stream);
+        assert_eq!(input.permissions, 0o660);
+        assert_eq!((input.owner, input.group), (Some(0), Some(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "input.unix_owner: unknown name [no-such-user-flowgger]")]
+    fn test_unix_input_unknown_owner() {
+        let config =
+            Config::from_string("[input]\nunix_owner = \"no-such-user-flowgger\"").unwrap();
+        UnixInput::new(&config);
+    }
+
+    #[test]
+    fn test_unix_input_prepare() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("log.sock");
+        let config = Config::from_string(&format!(
+            "[input]\nunix_path = {:?}",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        let input = UnixInput::new(&config);
+
+        let socket = UnixDatagram::bind(&path).unwrap();
+        assert!(std::panic::catch_unwind(|| input.prepare()).is_err());
+        assert!(path.exists());
+        drop(socket);
+        let listener = UnixListener::bind(dir.path().join("stream.sock")).unwrap();
+        assert!(is_listening(&dir.path().join("stream.sock")));
+        drop(listener);
+
+        // Stale sockets are removed
+        input.prepare();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_unix_input_datagram() {
+        let config = Config::from_string("").unwrap();
+        let (tx, rx) = sync_channel(10);
+        let decoder = RFC3164Decoder::new(&config);
+        let encoder = GelfEncoder::new(&config);
+        let credentials = Credentials {
+            pid: 42,
+            uid: 1000,
+            gid: 100,
+        };
+        handle_datagram(
+            b"<13>Aug  6 11:15:24 testhostname appname[69]: test\n\0",
+            Some(credentials),
+            &tx,
+            &decoder,
+            &encoder,
+        )
+        .unwrap();
+        let encoded = String::from_utf8(rx.recv().unwrap()).unwrap();
+        assert!(encoded.contains(r#""short_message":"appname[69]: test""#));
+        assert!(encoded.contains(r#""_pid":42"#));
+        assert!(encoded.contains(r#""_uid":1000"#));
+        assert!(encoded.contains(r#""_gid":100"#));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_unix_input_credentials() {
+        let dir = TempDir::new("flowgger").unwrap();
+        let path = dir.path().join("log.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        pass_credentials(&socket);
+        let client = UnixDatagram::unbound().unwrap();
+        client.send_to(b"hello", &path).unwrap();
+        let mut buf = vec![0; MAX_DATAGRAM_SIZE];
+        let (length, credentials) = recv_with_credentials(&socket, &mut buf).unwrap();
+        assert_eq!(&buf[..length], b"hello");
+        let credentials = credentials.unwrap();
+        assert_eq!(credentials.pid, std::process::id());
+        assert_eq!(credentials.uid, unsafe { libc::getuid() });
+
+        let (a, _b) = UnixStream::pair().unwrap();
+        assert_eq!(peer_credentials(&a).unwrap().pid, std::process::id());
+    }
+}
@@ -11,12 +11,16 @@ mod utils;
 #[cfg(feature = "capnp-recompile")]
 extern crate capnp;
 extern crate chrono;
+#[cfg(feature = "rfc3164")]
+extern crate chrono_tz;
 extern crate clap;
 extern crate flate2;
 #[cfg(feature = "file")]
 extern crate glob;
 #[cfg(feature = "kafka-output")]
 extern crate kafka;
+#[cfg(any(feature = "file", feature = "syslog", feature = "unix"))]
+extern crate libc;
 #[cfg(feature = "file")]
 extern crate notify;
 #[cfg(feature = "tls")]
@@ -24,24 +28,48 @@ extern crate openssl;
 extern crate rand;
 #[cfg(feature = "redis-input")]
 extern crate redis;
+#[cfg(feature = "regex")]
+extern crate regex;
 #[cfg(feature = "gelf")]
 extern crate serde_json;
 extern crate toml;
+#[cfg(feature = "file")]
+extern crate zstd;
 
 use self::config::Config;
+#[cfg(feature = "access-log")]
+use self::decoder::AccessLogDecoder;
+#[cfg(feature = "capnp-recompile")]
+use self::decoder::CapnpDecoder;
+#[cfg(feature = "cef")]
+use self::decoder::CefDecoder;
+#[cfg(feature = "cri")]
+use self::decoder::CriDecoder;
+#[cfg(feature = "csv")]
+use self::decoder::CsvDecoder;
+#[cfg(feature = "docker")]
+use self::decoder::DockerDecoder;
 #[cfg(feature = "gelf")]
 use self::decoder::GelfDecoder;
 #[cfg(feature = "json")]
 use self::decoder::JsonDecoder;
 #[cfg(feature = "ltsv")]
 use self::decoder::LTSVDecoder;
+#[cfg(feature = "leef")]
+use self::decoder::LeefDecoder;
+#[cfg(feature = "logfmt")]
+use self::decoder::LogfmtDecoder;
 #[cfg(feature = "rfc3164")]
 use self::decoder::RFC3164Decoder;
 #[cfg(feature = "rfc5424")]
 use self::decoder::RFC5424Decoder;
-use self::decoder::{Decoder, InvalidDecoder};
+#[cfg(feature = "regex")]
+use self::decoder::RegexDecoder;
+use self::decoder::{AutoDecoder, Decoder};
 #[cfg(feature = "capnp-recompile")]
 use self::encoder::CapnpEncoder;
+#[cfg(feature = "cef")]
+use self::encoder::CefEncoder;
 use self::encoder::Encoder;
 #[cfg(feature = "gelf")]
 use self::encoder::GelfEncoder;
@@ -49,16 +77,24 @@ use self::encoder::GelfEncoder;
 use self::encoder::JsonEncoder;
 #[cfg(feature = "ltsv")]
 use self::encoder::LTSVEncoder;
+#[cfg(feature = "leef")]
+use self::encoder::LeefEncoder;
+#[cfg(feature = "logfmt")]
+use self::encoder::LogfmtEncoder;
 #[cfg(feature = "rfc3164")]
 use self::encoder::RFC3164Encoder;
 #[cfg(feature = "rfc5424")]
 use self::encoder::RFC5424Encoder;
 #[cfg(feature = "file")]
 use self::input::FileInput;
+#[cfg(feature = "http")]
+use self::input::HttpInput;
 #[cfg(feature = "redis-input")]
 use self::input::RedisInput;
 #[cfg(feature = "tls")]
 use self::input::TlsInput;
+#[cfg(feature = "unix")]
+use self::input::UnixInput;
 use self::input::{Input, StdinInput};
 #[cfg(feature = "coroutines")]
 use self::input::{TcpCoInput, TlsCoInput};
@@ -106,6 +142,16 @@ fn get_input_tcpco(_config: &Config) -> ! {
     panic!("Support for coroutines is not compiled in")
 }
 
+#[cfg(feature = "http")]
+fn get_input_http(config: &Config) -> Box<dyn Input> {
+    Box::new(HttpInput::new(config)) as Box<dyn Input>
+}
+
+#[cfg(not(feature = "http"))]
+fn get_input_http(_config: &Config) -> ! {
+    panic!("Support for http is not compiled in")
+}
+
 #[cfg(feature = "redis-input")]
 fn get_input_redis(config: &Config) -> Box<dyn Input> {
     Box::new(RedisInput::new(&config)) as Box<dyn Input>
@@ -146,6 +192,16 @@ fn get_input_udp(_config: &Config) -> ! {
     panic!("Support for syslog is not compiled in")
 }
 
+#[cfg(feature = "unix")]
+fn get_input_unix(config: &Config) -> Box<dyn Input> {
+    Box::new(UnixInput::new(config)) as Box<dyn Input>
+}
+
+#[cfg(not(feature = "unix"))]
+fn get_input_unix(_config: &Config) -> ! {
+    panic!("Support for unix sockets is not compiled in")
+}
+
 #[cfg(feature = "file")]
 fn get_input_file(config: &Config) -> Box<dyn Input> {
     Box::new(FileInput::new(&config)) as Box<dyn Input>
@@ -158,6 +214,7 @@ fn get_input_file(_config: &Config) -> ! {
 
 fn get_input(input_type: &str, config: &Config) -> Box<dyn Input> {
     match input_type {
+        "http" => get_input_http(config),
         "redis" => get_input_redis(config),
         "stdin" => Box::new(StdinInput::new(config)) as Box<dyn Input>,
         "tcp" | "syslog-tcp" => get_input_tcp(config),
@@ -165,6 +222,7 @@ fn get_input(input_type: &str, config: &Config) -> Box<dyn Input> {
         "tls" | "syslog-tls" => get_input_tls(config),
         "tls_co" | "tlsco" | "syslog-tls_co" | "syslog-tlsco" => get_input_tlsco(config),
         "udp" => get_input_udp(config),
+        "unix" => get_input_unix(config),
         "file" => get_input_file(config),
         _ => panic!("Invalid input type: {}", input_type),
     }
@@ -210,6 +268,16 @@ fn get_output(output_type: &str, config: &Config) -> Box<dyn Output> {
     }
 }
 
+#[cfg(feature = "capnp-recompile")]
+fn get_capnp_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(CapnpDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "capnp-recompile"))]
+fn get_capnp_decoder(_config: &Config) -> ! {
+    panic!("Support for CapNProto hasn't been compiled in")
+}
+
 #[cfg(feature = "capnp-recompile")]
 fn get_capnp_encoder(config: &Config) -> Box<dyn Encoder + Send> {
     Box::new(CapnpEncoder::new(config)) as Box<dyn Encoder + Send>
@@ -260,6 +328,106 @@ fn get_json_decoder(_config: &Config) -> ! {
     panic!("Support for Json hasn't been compiled in")
 }
 
+#[cfg(feature = "access-log")]
+fn get_access_log_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(AccessLogDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "access-log"))]
+fn get_access_log_decoder(_config: &Config) -> ! {
+    panic!("Support for access logs hasn't been compiled in")
+}
+
+#[cfg(feature = "cef")]
+fn get_cef_encoder(config: &Config) -> Box<dyn Encoder + Send> {
+    Box::new(CefEncoder::new(config)) as Box<dyn Encoder + Send>
+}
+
+#[cfg(not(feature = "cef"))]
+fn get_cef_encoder(_config: &Config) -> ! {
+    panic!("Support for CEF hasn't been compiled in")
+}
+
+#[cfg(feature = "cef")]
+fn get_cef_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(CefDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "cef"))]
+fn get_cef_decoder(_config: &Config) -> ! {
+    panic!("Support for CEF hasn't been compiled in")
+}
+
+#[cfg(feature = "csv")]
+fn get_csv_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(CsvDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "csv"))]
+fn get_csv_decoder(_config: &Config) -> ! {
+    panic!("Support for CSV hasn't been compiled in")
+}
+
+#[cfg(feature = "cri")]
+fn get_cri_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(CriDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "cri"))]
+fn get_cri_decoder(_config: &Config) -> ! {
+    panic!("Support for CRI hasn't been compiled in")
+}
+
+#[cfg(feature = "docker")]
+fn get_docker_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(DockerDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "docker"))]
+fn get_docker_decoder(_config: &Config) -> ! {
+    panic!("Support for Docker hasn't been compiled in")
+}
+
+#[cfg(feature = "leef")]
+fn get_leef_encoder(config: &Config) -> Box<dyn Encoder + Send> {
+    Box::new(LeefEncoder::new(config)) as Box<dyn Encoder + Send>
+}
+
+#[cfg(not(feature = "leef"))]
+fn get_leef_encoder(_config: &Config) -> ! {
+    panic!("Support for LEEF hasn't been compiled in")
+}
+
+#[cfg(feature = "leef")]
+fn get_leef_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(LeefDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "leef"))]
+fn get_leef_decoder(_config: &Config) -> ! {
+    panic!("Support for LEEF hasn't been compiled in")
+}
+
+#[cfg(feature = "logfmt")]
+fn get_logfmt_encoder(config: &Config) -> Box<dyn Encoder + Send> {
+    Box::new(LogfmtEncoder::new(config)) as Box<dyn Encoder + Send>
+}
+
+#[cfg(not(feature = "logfmt"))]
+fn get_logfmt_encoder(_config: &Config) -> ! {
+    panic!("Support for logfmt hasn't been compiled in")
+}
+
+#[cfg(feature = "logfmt")]
+fn get_logfmt_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(LogfmtDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "logfmt"))]
+fn get_logfmt_decoder(_config: &Config) -> ! {
+    panic!("Support for logfmt hasn't been compiled in")
+}
+
 #[cfg(feature = "ltsv")]
 fn get_ltvs_encoder(config: &Config) -> Box<dyn Encoder + Send> {
     Box::new(LTSVEncoder::new(config)) as Box<dyn Encoder + Send>
@@ -280,6 +448,16 @@ fn get_ltvs_decoder(_config: &Config) -> ! {
     panic!("Support for Gelf hasn't been compiled in")
 }
 
+#[cfg(feature = "regex")]
+fn get_regex_decoder(config: &Config) -> Box<dyn Decoder + Send> {
+    Box::new(RegexDecoder::new(config)) as Box<dyn Decoder + Send>
+}
+
+#[cfg(not(feature = "regex"))]
+fn get_regex_decoder(_config: &Config) -> ! {
+    panic!("Support for regex hasn't been compiled in")
+}
+
 #[cfg(feature = "rfc5424")]
 fn get_decoder_rfc5424(config: &Config) -> Box<dyn Decoder + Send> {
     Box::new(RFC5424Decoder::new(config)) as Box<dyn Decoder + Send>
@@ -339,12 +517,19 @@ pub fn start(config_file: &str) {
     });
     let input = get_input(input_type, &config);
     let decoder = match input_format {
-        _ if input_format == "capnp" => {
-            Box::new(InvalidDecoder::new(&config)) as Box<dyn Decoder + Send>
-        }
+        "access_log" => get_access_log_decoder(&config),
+        "auto" => Box::new(AutoDecoder::new(&config)) as Box<dyn Decoder + Send>,
+        "capnp" => get_capnp_decoder(&config),
+        "cef" => get_cef_decoder(&config),
+        "cri" => get_cri_decoder(&config),
+        "csv" => get_csv_decoder(&config),
+        "docker" => get_docker_decoder(&config),
         "gelf" => get_gelf_decoder(&config),
         "json" => get_json_decoder(&config),
+        "leef" => get_leef_decoder(&config),
+        "logfmt" => get_logfmt_decoder(&config),
         "ltsv" => get_ltvs_decoder(&config),
+        "regex" => get_regex_decoder(&config),
         "rfc5424" => get_decoder_rfc5424(&config),
         "rfc3164" => get_decoder_rfc3164(&config),
         _ => panic!("Unknown input format: {}", input_format),
@@ -357,8 +542,11 @@ pub fn start(config_file: &str) {
         });
     let encoder = match output_format {
         "capnp" => get_capnp_encoder(&config),
+        "cef" => get_cef_encoder(&config),
         "gelf" => get_gelf_encoder(&config),
         "json" => get_json_encoder(&config),
+        "leef" => get_leef_encoder(&config),
+        "logfmt" => get_logfmt_encoder(&config),
         "ltsv" => get_ltvs_encoder(&config),
         "rfc3164" => get_encoder_rfc3164(&config),
         "rfc5424" => get_encoder_rfc5424(&config),
@@ -374,7 +562,7 @@ pub fn start(config_file: &str) {
         Some(framing) => framing.as_str().expect("output.framing must be a string"),
         None => match (output_format, output_type) {
             ("capnp", _) | (_, "kafka") => "noop",
-            (_, "debug") | ("ltsv", _) => "line",
+            (_, "debug") | ("ltsv", _) | ("logfmt", _) | ("cef", _) | ("leef", _) => "line",
             ("gelf", _) => "nul",
             _ => DEFAULT_OUTPUT_FRAMING,
         },
@@ -396,6 +584,7 @@ pub fn start(config_file: &str) {
     let (tx, rx): (SyncSender<Vec<u8>>, Receiver<Vec<u8>>) = sync_channel(queue_size);
     let arx = Arc::new(Mutex::new(rx));
 
+    utils::metrics::start_reporter(&config);
     output.start(arx, merger);
     input.accept(tx, decoder, encoder);
 }
@@ -1,6 +1,7 @@
 use super::Output;
 use crate::flowgger::config::Config;
 use crate::flowgger::merger::Merger;
+use crate::flowgger::utils::delivery;
 use std::io::{stdout, Write};
 use std::sync::mpsc::Receiver;
 use std::sync::{Arc, Mutex};
@@ -25,12 +26,17 @@ impl Output for DebugOutput {
                 Ok(line) => line,
                 Err(_) => return,
             };
+            let receipt = delivery::receipt(&bytes);
             if let Some(ref merger) = merger {
                 merger.frame(&mut bytes);
             }
             let out = String::from_utf8_lossy(&bytes);
             print!("{}", out);
-            let _ = stdout().flush();
+            if stdout().flush().is_ok() {
+                if let Some(receipt) = receipt {
+                    receipt.delivered();
+                }
+            }
         });
     }
 }
@@ -1,6 +1,7 @@
 use super::Output;
 use crate::flowgger::config::Config;
 use crate::flowgger::merger::Merger;
+use crate::flowgger::utils::delivery;
 use crate::flowgger::utils::rotating_file::RotatingFile;
 use std::io::{BufWriter, Write};
 use std::sync::mpsc::Receiver;
@@ -176,6 +177,9 @@ impl Output for FileOutput {
     /// Start a thread listening to the specified synchronized input and writing data to a file once received.
     /// See flowgger::Output trait for arguments description
     ///
+    /// Records an input waits for an acknowledgement of, such as the ones read from Redis, are
+    /// flushed as soon as they have been written, so that 'output.file_buffer_size' doesn't
+    /// apply to them.
     fn start(&self, arx: Arc<Mutex<Receiver<Vec<u8>>>>, merger: Option<Box<dyn Merger>>) {
         let merger = match merger {
             Some(merger) => Some(merger.clone_boxed()),
@@ -199,6 +203,7 @@ impl Output for FileOutput {
                 Err(_) => return,
             };
 
+            let receipt = delivery::receipt(&bytes);
             if let Some(ref merger) = merger {
                 merger.frame(&mut bytes);
             }
@@ -206,6 +211,11 @@ impl Output for FileOutput {
             writer
                 .write_all(&bytes)
                 .expect("Cannot write bytes to output file");
+            // Records waiting for an acknowledgement bypass the buffer
+            if let Some(receipt) = receipt {
+                writer.flush().expect("Cannot write bytes to output file");
+                receipt.delivered();
+            }
         });
     }
 }
@@ -13,6 +13,7 @@ use log::{debug, error, info};
 
 use crate::flowgger::config::Config;
 use crate::flowgger::merger::Merger;
+use crate::flowgger::utils::delivery::{self, Receipt};
 
 use super::Output;
 use super::super::kafka::client::SecurityConfig;
@@ -50,6 +51,7 @@ struct KafkaWorker<'a> {
     producer: Producer,
     config: KafkaConfig,
     queue: Vec<Record<'a, (), Vec<u8>>>,
+    receipts: Vec<Receipt>,
 }
 
 impl<'a> KafkaWorker<'a> {
@@ -118,6 +120,7 @@ impl<'a> KafkaWorker<'a> {
             producer,
             config,
             queue,
+            receipts: Vec::new(),
         }
     }
 
@@ -128,11 +131,16 @@ impl<'a> KafkaWorker<'a> {
                 Err(_) => return,
             };
             debug!("sending to kafka: {}", String::from_utf8(bytes.clone()).unwrap());
+            let receipt = delivery::receipt(&bytes);
             match self
                 .producer
                 .send(&Record::from_value(&self.config.topic, bytes))
             {
-                Ok(_) => {}
+                Ok(_) => {
+                    if let Some(receipt) = receipt {
+                        receipt.delivered();
+                    }
+                }
                 Err(e) => {
                     println!("Kafka not responsive: [{}]", e);
                     exit(1);
@@ -148,6 +156,9 @@ impl<'a> KafkaWorker<'a> {
                 Err(_) => return,
             };
             debug!("sending to kafka: {}", String::from_utf8(bytes.clone()).unwrap());
+            if let Some(receipt) = delivery::receipt(&bytes) {
+                self.receipts.push(receipt);
+            }
             let message = Record {
                 key: (),
                 partition: -1,
@@ -158,7 +169,11 @@ impl<'a> KafkaWorker<'a> {
             queue.push(message);
             if queue.len() >= self.config.coalesce {
                 match self.producer.send_all(queue) {
-                    Ok(_) => {}
+                    Ok(_) => {
+                        for receipt in self.receipts.drain(..) {
+                            receipt.delivered();
+                        }
+                    }
                     Err(e) => {
                         println!("Kafka not responsive: [{}]", e);
                         exit(1);
@@ -1,5 +1,6 @@
 use crate::flowgger::config::Config;
 use crate::flowgger::merger::Merger;
+use crate::flowgger::utils::delivery;
 use chrono;
 use openssl::bn::BigNum;
 use openssl::dh::Dh;
@@ -105,6 +106,7 @@ impl TlsWorker {
                     ))
                 }
             };
+            let receipt = delivery::receipt(&bytes);
             if let Some(ref merger) = *merger {
                 merger.frame(&mut bytes);
             }
@@ -115,9 +117,13 @@ impl TlsWorker {
                     _ => return Err(e),
                 },
             };
-            if !self.tls_config.async_ {
+            // Records waiting for an acknowledgement are flushed even in async mode
+            if !self.tls_config.async_ || receipt.is_some() {
                 writer.flush()?;
             }
+            if let Some(receipt) = receipt {
+                receipt.delivered();
+            }
         }
     }
 
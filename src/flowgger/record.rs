@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SDValue {
     String(String),
     Bool(bool),
@@ -10,8 +10,13 @@ pub enum SDValue {
     Null,
 }
 
-#[cfg(feature = "ltsv")]
-#[derive(Debug, Clone)]
+#[cfg(any(
+    feature = "csv",
+    feature = "ltsv",
+    feature = "logfmt",
+    feature = "regex"
+))]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SDValueType {
     String,
     Bool,
@@ -20,6 +25,49 @@ pub enum SDValueType {
     U64,
 }
 
+#[cfg(any(
+    feature = "csv",
+    feature = "ltsv",
+    feature = "logfmt",
+    feature = "regex"
+))]
+impl SDValueType {
+    /// Parse a case-insensitive type name, as found in the `input.*_schema` settings
+    pub fn from_name(name: &str) -> Option<SDValueType> {
+        match name.to_lowercase().as_ref() {
+            "string" => Some(SDValueType::String),
+            "bool" => Some(SDValueType::Bool),
+            "f64" => Some(SDValueType::F64),
+            "i64" => Some(SDValueType::I64),
+            "u64" => Some(SDValueType::U64),
+            _ => None,
+        }
+    }
+
+    /// Convert a raw string into a value of this type
+    pub fn parse_value(&self, value: &str) -> Result<SDValue, &'static str> {
+        match *self {
+            SDValueType::String => Ok(SDValue::String(value.to_owned())),
+            SDValueType::Bool => value
+                .parse::<bool>()
+                .map(SDValue::Bool)
+                .or(Err("Type error; boolean was expected")),
+            SDValueType::F64 => value
+                .parse::<f64>()
+                .map(SDValue::F64)
+                .or(Err("Type error; f64 was expected")),
+            SDValueType::I64 => value
+                .parse::<i64>()
+                .map(SDValue::I64)
+                .or(Err("Type error; i64 was expected")),
+            SDValueType::U64 => value
+                .parse::<u64>()
+                .map(SDValue::U64)
+                .or(Err("Type error; u64 was expected")),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct StructuredData {
     pub sd_id: Option<String>,
@@ -0,0 +1,252 @@
+use super::line_splitter::handle_line;
+use super::syslen_splitter::parse_msglen;
+use super::Splitter;
+use crate::flowgger::decoder::Decoder;
+use crate::flowgger::encoder::Encoder;
+use std::io::{self, stderr, BufRead, BufReader, ErrorKind, Read, Write};
+use std::str;
+use std::sync::mpsc::SyncSender;
+
+/// Length prefixes can't have more digits than this
+const MAX_MSGLEN_DIGITS: usize = 10;
+
+/// Splitter detecting the RFC 6587 framing of each message: octet counting
+/// (`<length> <message>`), or non-transparent framing where messages are terminated by a
+/// line feed or a NUL character
+pub struct AutoSplitter {
+    max_size: usize,
+}
+
+impl AutoSplitter {
+    pub fn new(max_size: usize) -> AutoSplitter {
+        AutoSplitter { max_size }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Frame {
+    Message(Vec<u8>),
+    TooLarge(usize),
+    Eof,
+}
+
+impl<T: Read> Splitter<T> for AutoSplitter {
+    fn run(
+        &self,
+        buf_reader: BufReader<T>,
+        tx: SyncSender<Vec<u8>>,
+        decoder: Box<dyn Decoder>,
+        encoder: Box<dyn Encoder>,
+    ) {
+        let mut buf_reader = buf_reader;
+        loop {
+            let message = match read_frame(&mut buf_reader, self.max_size) {
+                Ok(Frame::Message(message)) => message,
+                Ok(Frame::TooLarge(size)) => {
+                    let _ = writeln!(stderr(), "Message too large ({} bytes), ignoring it", size);
+                    continue;
+                }
+                Ok(Frame::Eof) => return,
+                Err(e) => {
+                    match e.kind() {
+                        ErrorKind::WouldBlock | ErrorKind::TimedOut => {
+                            let _ = writeln!(
+                                stderr(),
+                                "Client hasn't sent any data for a while - Closing \
+                                 idle connection"
+                            );
+                        }
+                        _ => {
+                            let _ = writeln!(stderr(), "{}", e);
+                        }
+                    }
+                    return;
+                }
+            };
+            let message = match str::from_utf8(&message) {
+                Ok(message) => message,
+                Err(_) => {
+                    let _ = writeln!(stderr(), "Invalid UTF-8 input");
+                    continue;
+                }
+            };
+            if let Err(e) = handle_line(message, &tx, &decoder, &encoder) {
+                let _ = writeln!(stderr(), "{}: [{}]", e, message.trim());
+            }
+        }
+    }
+}
+
+/// Return the next buffered byte without consuming it, or `None` at the end of the
+/// stream
+fn peek_byte(reader: &mut dyn BufRead) -> io::Result<Option<u8>> {
+    loop {
+        match reader.fill_buf() {
+            Ok(buf) => return Ok(buf.first().cloned()),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Read the next message. Messages starting with a length followed by a space and a
+/// `<` are read using octet counting. Other messages, including messages that merely
+/// start with digits such as `404 Not Found`, end with a line feed or a NUL character.
+/// Empty lines between messages are skipped, and messages larger than `max_size` are
+/// discarded.
+fn read_frame(reader: &mut dyn BufRead, max_size: usize) -> io::Result<Frame> {
+    loop {
+        match peek_byte(reader)? {
+            None => return Ok(Frame::Eof),
+            Some(b'\n') | Some(b'\r') | Some(0) => reader.consume(1),
+            Some(_) => break,
+        }
+    }
+    let mut message = Vec::new();
+    while message.len() < MAX_MSGLEN_DIGITS {
+        match peek_byte(reader)? {
+            Some(c) if c.is_ascii_digit() => {
+                message.push(c);
+                reader.consume(1);
+            }
+            _ => break,
+        }
+    }
+    if message.is_empty() || peek_byte(reader)? != Some(b' ') {
+        return read_delimited(reader, message, max_size);
+    }
+    reader.consume(1);
+    // Octet-counted messages start with a PRI
+    if peek_byte(reader)? != Some(b'<') {
+        message.push(b' ');
+        return read_delimited(reader, message, max_size);
+    }
+    let size = parse_msglen(&message).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    if size > max_size {
+        io::copy(&mut reader.take(size as u64), &mut io::sink())?;
+        return Ok(Frame::TooLarge(size));
+    }
+    let mut message = vec![0; size];
+    reader.read_exact(&mut message)?;
+    Ok(Frame::Message(message))
+}
+
+/// Read until a line feed, a NUL character or the end of the stream, appending to
+/// `message`
+fn read_delimited(
+    reader: &mut dyn BufRead,
+    mut message: Vec<u8>,
+    max_size: usize,
+) -> io::Result<Frame> {
+    let mut size = message.len();
+    loop {
+        let (done, used) = {
+            let buf = match reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if buf.is_empty() {
+                (true, 0)
+            } else {
+                match buf.iter().position(|&c| c == b'\n' || c == 0) {
+                    Some(end) => {
+                        if size + end <= max_size {
+                            message.extend_from_slice(&buf[..end]);
+                        }
+                        size += end;
+                        (true, end + 1)
+                    }
+                    None => {
+                        if size + buf.len() <= max_size {
+                            message.extend_from_slice(buf);
+                        }
+                        size += buf.len();
+                        (false, buf.len())
+                    }
+                }
+            }
+        };
+        reader.consume(used);
+        if done {
+            break;
+        }
+    }
+    if size > max_size {
+        return Ok(Frame::TooLarge(size));
+    }
+    if message.last() == Some(&b'\r') {
+        message.pop();
+    }
+    Ok(Frame::Message(message))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn frames(input: &[u8], max_size: usize) -> Vec<Frame> {
+        let mut reader = BufReader::with_capacity(4, Cursor::new(input.to_vec()));
+        let mut frames = Vec::new();
+        loop {
+            let frame = read_frame(&mut reader, max_size).unwrap();
+            if frame == Frame::Eof {
+                return frames;
+            }
+            frames.push(frame);
+        }
+    }
+
+    fn message(message: &str) -> Frame {
+        Frame::Message(message.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_auto_framing() {
+        let input = b"9 <13>a\nb c\n<13>line\r\n\n2015-08-05 log\x00<14>nul\x0011 <13>counted";
+        assert_eq!(
+            frames(input, 1024),
+            vec![
+                message("<13>a\nb c"),
+                message("<13>line"),
+                message("2015-08-05 log"),
+                message("<14>nul"),
+                message("<13>counted"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_framing_max_size() {
+        let input = b"20 <13>this is too long<13>short\n<13>this is too long\n";
+        assert_eq!(
+            frames(input, 10),
+            vec![
+                Frame::TooLarge(20),
+                message("<13>short"),
+                Frame::TooLarge(20)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_framing_digits() {
+        let input = b"404 Not Found\n2024 was a good year\n9 <13>a\nb c42 \n";
+        assert_eq!(
+            frames(input, 1024),
+            vec![
+                message("404 Not Found"),
+                message("2024 was a good year"),
+                message("<13>a\nb c"),
+                message("42 "),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_auto_framing_truncated() {
+        let mut reader = BufReader::new(Cursor::new(b"20 <13>short".to_vec()));
+        assert!(read_frame(&mut reader, 1024).is_err());
+    }
+}
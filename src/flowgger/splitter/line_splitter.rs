@@ -41,7 +41,7 @@ impl<T: Read> Splitter<T> for LineSplitter {
     }
 }
 
-fn handle_line(
+pub(super) fn handle_line(
     line: &str,
     tx: &SyncSender<Vec<u8>>,
     decoder: &Box<dyn Decoder>,
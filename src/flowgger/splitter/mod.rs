@@ -1,18 +1,28 @@
+mod auto_splitter;
 #[cfg(feature = "capnp-recompile")]
 mod capnp_splitter;
 mod line_splitter;
+mod multiline;
+#[cfg(feature = "multiline")]
+mod multiline_splitter;
 mod nul_splitter;
 mod syslen_splitter;
 
+pub use self::auto_splitter::AutoSplitter;
 #[cfg(feature = "capnp-recompile")]
 pub use self::capnp_splitter::CapnpSplitter;
 pub use self::line_splitter::LineSplitter;
+#[cfg(feature = "file")]
+pub use self::multiline::Multiline;
+pub use self::multiline::MultilineConfig;
+#[cfg(feature = "multiline")]
+pub use self::multiline_splitter::MultilineSplitter;
 pub use self::nul_splitter::NulSplitter;
 pub use self::syslen_splitter::SyslenSplitter;
 
 use crate::flowgger::decoder::Decoder;
 use crate::flowgger::encoder::Encoder;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::sync::mpsc::SyncSender;
 
 pub trait Splitter<T> {
@@ -24,3 +34,16 @@ pub trait Splitter<T> {
         encoder: Box<dyn Encoder>,
     );
 }
+
+/// Line splitter, joining lines into multiline records when `multiline` is set
+pub fn get_line_splitter<T: Read + Send + 'static>(
+    multiline: &Option<MultilineConfig>,
+) -> Box<dyn Splitter<T>> {
+    match *multiline {
+        #[cfg(feature = "multiline")]
+        Some(ref config) => Box::new(MultilineSplitter::new(config)) as Box<dyn Splitter<_>>,
+        #[cfg(not(feature = "multiline"))]
+        Some(ref config) => match *config {},
+        None => Box::new(LineSplitter) as Box<dyn Splitter<_>>,
+    }
+}
@@ -0,0 +1,254 @@
+use crate::flowgger::config::Config;
+#[cfg(feature = "multiline")]
+use regex::Regex;
+#[cfg(feature = "multiline")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "multiline")]
+const DEFAULT_MAX_LINES: usize = 500;
+#[cfg(feature = "multiline")]
+const DEFAULT_MAX_BYTES: usize = 1_048_576;
+#[cfg(feature = "multiline")]
+const DEFAULT_FLUSH_MS: u64 = 1000;
+
+/// Rules used to join physical lines into logical records, such as stack traces
+#[cfg(feature = "multiline")]
+#[derive(Clone)]
+pub struct MultilineConfig {
+    start: Option<Regex>,
+    continuation: Option<Regex>,
+    max_lines: usize,
+    max_bytes: usize,
+    flush_timeout: Duration,
+}
+
+#[cfg(feature = "multiline")]
+impl MultilineConfig {
+    /// Multiline records are enabled by setting `input.multiline_start`, a regular
+    /// expression matching the first line of a record, and/or
+    /// `input.multiline_continuation`, a regular expression matching the lines that
+    /// continue the previous one. Records are limited to `input.multiline_max_lines`
+    /// lines and `input.multiline_max_bytes` bytes, and a pending record is sent after
+    /// `input.multiline_flush_ms` milliseconds without a new line.
+    pub fn new(config: &Config) -> Option<MultilineConfig> {
+        let lookup_regex = |key: &str| {
+            config.lookup(key).map(|x| {
+                let pattern = x
+                    .as_str()
+                    .unwrap_or_else(|| panic!("{} must be a string", key));
+                Regex::new(pattern)
+                    .unwrap_or_else(|e| panic!("{} is not a valid regular expression: {}", key, e))
+            })
+        };
+        let lookup_usize = |key: &str, default: usize| {
+            config.lookup(key).map_or(default, |x| {
+                let value = x
+                    .as_integer()
+                    .unwrap_or_else(|| panic!("{} must be a positive integer", key));
+                if value <= 0 {
+                    panic!("{} must be a positive integer", key);
+                }
+                value as usize
+            })
+        };
+        let start = lookup_regex("input.multiline_start");
+        let continuation = lookup_regex("input.multiline_continuation");
+        if start.is_none() && continuation.is_none() {
+            return None;
+        }
+        let max_lines = lookup_usize("input.multiline_max_lines", DEFAULT_MAX_LINES);
+        let max_bytes = lookup_usize("input.multiline_max_bytes", DEFAULT_MAX_BYTES);
+        let flush_ms = lookup_usize("input.multiline_flush_ms", DEFAULT_FLUSH_MS as usize);
+        Some(MultilineConfig {
+            start,
+            continuation,
+            max_lines,
+            max_bytes,
+            flush_timeout: Duration::from_millis(flush_ms as u64),
+        })
+    }
+
+    /// Whether `line` continues the current record rather than starting a new one
+    fn continues(&self, line: &str) -> bool {
+        if let Some(ref continuation) = self.continuation {
+            if continuation.is_match(line) {
+                return true;
+            }
+        }
+        match self.start {
+            Some(ref start) => !start.is_match(line),
+            None => false,
+        }
+    }
+}
+
+/// Accumulate physical lines until a line starts a new record
+#[cfg(feature = "multiline")]
+pub struct Multiline {
+    config: MultilineConfig,
+    record: String,
+    lines: usize,
+    last_line: Option<Instant>,
+}
+
+#[cfg(feature = "multiline")]
+impl Multiline {
+    pub fn new(config: &MultilineConfig) -> Multiline {
+        Multiline {
+            config: config.clone(),
+            record: String::new(),
+            lines: 0,
+            last_line: None,
+        }
+    }
+
+    /// Add a line, and return the previous record if this line starts a new one, or if
+    /// adding it would exceed the size limits
+    pub fn push(&mut self, line: &str) -> Option<String> {
+        let continues = self.lines > 0
+            && self.config.continues(line)
+            && self.lines < self.config.max_lines
+            && self.record.len() + 1 + line.len() <= self.config.max_bytes;
+        let previous = if continues {
+            self.record.push('\n');
+            None
+        } else {
+            self.flush()
+        };
+        self.record.push_str(line);
+        self.lines += 1;
+        self.last_line = Some(Instant::now());
+        previous
+    }
+
+    /// Return the pending record, if any
+    pub fn flush(&mut self) -> Option<String> {
+        if self.lines == 0 {
+            return None;
+        }
+        self.lines = 0;
+        self.last_line = None;
+        Some(std::mem::take(&mut self.record))
+    }
+
+    /// Time left before the pending record has to be flushed, or `None` if there is no
+    /// pending record
+    pub fn time_left(&self) -> Option<Duration> {
+        let elapsed = self.last_line?.elapsed();
+        Some(
+            self.config
+                .flush_timeout
+                .checked_sub(elapsed)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+/// Multiline support isn't compiled in: the configuration can never be set
+#[cfg(not(feature = "multiline"))]
+#[derive(Clone)]
+pub enum MultilineConfig {}
+
+#[cfg(not(feature = "multiline"))]
+impl MultilineConfig {
+    pub fn new(config: &Config) -> Option<MultilineConfig> {
+        if config.lookup("input.multiline_start").is_some()
+            || config.lookup("input.multiline_continuation").is_some()
+        {
+            panic!("Support for multiline records hasn't been compiled in")
+        }
+        None
+    }
+}
+
+/// Only used by the file input, the other inputs split multiline records with a
+/// `MultilineSplitter`
+#[cfg(all(feature = "file", not(feature = "multiline")))]
+pub enum Multiline {}
+
+#[cfg(all(feature = "file", not(feature = "multiline")))]
+impl Multiline {
+    pub fn new(config: &MultilineConfig) -> Multiline {
+        match *config {}
+    }
+
+    pub fn push(&mut self, _line: &str) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn flush(&mut self) -> Option<String> {
+        match *self {}
+    }
+
+    pub fn time_left(&self) -> Option<std::time::Duration> {
+        match *self {}
+    }
+}
+
+#[cfg(all(test, feature = "multiline"))]
+mod test {
+    use super::*;
+
+    fn multiline(config: &str) -> Multiline {
+        let config = Config::from_string(config).unwrap();
+        Multiline::new(&MultilineConfig::new(&config).unwrap())
+    }
+
+    #[test]
+    fn test_multiline_continuation() {
+        let mut multiline = multiline("[input]\nmultiline_continuation = '^(\\s|Caused by:)'");
+        assert_eq!(
+            multiline.push("Exception in thread \"main\" java.lang.Error"),
+            None
+        );
+        assert_eq!(multiline.push("\tat Main.main(Main.java:3)"), None);
+        assert_eq!(multiline.push("Caused by: java.io.IOException"), None);
+        assert!(multiline.time_left().is_some());
+        assert_eq!(
+            multiline.push("next record"),
+            Some(
+                "Exception in thread \"main\" java.lang.Error\n\tat Main.main(Main.java:3)\n\
+                 Caused by: java.io.IOException"
+                    .to_owned()
+            )
+        );
+        assert_eq!(multiline.flush(), Some("next record".to_owned()));
+        assert_eq!(multiline.flush(), None);
+        assert!(multiline.time_left().is_none());
+    }
+
+    #[test]
+    fn test_multiline_start() {
+        let mut multiline = multiline("[input]\nmultiline_start = '^\\d{4}-'");
+        assert_eq!(
+            multiline.push("2015-08-05 Traceback (most recent call last):"),
+            None
+        );
+        assert_eq!(multiline.push("  File \"x.py\", line 1"), None);
+        assert_eq!(multiline.push("ValueError"), None);
+        assert_eq!(
+            multiline.push("2015-08-05 ok"),
+            Some(
+                "2015-08-05 Traceback (most recent call last):\n  File \"x.py\", line 1\nValueError"
+                    .to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn test_multiline_limits() {
+        let mut multiline = multiline(
+            "[input]\nmultiline_continuation = '^ '\nmultiline_max_lines = 2\nmultiline_max_bytes = 8",
+        );
+        assert_eq!(multiline.push("a"), None);
+        assert_eq!(multiline.push(" b"), None);
+        assert_eq!(multiline.push(" c"), Some("a\n b".to_owned()));
+        assert_eq!(multiline.push(" 123456"), Some(" c".to_owned()));
+    }
+
+    #[test]
+    fn test_multiline_disabled() {
+        let config = Config::from_string("").unwrap();
+        assert!(MultilineConfig::new(&config).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use super::multiline::{Multiline, MultilineConfig};
+use super::Splitter;
+use crate::flowgger::decoder::Decoder;
+use crate::flowgger::encoder::Encoder;
+use std::io::{stderr, BufRead, BufReader, ErrorKind, Read, Write};
+use std::sync::mpsc::{sync_channel, RecvTimeoutError, SyncSender};
+use std::thread;
+
+const LINES_QUEUE_SIZE: usize = 1024;
+
+/// Line splitter joining consecutive lines into multiline records
+pub struct MultilineSplitter {
+    config: MultilineConfig,
+}
+
+impl MultilineSplitter {
+    pub fn new(config: &MultilineConfig) -> MultilineSplitter {
+        MultilineSplitter {
+            config: config.clone(),
+        }
+    }
+}
+
+impl<T: Read + Send + 'static> Splitter<T> for MultilineSplitter {
+    /// Lines are read from a dedicated thread, so that a pending record can be flushed
+    /// when no line has been received for a while
+    fn run(
+        &self,
+        buf_reader: BufReader<T>,
+        tx: SyncSender<Vec<u8>>,
+        decoder: Box<dyn Decoder>,
+        encoder: Box<dyn Encoder>,
+    ) {
+        let (line_tx, line_rx) = sync_channel(LINES_QUEUE_SIZE);
+        thread::spawn(move || {
+            for line in buf_reader.lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => match e.kind() {
+                        ErrorKind::Interrupted => continue,
+                        ErrorKind::InvalidInput | ErrorKind::InvalidData => {
+                            let _ = writeln!(stderr(), "Invalid UTF-8 input");
+                            continue;
+                        }
+                        ErrorKind::WouldBlock => {
+                            let _ = writeln!(
+                                stderr(),
+                                "Client hasn't sent any data for a while - Closing \
+                                 idle connection"
+                            );
+                            return;
+                        }
+                        _ => return,
+                    },
+                };
+                if line_tx.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let mut multiline = Multiline::new(&self.config);
+        loop {
+            let record = match multiline.time_left() {
+                None => match line_rx.recv() {
+                    Ok(line) => multiline.push(&line),
+                    Err(_) => break,
+                },
+                Some(timeout) => match line_rx.recv_timeout(timeout) {
+                    Ok(line) => multiline.push(&line),
+                    Err(RecvTimeoutError::Timeout) => multiline.flush(),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                },
+            };
+            if let Some(record) = record {
+                handle_record(&record, &tx, &*decoder, &*encoder);
+            }
+        }
+        if let Some(record) = multiline.flush() {
+            handle_record(&record, &tx, &*decoder, &*encoder);
+        }
+    }
+}
+
+fn handle_record(
+    record: &str,
+    tx: &SyncSender<Vec<u8>>,
+    decoder: &dyn Decoder,
+    encoder: &dyn Encoder,
+) {
+    let reencoded = decoder
+        .decode(record)
+        .and_then(|decoded| encoder.encode(decoded));
+    match reencoded {
+        Ok(reencoded) => tx.send(reencoded).unwrap(),
+        Err(e) => {
+            let _ = writeln!(stderr(), "{}: [{}]", e, record.trim());
+        }
+    }
+}
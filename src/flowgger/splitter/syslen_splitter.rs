@@ -45,7 +45,12 @@ fn read_msglen(reader: &mut dyn BufRead) -> Result<usize, &'static str> {
         Err(_) | Ok(0) | Ok(1) => return Err("Connection closed"),
         Ok(nbytes_vl) => nbytes_vl,
     };
-    let nbytes_s = match str::from_utf8(&nbytes_v[..nbytes_vl - 1]) {
+    parse_msglen(&nbytes_v[..nbytes_vl - 1])
+}
+
+/// Parse the length prefix of an octet-counted message, without the trailing space
+pub(super) fn parse_msglen(nbytes_v: &[u8]) -> Result<usize, &'static str> {
+    let nbytes_s = match str::from_utf8(nbytes_v) {
         Err(_) => return Err("Invalid or missing message length. Disable framing, maybe?"),
         Ok(nbytes_s) => nbytes_s,
     };
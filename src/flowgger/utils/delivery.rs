@@ -0,0 +1,78 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+type Ack = Box<dyn FnOnce() + Send>;
+
+/// Acknowledgements waiting for a record to be delivered, indexed by the hash of the
+/// encoded record
+static PENDING: Mutex<BTreeMap<u64, VecDeque<Ack>>> = Mutex::new(BTreeMap::new());
+static PENDING_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn hash(record: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    record.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Run `on_delivery` once an output has delivered `record`. This has to be called
+/// before the record is queued. Identical records are acknowledged in order.
+#[cfg(any(feature = "file", feature = "redis-input"))]
+pub fn expect<F: FnOnce() + Send + 'static>(record: &[u8], on_delivery: F) {
+    let mut pending = PENDING.lock().unwrap();
+    pending
+        .entry(hash(record))
+        .or_default()
+        .push_back(Box::new(on_delivery));
+    PENDING_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Proof that a record taken from the queue has to be acknowledged once delivered. A
+/// receipt dropped without calling `delivered()` leaves the record unacknowledged, and
+/// the input will eventually send it again.
+pub struct Receipt(Ack);
+
+impl Receipt {
+    pub fn delivered(self) {
+        (self.0)()
+    }
+}
+
+/// Called by outputs for every record taken from the queue, before it gets framed.
+/// Returns `None` for records no input is waiting for.
+pub fn receipt(record: &[u8]) -> Option<Receipt> {
+    if PENDING_COUNT.load(Ordering::SeqCst) == 0 {
+        return None;
+    }
+    let key = hash(record);
+    let mut pending = PENDING.lock().unwrap();
+    let acks = pending.get_mut(&key)?;
+    let ack = acks.pop_front()?;
+    if acks.is_empty() {
+        pending.remove(&key);
+    }
+    PENDING_COUNT.fetch_sub(1, Ordering::SeqCst);
+    Some(Receipt(ack))
+}
+
+#[cfg(any(feature = "file", feature = "redis-input"))]
+#[test]
+fn test_delivery() {
+    use std::sync::mpsc::channel;
+
+    let (tx, rx) = channel();
+    for i in 0..2 {
+        let tx = tx.clone();
+        expect(b"test_delivery record", move || tx.send(i).unwrap());
+    }
+    assert!(receipt(b"test_delivery other record").is_none());
+    let first = receipt(b"test_delivery record").unwrap();
+    let second = receipt(b"test_delivery record").unwrap();
+    assert!(receipt(b"test_delivery record").is_none());
+    second.delivered();
+    assert_eq!(rx.try_recv(), Ok(1));
+    drop(first);
+    assert!(rx.try_recv().is_err());
+}
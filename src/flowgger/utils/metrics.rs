@@ -0,0 +1,89 @@
+use crate::flowgger::config::Config;
+use log::info;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_REPORT_INTERVAL: u64 = 60;
+
+static REGISTRY: Mutex<Vec<(String, Counter)>> = Mutex::new(Vec::new());
+
+/// A named counter, shared by all the threads that requested the same name
+#[derive(Clone)]
+pub struct Counter(Arc<AtomicU64>);
+
+impl Counter {
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    #[inline]
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Return the counter registered under `name`, registering it if needed
+pub fn counter(name: &str) -> Counter {
+    let mut registry = REGISTRY.lock().unwrap();
+    if let Some((_, counter)) = registry.iter().find(|(x, _)| x == name) {
+        return counter.clone();
+    }
+    let counter = Counter(Arc::new(AtomicU64::new(0)));
+    registry.push((name.to_owned(), counter.clone()));
+    counter
+}
+
+/// Current value of every registered counter, in registration order
+pub fn snapshot() -> Vec<(String, u64)> {
+    let registry = REGISTRY.lock().unwrap();
+    registry
+        .iter()
+        .map(|(name, counter)| (name.clone(), counter.get()))
+        .collect()
+}
+
+/// Log the counters every `metrics.report_interval` seconds. A `0` interval disables
+/// reporting.
+pub fn start_reporter(config: &Config) {
+    let interval = config
+        .lookup("metrics.report_interval")
+        .map_or(DEFAULT_REPORT_INTERVAL, |x| {
+            x.as_integer()
+                .expect("metrics.report_interval must be a number of seconds") as u64
+        });
+    if interval == 0 {
+        return;
+    }
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(interval));
+        let counters = snapshot();
+        if counters.is_empty() {
+            continue;
+        }
+        let counters: Vec<String> = counters
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        info!("Counters: {}", counters.join(" "));
+    });
+}
+
+#[test]
+fn test_counter() {
+    let a = counter("test.counter");
+    let b = counter("test.counter");
+    a.inc();
+    b.add(2);
+    assert_eq!(a.get(), 3);
+    assert!(snapshot()
+        .iter()
+        .any(|(name, value)| name == "test.counter" && *value == 3));
+}
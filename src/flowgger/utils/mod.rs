@@ -1,9 +1,19 @@
+pub mod delivery;
+pub mod metrics;
 pub mod rotating_file;
 #[cfg(test)]
 pub mod test_utils;
 
 use chrono::{DateTime, FixedOffset, NaiveDateTime, Timelike};
-#[cfg(feature = "gelf")]
+#[cfg(any(
+    feature = "access-log",
+    feature = "csv",
+    feature = "gelf",
+    feature = "json",
+    feature = "logfmt",
+    feature = "rfc5424",
+    feature = "regex"
+))]
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct PreciseTimestamp {
@@ -11,7 +21,15 @@ pub struct PreciseTimestamp {
 }
 
 impl PreciseTimestamp {
-    #[cfg(feature = "gelf")]
+    #[cfg(any(
+        feature = "access-log",
+        feature = "csv",
+        feature = "gelf",
+        feature = "json",
+        feature = "logfmt",
+        feature = "rfc5424",
+        feature = "regex"
+    ))]
     #[inline]
     pub fn now() -> Self {
         let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
@@ -39,3 +57,45 @@ impl PreciseTimestamp {
         self.ts
     }
 }
+
+/// Parse a date according to a strftime format. Dates without a time zone are assumed to be
+/// UTC.
+#[cfg(any(feature = "csv", feature = "json", feature = "leef", feature = "regex"))]
+pub fn parse_ts_with_format(ts: &str, format: &str) -> Result<f64, &'static str> {
+    let res = if format.contains("%z") || format.contains("%:z") || format.contains("%#z") {
+        DateTime::parse_from_str(ts, format).map(PreciseTimestamp::from_datetime)
+    } else {
+        NaiveDateTime::parse_from_str(ts, format).map(PreciseTimestamp::from_naive_datetime)
+    };
+    res.map(|ts| ts.as_f64())
+        .or(Err("Unable to parse the date"))
+}
+
+/// Parse a syslog severity, given either as a number between 0 and 7 or as a case-insensitive
+/// level name such as `warn`, `ERROR` or `crit`
+#[cfg(any(
+    feature = "csv",
+    feature = "json",
+    feature = "logfmt",
+    feature = "regex"
+))]
+pub fn parse_severity(severity: &str) -> Result<u8, &'static str> {
+    if let Ok(severity) = severity.parse::<u8>() {
+        return if severity <= 7 {
+            Ok(severity)
+        } else {
+            Err("Severity level should be <= 7")
+        };
+    }
+    match severity.to_lowercase().as_ref() {
+        "emerg" | "emergency" | "panic" => Ok(0),
+        "alert" => Ok(1),
+        "crit" | "critical" | "fatal" => Ok(2),
+        "err" | "error" | "severe" => Ok(3),
+        "warn" | "warning" => Ok(4),
+        "notice" => Ok(5),
+        "info" | "informational" => Ok(6),
+        "debug" | "trace" => Ok(7),
+        _ => Err("Invalid severity level"),
+    }
+}
@@ -3,10 +3,15 @@ pub mod rfc_test_utils {
     use crate::flowgger::utils;
     use chrono::{Datelike, NaiveDateTime, Utc, DateTime};
 
-    /// Converts a partial date to a timestamp in ms assuming the year is the current one
+    /// Converts a partial date to a timestamp in ms, picking the year closest to now
     #[inline]
     pub fn ts_from_partial_date_time(month: u32, day: u32, hour: u32, min: u32, sec: u32) -> f64 {
-        ts_from_date_time(Utc::now().year(), month, day, hour, min, sec, 0)
+        let now = Utc::now();
+        let now_ts = now.timestamp() as f64;
+        (now.year() - 1..=now.year() + 1)
+            .map(|year| ts_from_date_time(year, month, day, hour, min, sec, 0))
+            .min_by(|a, b| (a - now_ts).abs().partial_cmp(&(b - now_ts).abs()).unwrap())
+            .unwrap()
     }
 
     /// Converts a full date to a timestamp in ms